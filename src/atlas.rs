@@ -0,0 +1,183 @@
+use bevy::{
+    asset::RenderAssetUsages,
+    image::ImageSampler,
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+};
+
+use crate::{
+    components::Shape,
+    constants::GARBAGE_COLOR,
+    resources::ColorScheme,
+    tetromino::get_tetromino_color,
+};
+
+/// Width and height in pixels of each color tile in the atlas.
+const TILE_SIZE: u32 = 4;
+
+/// Width and height in pixels of the background cell tile, and the width of the transparent
+/// border around it that separates neighbouring cells.
+const GRID_TILE_SIZE: u32 = 40;
+const GRID_TILE_BORDER: u32 = 1;
+
+/// Resource holding a texture with one solid tile per block color, so block sprites can share
+/// a single texture instead of each carrying its own color. When the atlas could not be built
+/// sprites fall back to plain colored sprites.
+/// It also holds the white tile the playfield background is tiled from.
+#[derive(Resource, Default)]
+pub struct BlockAtlas {
+    texture: Option<(Handle<Image>, Handle<TextureAtlasLayout>)>,
+    colors: Vec<Color>,
+    grid_tile: Option<Handle<Image>>,
+}
+
+impl BlockAtlas {
+    /// Returns a block sprite of the given color and size. It uses the atlas tile for the color
+    /// when there is one, and a solid-color sprite otherwise.
+    pub fn sprite(&self, color: Color, size: Vec2) -> Sprite {
+        let tile = self
+            .colors
+            .iter()
+            .position(|tile_color| *tile_color == color);
+        match (&self.texture, tile) {
+            (Some((image, layout)), Some(index)) => Sprite {
+                image: image.clone(),
+                texture_atlas: Some(TextureAtlas {
+                    layout: layout.clone(),
+                    index,
+                }),
+                custom_size: Some(size),
+                ..default()
+            },
+            _ => Sprite {
+                color,
+                custom_size: Some(size),
+                ..default()
+            },
+        }
+    }
+
+    /// Returns a single sprite of the given size drawing background cells of the given color,
+    /// each with a thin border like the blocks. The cell tile repeats every `block_size`, so one
+    /// sprite covers any number of cells. Without the tile it is one plain colored sprite.
+    pub fn grid_sprite(&self, color: Color, size: Vec2, block_size: f32) -> Sprite {
+        match &self.grid_tile {
+            Some(image) => Sprite {
+                image: image.clone(),
+                color,
+                custom_size: Some(size),
+                image_mode: SpriteImageMode::Tiled {
+                    tile_x: true,
+                    tile_y: true,
+                    stretch_value: block_size / GRID_TILE_SIZE as f32,
+                },
+                ..default()
+            },
+            None => Sprite {
+                color,
+                custom_size: Some(size),
+                ..default()
+            },
+        }
+    }
+}
+
+/// Returns every color a block can be drawn in, across all color schemes.
+fn block_colors() -> Vec<Color> {
+    let mut colors = Vec::new();
+    for color_scheme in [ColorScheme::Standard, ColorScheme::HighContrast] {
+        for shape in Shape::ALL {
+            colors.push(get_tetromino_color(shape, color_scheme));
+        }
+    }
+    colors.push(GARBAGE_COLOR);
+    colors
+}
+
+/// Paints one tile per color into a new image. The image is a float texture so the bright,
+/// bloom-driving tetromino colors are kept exactly as they are.
+fn paint_atlas(colors: &[Color]) -> Result<Image, String> {
+    let mut image = Image::new_fill(
+        Extent3d {
+            width: TILE_SIZE * colors.len() as u32,
+            height: TILE_SIZE,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[0; 8],
+        TextureFormat::Rgba16Float,
+        RenderAssetUsages::default(),
+    );
+    image.sampler = ImageSampler::nearest();
+    for (index, color) in colors.iter().enumerate() {
+        for x in 0..TILE_SIZE {
+            for y in 0..TILE_SIZE {
+                image
+                    .set_color_at(index as u32 * TILE_SIZE + x, y, *color)
+                    .map_err(|error| error.to_string())?;
+            }
+        }
+    }
+    Ok(image)
+}
+
+/// Paints the background cell tile: white, so a sprite color tints it, with a transparent border.
+fn paint_grid_tile() -> Result<Image, String> {
+    let mut image = Image::new_fill(
+        Extent3d {
+            width: GRID_TILE_SIZE,
+            height: GRID_TILE_SIZE,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[0; 4],
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::default(),
+    );
+    image.sampler = ImageSampler::nearest();
+    for x in GRID_TILE_BORDER..GRID_TILE_SIZE - GRID_TILE_BORDER {
+        for y in GRID_TILE_BORDER..GRID_TILE_SIZE - GRID_TILE_BORDER {
+            image
+                .set_color_at(x, y, Color::WHITE)
+                .map_err(|error| error.to_string())?;
+        }
+    }
+    Ok(image)
+}
+
+/// A startup system that builds the block color atlas.
+pub fn setup_block_atlas(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    mut layouts: ResMut<Assets<TextureAtlasLayout>>,
+) {
+    let colors = block_colors();
+    let texture = match paint_atlas(&colors) {
+        Ok(image) => {
+            let layout = TextureAtlasLayout::from_grid(
+                UVec2::splat(TILE_SIZE),
+                colors.len() as u32,
+                1,
+                None,
+                None,
+            );
+            Some((images.add(image), layouts.add(layout)))
+        }
+        Err(error) => {
+            warn!("Could not build the block atlas, using plain colors instead: {error}");
+            None
+        }
+    };
+    let grid_tile = match paint_grid_tile() {
+        Ok(image) => Some(images.add(image)),
+        Err(error) => {
+            warn!("Could not build the grid tile, using a plain background instead: {error}");
+            None
+        }
+    };
+    commands.insert_resource(BlockAtlas {
+        texture,
+        colors,
+        grid_tile,
+    });
+}
@@ -0,0 +1,439 @@
+use bevy::{
+    ecs::system::SystemParam,
+    input::{
+        ButtonState,
+        gamepad::{GamepadConnection, GamepadConnectionEvent},
+        keyboard::KeyboardInput,
+    },
+    prelude::*,
+};
+use std::collections::HashMap;
+
+use crate::{
+    constants::{GAMEPAD_STICK_THRESHOLD, KEY_BINDINGS_FILE},
+    game_logic::transition_pending,
+    game_state::GameState,
+};
+
+/// Something the player can do during a game, whichever key or button it is bound to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GameAction {
+    MoveLeft,
+    MoveRight,
+    SoftDrop,
+    Rotate,
+    RotateCounterclockwise,
+    Rotate180,
+    HardDrop,
+    Hold,
+    Pause,
+    Reset,
+}
+
+impl GameAction {
+    /// Every action, in the order they are listed in the controls legend.
+    pub const ALL: [GameAction; 10] = [
+        GameAction::MoveLeft,
+        GameAction::MoveRight,
+        GameAction::Rotate,
+        GameAction::RotateCounterclockwise,
+        GameAction::Rotate180,
+        GameAction::SoftDrop,
+        GameAction::HardDrop,
+        GameAction::Hold,
+        GameAction::Pause,
+        GameAction::Reset,
+    ];
+
+    /// Returns the name of the action shown in the controls legend.
+    pub fn label(self) -> &'static str {
+        match self {
+            GameAction::MoveLeft => "Move left",
+            GameAction::MoveRight => "Move right",
+            GameAction::SoftDrop => "Soft drop",
+            GameAction::Rotate => "Rotate",
+            GameAction::RotateCounterclockwise => "Rotate left",
+            GameAction::Rotate180 => "Rotate 180",
+            GameAction::HardDrop => "Hard drop",
+            GameAction::Hold => "Hold",
+            GameAction::Pause => "Pause",
+            GameAction::Reset => "Reset",
+        }
+    }
+
+    /// Returns how many quarter turns clockwise the action rotates the piece by, negative for
+    /// counterclockwise, or 0 when it does not rotate it.
+    pub fn quarter_turns(self) -> i32 {
+        match self {
+            GameAction::Rotate => 1,
+            GameAction::RotateCounterclockwise => -1,
+            GameAction::Rotate180 => 2,
+            _ => 0,
+        }
+    }
+
+    /// Returns the action with the given name in the key bindings file, such as `hard_drop`.
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "move_left" => Some(GameAction::MoveLeft),
+            "move_right" => Some(GameAction::MoveRight),
+            "soft_drop" => Some(GameAction::SoftDrop),
+            "rotate" => Some(GameAction::Rotate),
+            "rotate_counterclockwise" => Some(GameAction::RotateCounterclockwise),
+            "rotate_180" => Some(GameAction::Rotate180),
+            "hard_drop" => Some(GameAction::HardDrop),
+            "hold" => Some(GameAction::Hold),
+            "pause" => Some(GameAction::Pause),
+            "reset" => Some(GameAction::Reset),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the key with the given name in the key bindings file. Keys are named as Bevy names
+/// them, such as `KeyX`, `ArrowUp`, `Space` or `ShiftLeft`; only the keys listed here can be
+/// bound.
+fn key_from_name(name: &str) -> Option<KeyCode> {
+    use KeyCode::*;
+    let letters = [
+        KeyA, KeyB, KeyC, KeyD, KeyE, KeyF, KeyG, KeyH, KeyI, KeyJ, KeyK, KeyL, KeyM, KeyN, KeyO,
+        KeyP, KeyQ, KeyR, KeyS, KeyT, KeyU, KeyV, KeyW, KeyX, KeyY, KeyZ,
+    ];
+    let digits = [
+        Digit0, Digit1, Digit2, Digit3, Digit4, Digit5, Digit6, Digit7, Digit8, Digit9, Numpad0,
+        Numpad1, Numpad2, Numpad3, Numpad4, Numpad5, Numpad6, Numpad7, Numpad8, Numpad9,
+    ];
+    let others = [
+        ArrowLeft, ArrowRight, ArrowUp, ArrowDown, Space, Enter, Tab, Escape, Backspace, ShiftLeft,
+        ShiftRight, AltLeft, AltRight, Comma, Period, Slash, Semicolon, Quote, Backslash,
+        Backquote, Home, End, PageUp, PageDown,
+    ];
+    let control_and_brackets = [ControlLeft, ControlRight, BracketLeft, BracketRight];
+    letters
+        .into_iter()
+        .chain(digits)
+        .chain(others)
+        .chain(control_and_brackets)
+        .find(|key_code| format!("{key_code:?}") == name)
+}
+
+/// Returns the name of a key as shown to the player, such as `X` for `KeyX` or `Up` for `ArrowUp`.
+fn key_label(key_code: KeyCode) -> String {
+    let name = format!("{key_code:?}");
+    ["Key", "Digit", "Arrow"]
+        .into_iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+        .unwrap_or(&name)
+        .to_string()
+}
+
+/// Resource mapping keyboard keys to game actions.
+#[derive(Resource, Debug, Clone)]
+pub struct KeyBindings(pub HashMap<KeyCode, GameAction>);
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self(HashMap::from([
+            (KeyCode::ArrowLeft, GameAction::MoveLeft),
+            (KeyCode::ArrowRight, GameAction::MoveRight),
+            (KeyCode::ArrowDown, GameAction::SoftDrop),
+            (KeyCode::ArrowUp, GameAction::Rotate),
+            (KeyCode::KeyZ, GameAction::RotateCounterclockwise),
+            (KeyCode::KeyA, GameAction::Rotate180),
+            (KeyCode::Space, GameAction::HardDrop),
+            (KeyCode::KeyC, GameAction::Hold),
+            (KeyCode::KeyP, GameAction::Pause),
+            (KeyCode::KeyR, GameAction::Reset),
+        ]))
+    }
+}
+
+impl KeyBindings {
+    /// Returns one line per bound action listing the keys it is bound to, such as
+    /// `Space: Hard drop`. Actions without a key are left out.
+    pub fn legend(&self) -> Vec<String> {
+        GameAction::ALL
+            .into_iter()
+            .filter_map(|action| {
+                let mut keys: Vec<String> = self
+                    .0
+                    .iter()
+                    .filter(|(_, bound)| **bound == action)
+                    .map(|(key_code, _)| key_label(*key_code))
+                    .collect();
+                if keys.is_empty() {
+                    return None;
+                }
+                keys.sort();
+                Some(format!("{}: {}", keys.join(" / "), action.label()))
+            })
+            .collect()
+    }
+}
+
+/// Reads the key bindings, starting from the defaults and applying the overrides in the key
+/// bindings file, if there is one. Each `<action>=<key>` line, such as `hard_drop=KeyX`, takes
+/// the action off its default keys and binds it to the named key; an action can be bound to
+/// several keys with several lines. Lines that cannot be read are reported and skipped.
+pub fn load_key_bindings() -> KeyBindings {
+    let mut bindings = KeyBindings::default();
+    let Ok(text) = std::fs::read_to_string(KEY_BINDINGS_FILE) else {
+        return bindings;
+    };
+    let mut rebound = Vec::new();
+    for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        let parsed = line.split_once('=').and_then(|(action, key)| {
+            let action = GameAction::from_name(action.trim())?;
+            let key = key_from_name(key.trim())?;
+            Some((action, key))
+        });
+        let Some((action, key)) = parsed else {
+            warn!("Ignoring invalid key binding {line} in {KEY_BINDINGS_FILE}");
+            continue;
+        };
+        if !rebound.contains(&action) {
+            bindings.0.retain(|_, bound| *bound != action);
+            rebound.push(action);
+        }
+        bindings.0.insert(key, action);
+    }
+    bindings
+}
+
+/// Resource mapping gamepad buttons to game actions. The left stick always moves and soft
+/// drops, like the D-pad does by default.
+#[derive(Resource, Debug, Clone)]
+pub struct GamepadBindings(pub HashMap<GamepadButton, GameAction>);
+
+impl Default for GamepadBindings {
+    fn default() -> Self {
+        Self(HashMap::from([
+            (GamepadButton::DPadLeft, GameAction::MoveLeft),
+            (GamepadButton::DPadRight, GameAction::MoveRight),
+            (GamepadButton::DPadDown, GameAction::SoftDrop),
+            (GamepadButton::South, GameAction::Rotate),
+            (GamepadButton::East, GameAction::Rotate),
+            (GamepadButton::West, GameAction::RotateCounterclockwise),
+            (GamepadButton::RightTrigger, GameAction::Rotate180),
+            (GamepadButton::North, GameAction::HardDrop),
+            (GamepadButton::DPadUp, GameAction::HardDrop),
+            (GamepadButton::LeftTrigger, GameAction::Hold),
+            (GamepadButton::Start, GameAction::Pause),
+            (GamepadButton::Select, GameAction::Reset),
+        ]))
+    }
+}
+
+/// Returns which way a stick is pushed: -1, 0 or 1 on each axis, ignoring small deflections.
+fn stick_direction(stick: Vec2) -> IVec2 {
+    let axis = |value: f32| {
+        if value <= -GAMEPAD_STICK_THRESHOLD {
+            -1
+        } else if value >= GAMEPAD_STICK_THRESHOLD {
+            1
+        } else {
+            0
+        }
+    };
+    IVec2::new(axis(stick.x), axis(stick.y))
+}
+
+/// System parameter that reads the keyboard and every connected gamepad as game actions,
+/// through the configured bindings.
+#[derive(SystemParam)]
+pub struct ActionInput<'w, 's> {
+    keys: Res<'w, ButtonInput<KeyCode>>,
+    keyboard_events: EventReader<'w, 's, KeyboardInput>,
+    gamepads: Query<'w, 's, (Entity, &'static Gamepad)>,
+    key_bindings: Res<'w, KeyBindings>,
+    gamepad_bindings: Res<'w, GamepadBindings>,
+    /// Which way each gamepad's left stick was pushed when last read.
+    stick_directions: Local<'s, HashMap<Entity, IVec2>>,
+}
+
+impl ActionInput<'_, '_> {
+    /// Returns the actions started this frame: key presses in the order they happened, then
+    /// gamepad buttons, then left stick pushes. Call it once per frame, as reading consumes the
+    /// presses.
+    pub fn read_presses(&mut self) -> Vec<GameAction> {
+        let key_bindings = &self.key_bindings.0;
+        let mut presses: Vec<GameAction> = self
+            .keyboard_events
+            .read()
+            .filter(|event| event.state == ButtonState::Pressed && !event.repeat)
+            .filter_map(|event| key_bindings.get(&event.key_code).copied())
+            .collect();
+
+        // Forget the sticks of pads that have been unplugged
+        let gamepads = &self.gamepads;
+        self.stick_directions.retain(|entity, _| gamepads.contains(*entity));
+        for (entity, gamepad) in self.gamepads.iter() {
+            presses.extend(
+                gamepad
+                    .get_just_pressed()
+                    .filter_map(|button| self.gamepad_bindings.0.get(button).copied()),
+            );
+
+            // Pushing the stick over the threshold counts as a press, like a D-pad button
+            let direction = stick_direction(gamepad.left_stick());
+            let previous = self
+                .stick_directions
+                .insert(entity, direction)
+                .unwrap_or_default();
+            if direction.x != previous.x && direction.x != 0 {
+                presses.push(if direction.x < 0 {
+                    GameAction::MoveLeft
+                } else {
+                    GameAction::MoveRight
+                });
+            }
+            if direction.y < 0 && previous.y >= 0 {
+                presses.push(GameAction::SoftDrop);
+            }
+        }
+        presses
+    }
+
+    /// Returns whether any key, button or stick bound to the action is held down.
+    pub fn pressed(&self, action: GameAction) -> bool {
+        let key_held = self
+            .key_bindings
+            .0
+            .iter()
+            .any(|(key_code, bound)| *bound == action && self.keys.pressed(*key_code));
+        let gamepad_held = self.gamepads.iter().any(|(_, gamepad)| {
+            let button_held = self
+                .gamepad_bindings
+                .0
+                .iter()
+                .any(|(button, bound)| *bound == action && gamepad.pressed(*button));
+            let direction = stick_direction(gamepad.left_stick());
+            let stick_held = match action {
+                GameAction::MoveLeft => direction.x < 0,
+                GameAction::MoveRight => direction.x > 0,
+                GameAction::SoftDrop => direction.y < 0,
+                _ => false,
+            };
+            button_held || stick_held
+        });
+        key_held || gamepad_held
+    }
+}
+
+/// A system that reports gamepads as they are plugged in and unplugged. Pads can come and go at
+/// any time and are read whenever they are there. One that drops out mid-game pauses it, so the
+/// piece is not left falling on its own; the keyboard and any other pad keep working.
+pub fn handle_gamepad_connections(
+    mut connection_events: EventReader<GamepadConnectionEvent>,
+    current_state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    for event in connection_events.read() {
+        match &event.connection {
+            GamepadConnection::Connected { name, .. } => info!("Gamepad connected: {name}"),
+            GamepadConnection::Disconnected => {
+                info!("Gamepad disconnected");
+                let in_game = matches!(
+                    current_state.get(),
+                    GameState::Playing | GameState::Countdown
+                );
+                // A piece locking this frame has already picked the next state
+                if in_game && !transition_pending(&next_state) {
+                    next_state.set(GameState::Paused);
+                    info!("Game Paused");
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::input::gamepad::GamepadAxis;
+
+    /// Builds a world with the default bindings, nothing pressed and one gamepad plugged in.
+    fn world_with_gamepad() -> (World, Entity) {
+        let mut world = World::new();
+        world.init_resource::<ButtonInput<KeyCode>>();
+        world.init_resource::<Events<KeyboardInput>>();
+        world.init_resource::<KeyBindings>();
+        world.init_resource::<GamepadBindings>();
+        let gamepad = world.spawn(Gamepad::default()).id();
+        (world, gamepad)
+    }
+
+    /// Changes the gamepad's state for the next frame; buttons pressed before stay held.
+    fn update_gamepad(world: &mut World, gamepad: Entity, change: impl FnOnce(&mut Gamepad)) {
+        let mut entity = world.entity_mut(gamepad);
+        let mut gamepad = entity.get_mut::<Gamepad>().unwrap();
+        gamepad.digital_mut().clear();
+        change(&mut gamepad);
+    }
+
+    #[test]
+    fn mapped_gamepad_buttons_press_their_actions() {
+        let (mut world, gamepad) = world_with_gamepad();
+        let read_presses = world.register_system(|mut action_input: ActionInput| {
+            let presses = action_input.read_presses();
+            let held = action_input.pressed(GameAction::RotateCounterclockwise);
+            (presses, held)
+        });
+
+        update_gamepad(&mut world, gamepad, |gamepad| {
+            gamepad.digital_mut().press(GamepadButton::West);
+        });
+        let (presses, held) = world.run_system(read_presses).unwrap();
+        assert_eq!(presses, [GameAction::RotateCounterclockwise]);
+        assert!(held);
+
+        // Held on the next frame, it is no new press
+        update_gamepad(&mut world, gamepad, |_| {});
+        assert_eq!(world.run_system(read_presses).unwrap(), (Vec::new(), true));
+
+        // Rebinding the button changes what it does
+        world
+            .resource_mut::<GamepadBindings>()
+            .0
+            .insert(GamepadButton::South, GameAction::Hold);
+        update_gamepad(&mut world, gamepad, |gamepad| {
+            gamepad.digital_mut().release(GamepadButton::West);
+            gamepad.digital_mut().press(GamepadButton::South);
+        });
+        assert_eq!(
+            world.run_system(read_presses).unwrap(),
+            (vec![GameAction::Hold], false)
+        );
+    }
+
+    #[test]
+    fn pushing_the_left_stick_moves_once_until_it_is_let_go() {
+        let (mut world, gamepad) = world_with_gamepad();
+        let read_presses = world.register_system(|mut action_input: ActionInput| {
+            let presses = action_input.read_presses();
+            (presses, action_input.pressed(GameAction::MoveLeft))
+        });
+        let push_left = |value: f32| {
+            move |gamepad: &mut Gamepad| {
+                gamepad.analog_mut().set(GamepadAxis::LeftStickX, value);
+            }
+        };
+
+        update_gamepad(&mut world, gamepad, push_left(-1.0));
+        assert_eq!(
+            world.run_system(read_presses).unwrap(),
+            (vec![GameAction::MoveLeft], true)
+        );
+        update_gamepad(&mut world, gamepad, push_left(-0.9));
+        assert_eq!(world.run_system(read_presses).unwrap(), (Vec::new(), true));
+
+        // A small deflection counts as letting go, and pushing again moves again
+        update_gamepad(&mut world, gamepad, push_left(-0.1));
+        assert_eq!(world.run_system(read_presses).unwrap(), (Vec::new(), false));
+        update_gamepad(&mut world, gamepad, push_left(-1.0));
+        assert_eq!(
+            world.run_system(read_presses).unwrap(),
+            (vec![GameAction::MoveLeft], true)
+        );
+    }
+}
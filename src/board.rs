@@ -0,0 +1,748 @@
+use bevy::prelude::*;
+use std::fmt;
+
+use crate::{
+    atlas::BlockAtlas,
+    components::{GridPosition, Shape, Tetromino},
+    constants::{GARBAGE_COLOR, GRID_BUFFER_ROWS, GRID_SIZE_X, GRID_SIZE_Y},
+    resources::{BoardMetrics, ColorScheme, HoldPiece},
+    tetromino::get_tetromino_color,
+};
+
+/// The contents of a single occupied cell on the board.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cell {
+    /// A block left behind by a locked tetromino.
+    Piece(Shape),
+    /// A block that rose up as garbage.
+    Garbage,
+}
+
+impl Cell {
+    /// Returns the cell for a static block, which is garbage when it carries no shape.
+    pub fn from_shape(shape: Option<&Shape>) -> Self {
+        match shape {
+            Some(shape) => Cell::Piece(*shape),
+            None => Cell::Garbage,
+        }
+    }
+
+    /// Returns the color used to draw the cell in the given color scheme.
+    pub fn color(self, color_scheme: ColorScheme) -> Color {
+        match self {
+            Cell::Piece(shape) => get_tetromino_color(shape, color_scheme),
+            Cell::Garbage => GARBAGE_COLOR,
+        }
+    }
+
+    /// Returns the character used for the cell in the board string notation.
+    pub fn to_char(self) -> char {
+        match self {
+            Cell::Piece(Shape::I) => 'I',
+            Cell::Piece(Shape::O) => 'O',
+            Cell::Piece(Shape::T) => 'T',
+            Cell::Piece(Shape::L) => 'L',
+            Cell::Piece(Shape::J) => 'J',
+            Cell::Piece(Shape::S) => 'S',
+            Cell::Piece(Shape::Z) => 'Z',
+            Cell::Garbage => 'G',
+        }
+    }
+
+    /// Parses a cell from the board string notation.
+    pub fn from_char(ch: char) -> Option<Self> {
+        match ch {
+            'I' => Some(Cell::Piece(Shape::I)),
+            'O' => Some(Cell::Piece(Shape::O)),
+            'T' => Some(Cell::Piece(Shape::T)),
+            'L' => Some(Cell::Piece(Shape::L)),
+            'J' => Some(Cell::Piece(Shape::J)),
+            'S' => Some(Cell::Piece(Shape::S)),
+            'Z' => Some(Cell::Piece(Shape::Z)),
+            'G' => Some(Cell::Garbage),
+            _ => None,
+        }
+    }
+}
+
+/// A plain-data snapshot of the settled blocks on the board.
+/// Row 0 is the bottom of the playfield, matching `GridPosition`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Board {
+    cells: [[Option<Cell>; GRID_SIZE_X as usize]; GRID_SIZE_Y as usize],
+}
+
+impl Default for Board {
+    fn default() -> Self {
+        Self {
+            cells: [[None; GRID_SIZE_X as usize]; GRID_SIZE_Y as usize],
+        }
+    }
+}
+
+impl Board {
+    /// Builds a board from the positions and contents of static blocks.
+    /// Blocks outside the playfield are ignored.
+    pub fn from_blocks(blocks: impl IntoIterator<Item = (GridPosition, Cell)>) -> Self {
+        let mut board = Board::default();
+        for (position, cell) in blocks {
+            board.set(position, Some(cell));
+        }
+        board
+    }
+
+    /// Sets the contents of a cell. Positions off the board are ignored.
+    pub fn set(&mut self, position: GridPosition, cell: Option<Cell>) {
+        if Self::in_bounds(position) {
+            self.cells[position.y as usize][position.x as usize] = cell;
+        }
+    }
+
+    /// Iterates over every occupied cell on the board.
+    pub fn occupied(&self) -> impl Iterator<Item = (GridPosition, Cell)> + '_ {
+        self.cells.iter().enumerate().flat_map(|(y, row)| {
+            row.iter().enumerate().filter_map(move |(x, cell)| {
+                cell.map(|cell| {
+                    (
+                        GridPosition {
+                            x: x as i32,
+                            y: y as i32,
+                        },
+                        cell,
+                    )
+                })
+            })
+        })
+    }
+
+    /// Returns the lowest row with no empty cell, if there is one. Such a row would sit on the
+    /// board until the first lock cleared it for free, so loaded boards may not have one.
+    pub fn full_row(&self) -> Option<i32> {
+        self.cells
+            .iter()
+            .position(|row| row.iter().all(Option::is_some))
+            .map(|y| y as i32)
+    }
+
+    fn in_bounds(position: GridPosition) -> bool {
+        (0..GRID_SIZE_X).contains(&position.x) && (0..GRID_SIZE_Y).contains(&position.y)
+    }
+}
+
+/// Resource mirroring which entity holds each cell of the board, the buffer rows above it
+/// included, so a collision check looks its cell up directly instead of scanning every settled
+/// block. `sync_collision_grid` keeps it up to date as pieces lock and rows clear.
+#[derive(Resource, Debug, Clone, PartialEq, Eq)]
+pub struct CollisionGrid {
+    cells: Vec<Option<Entity>>,
+    block_count: usize,
+}
+
+impl Default for CollisionGrid {
+    fn default() -> Self {
+        Self {
+            cells: vec![None; (GRID_SIZE_X * CollisionGrid::HEIGHT) as usize],
+            block_count: 0,
+        }
+    }
+}
+
+impl CollisionGrid {
+    /// The number of rows covered: the playfield and the buffer rows above it.
+    const HEIGHT: i32 = GRID_SIZE_Y + GRID_BUFFER_ROWS;
+
+    /// Builds the grid from the entities and positions of the settled blocks. Blocks outside
+    /// the grid are ignored.
+    pub fn from_blocks(blocks: impl IntoIterator<Item = (Entity, GridPosition)>) -> Self {
+        let mut grid = CollisionGrid::default();
+        for (entity, position) in blocks {
+            if let Some(index) = Self::index(position) {
+                grid.cells[index] = Some(entity);
+            }
+            grid.block_count += 1;
+        }
+        grid
+    }
+
+    /// Returns the block occupying a cell, if any. Cells off the grid are always empty.
+    pub fn get(&self, position: GridPosition) -> Option<Entity> {
+        Self::index(position).and_then(|index| self.cells[index])
+    }
+
+    /// Returns true when a settled block occupies the cell.
+    pub fn is_occupied(&self, position: GridPosition) -> bool {
+        self.get(position).is_some()
+    }
+
+    fn index(position: GridPosition) -> Option<usize> {
+        ((0..GRID_SIZE_X).contains(&position.x) && (0..Self::HEIGHT).contains(&position.y))
+            .then(|| (position.y * GRID_SIZE_X + position.x) as usize)
+    }
+}
+
+/// A system that rebuilds the `CollisionGrid` whenever the settled blocks change: a piece
+/// locked, rows were cleared or shifted, garbage rose or a board was loaded. On the frames in
+/// between nothing has moved and it leaves the grid alone.
+pub fn sync_collision_grid(
+    mut collision_grid: ResMut<CollisionGrid>,
+    changed_query: Query<(), (Changed<GridPosition>, Without<Tetromino>)>,
+    block_query: Query<(Entity, &GridPosition), Without<Tetromino>>,
+) {
+    // Blocks that were only removed leave nothing changed behind, but the count drops
+    if changed_query.is_empty() && block_query.iter().len() == collision_grid.block_count {
+        return;
+    }
+    *collision_grid = CollisionGrid::from_blocks(
+        block_query
+            .iter()
+            .map(|(entity, position)| (entity, *position)),
+    );
+}
+
+/// Returns how many cells of the board hold a block.
+pub fn occupied_cell_count(board: &Board) -> usize {
+    board.occupied().count()
+}
+
+/// Returns true when no cell of the board holds a block, as after a perfect clear.
+pub fn board_is_empty(board: &Board) -> bool {
+    occupied_cell_count(board) == 0
+}
+
+/// Returns true when none of the settled blocks is left, neither on the board nor in the buffer
+/// rows above it. A `Board` only covers the playfield, so blocks left in the buffer rows are
+/// checked on their own.
+pub fn stack_is_empty(blocks: impl IntoIterator<Item = (GridPosition, Cell)>) -> bool {
+    let blocks: Vec<(GridPosition, Cell)> = blocks.into_iter().collect();
+    board_is_empty(&Board::from_blocks(blocks.iter().copied()))
+        && blocks.iter().all(|(position, _)| position.y < GRID_SIZE_Y)
+}
+
+/// The reasons a board string can fail to parse.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BoardParseError {
+    /// The string has more rows than the board is tall.
+    TooManyRows(usize),
+    /// A row is not exactly as wide as the board.
+    /// Rows and columns are zero-based and counted from the top-left of the string.
+    WrongRowWidth { row: usize, width: usize },
+    /// A character that is neither '.' nor a known cell letter.
+    UnknownCell { row: usize, column: usize, ch: char },
+    /// A row with no empty cell, which the first lock would clear for free.
+    FullRow(usize),
+}
+
+impl fmt::Display for BoardParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BoardParseError::TooManyRows(rows) => {
+                write!(f, "board has {rows} rows but at most {GRID_SIZE_Y} are allowed")
+            }
+            BoardParseError::WrongRowWidth { row, width } => {
+                let row = row + 1;
+                write!(f, "row {row} is {width} cells wide but must be {GRID_SIZE_X}")
+            }
+            BoardParseError::UnknownCell { row, column, ch } => {
+                let (row, column) = (row + 1, column + 1);
+                write!(f, "unknown cell '{ch}' at row {row}, column {column}")
+            }
+            BoardParseError::FullRow(row) => {
+                let row = row + 1;
+                write!(f, "row {row} is full; every row needs at least one empty cell")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BoardParseError {}
+
+/// Writes the board as text, one line per row from the top down and one character per cell:
+/// '.' for empty, the shape letter for piece blocks and 'G' for garbage.
+pub fn board_to_string(board: &Board) -> String {
+    board
+        .cells
+        .iter()
+        .rev()
+        .map(|row| {
+            row.iter()
+                .map(|cell| cell.map_or('.', Cell::to_char))
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Parses a board written by `board_to_string`. Blank lines around the board are ignored,
+/// and a string with fewer rows than the board fills it from the bottom up.
+/// Full rows are rejected, as they would sit on the board until the first lock cleared them.
+pub fn board_from_string(text: &str) -> Result<Board, BoardParseError> {
+    let rows: Vec<&str> = text.trim().lines().map(str::trim).collect();
+    if rows.len() > GRID_SIZE_Y as usize {
+        return Err(BoardParseError::TooManyRows(rows.len()));
+    }
+
+    let mut board = Board::default();
+    for (row, line) in rows.iter().enumerate() {
+        let width = line.chars().count();
+        if width != GRID_SIZE_X as usize {
+            return Err(BoardParseError::WrongRowWidth { row, width });
+        }
+        let y = (rows.len() - 1 - row) as i32;
+        for (column, ch) in line.chars().enumerate() {
+            let cell = match ch {
+                '.' => None,
+                _ => Some(Cell::from_char(ch).ok_or(BoardParseError::UnknownCell {
+                    row,
+                    column,
+                    ch,
+                })?),
+            };
+            board.set(
+                GridPosition {
+                    x: column as i32,
+                    y,
+                },
+                cell,
+            );
+        }
+    }
+    if let Some(y) = board.full_row() {
+        return Err(BoardParseError::FullRow(rows.len() - 1 - y as usize));
+    }
+    Ok(board)
+}
+
+/// Number of cells in the occupancy bitset, one bit per cell.
+const OCCUPANCY_BITS: usize = (GRID_SIZE_X * GRID_SIZE_Y) as usize;
+/// Number of bytes used for the occupancy bitset, with the last byte padded when the cells
+/// don't fill it.
+const OCCUPANCY_BYTES: usize = OCCUPANCY_BITS.div_ceil(8);
+/// Marker byte for "no active piece" in place of a shape letter.
+const NO_PIECE: u8 = 0;
+
+/// The falling piece, described by its shape and the board positions of its four blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ActivePiece {
+    pub shape: Shape,
+    pub blocks: [GridPosition; 4],
+}
+
+/// The minimal state needed to mirror a board: which cells are occupied, plus the active piece
+/// and the hold box. Unlike the board string notation it drops what is in each cell, so it fits
+/// in a small fixed number of bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompactBoard(pub [u8; CompactBoard::SIZE]);
+
+/// The reasons compact board bytes can fail to decode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompactBoardError {
+    /// The active or held piece byte is neither empty nor a shape letter.
+    UnknownShape(u8),
+    /// A block of the active piece lies outside the board and its buffer rows.
+    PieceOutOfBounds(GridPosition),
+    /// A row with no empty cell, counted from the bottom of the board.
+    FullRow(i32),
+}
+
+impl fmt::Display for CompactBoardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompactBoardError::UnknownShape(byte) => write!(f, "unknown piece shape byte {byte}"),
+            CompactBoardError::PieceOutOfBounds(GridPosition { x, y }) => {
+                write!(f, "active piece block at ({x}, {y}) is off the board")
+            }
+            CompactBoardError::FullRow(y) => {
+                write!(
+                    f,
+                    "row {y} from the bottom is full; every row needs an empty cell"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for CompactBoardError {}
+
+/// Reads a piece byte of a compact board: empty or a shape letter.
+fn decode_shape(byte: u8) -> Result<Option<Shape>, CompactBoardError> {
+    if byte == NO_PIECE {
+        return Ok(None);
+    }
+    match Cell::from_char(byte as char) {
+        Some(Cell::Piece(shape)) => Ok(Some(shape)),
+        _ => Err(CompactBoardError::UnknownShape(byte)),
+    }
+}
+
+impl CompactBoard {
+    /// Size in bytes: the occupancy bitset, then the active piece as its shape letter followed
+    /// by an x and y byte per block, then the held piece's shape letter and whether the active
+    /// piece can still be held.
+    pub const SIZE: usize = OCCUPANCY_BYTES + 1 + 4 * 2 + 2;
+
+    /// Where the held piece's shape letter is stored; the can-hold flag follows it.
+    const HELD_SHAPE_BYTE: usize = OCCUPANCY_BYTES + 1 + 4 * 2;
+
+    /// Packs the occupied cells of the board, row by row from the bottom, the active piece and
+    /// the hold box. A hold still waiting for the next piece is stored as already held.
+    pub fn encode(board: &Board, piece: Option<&ActivePiece>, hold_piece: &HoldPiece) -> Self {
+        let mut bytes = [0; Self::SIZE];
+        for (position, _) in board.occupied() {
+            let bit = (position.y * GRID_SIZE_X + position.x) as usize;
+            bytes[bit / 8] |= 1 << (bit % 8);
+        }
+        if let Some(piece) = piece {
+            bytes[OCCUPANCY_BYTES] = Cell::Piece(piece.shape).to_char() as u8;
+            for (i, block) in piece.blocks.iter().enumerate() {
+                bytes[OCCUPANCY_BYTES + 1 + 2 * i] = block.x as u8;
+                bytes[OCCUPANCY_BYTES + 2 + 2 * i] = block.y as u8;
+            }
+        }
+        if let Some(shape) = hold_piece.shown() {
+            bytes[Self::HELD_SHAPE_BYTE] = Cell::Piece(shape).to_char() as u8;
+        }
+        bytes[Self::HELD_SHAPE_BYTE + 1] = hold_piece.can_hold as u8;
+        CompactBoard(bytes)
+    }
+
+    /// Unpacks the board, active piece and hold box. Cell contents are not stored, so every
+    /// occupied cell comes back as garbage. Full rows are rejected like in board strings, and so
+    /// is an active piece with a block off the board or above its buffer rows, as it could never
+    /// have been in play.
+    pub fn decode(&self) -> Result<(Board, Option<ActivePiece>, HoldPiece), CompactBoardError> {
+        let bytes = &self.0;
+        let mut board = Board::default();
+        for bit in 0..OCCUPANCY_BITS {
+            if bytes[bit / 8] & (1 << (bit % 8)) != 0 {
+                let position = GridPosition {
+                    x: bit as i32 % GRID_SIZE_X,
+                    y: bit as i32 / GRID_SIZE_X,
+                };
+                board.set(position, Some(Cell::Garbage));
+            }
+        }
+
+        if let Some(y) = board.full_row() {
+            return Err(CompactBoardError::FullRow(y));
+        }
+
+        let hold_piece = HoldPiece {
+            shape: decode_shape(bytes[Self::HELD_SHAPE_BYTE])?,
+            pending: None,
+            can_hold: bytes[Self::HELD_SHAPE_BYTE + 1] != 0,
+        };
+
+        let Some(shape) = decode_shape(bytes[OCCUPANCY_BYTES])? else {
+            return Ok((board, None, hold_piece));
+        };
+        let blocks = std::array::from_fn(|i| GridPosition {
+            x: bytes[OCCUPANCY_BYTES + 1 + 2 * i] as i32,
+            y: bytes[OCCUPANCY_BYTES + 2 + 2 * i] as i32,
+        });
+        if let Some(block) = blocks.iter().find(|block| {
+            !(0..GRID_SIZE_X).contains(&block.x)
+                || !(0..GRID_SIZE_Y + GRID_BUFFER_ROWS).contains(&block.y)
+        }) {
+            return Err(CompactBoardError::PieceOutOfBounds(*block));
+        }
+        Ok((board, Some(ActivePiece { shape, blocks }), hold_piece))
+    }
+
+    /// Writes the bytes as lowercase hex, two characters per byte.
+    pub fn to_hex(self) -> String {
+        self.0.iter().map(|byte| format!("{byte:02x}")).collect()
+    }
+
+    /// Reads bytes written by `to_hex`. Returns `None` unless the text is exactly the right
+    /// number of hex digits.
+    pub fn from_hex(text: &str) -> Option<Self> {
+        if text.len() != Self::SIZE * 2 || !text.is_ascii() {
+            return None;
+        }
+        let mut bytes = [0; Self::SIZE];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&text[2 * i..2 * i + 2], 16).ok()?;
+        }
+        Some(CompactBoard(bytes))
+    }
+}
+
+/// Spawns a static block entity for every occupied cell of the board.
+pub fn spawn_board_blocks(
+    commands: &mut Commands,
+    metrics: &BoardMetrics,
+    block_atlas: &BlockAtlas,
+    color_scheme: ColorScheme,
+    board: &Board,
+) {
+    spawn_blocks(
+        commands,
+        metrics,
+        block_atlas,
+        color_scheme,
+        board.occupied(),
+    );
+}
+
+/// Spawns a static block entity for each position and cell, wherever it lies.
+pub fn spawn_blocks(
+    commands: &mut Commands,
+    metrics: &BoardMetrics,
+    block_atlas: &BlockAtlas,
+    color_scheme: ColorScheme,
+    blocks: impl IntoIterator<Item = (GridPosition, Cell)>,
+) {
+    for (position, cell) in blocks {
+        let world = metrics.grid_to_world(position);
+        let mut entity_commands = commands.spawn((
+            block_atlas.sprite(cell.color(color_scheme), Vec2::splat(metrics.block_size)),
+            Transform::from_xyz(world.x, world.y, 1.0),
+            position,
+        ));
+        if let Cell::Piece(shape) = cell {
+            entity_commands.insert(shape);
+        }
+    }
+}
+
+/// A system that prints the settled blocks as a board string when F2 is pressed,
+/// so a position can be shared and loaded again with `--board`. The compact form,
+/// which also carries the falling piece and the hold box, is printed below it.
+pub fn export_board(
+    input: Res<ButtonInput<KeyCode>>,
+    block_query: Query<(&GridPosition, Option<&Shape>), Without<Tetromino>>,
+    tetromino_query: Query<(&GridPosition, &Shape), With<Tetromino>>,
+    hold_piece: Res<HoldPiece>,
+) {
+    if input.just_pressed(KeyCode::F2) {
+        let board = Board::from_blocks(
+            block_query
+                .iter()
+                .map(|(position, shape)| (*position, Cell::from_shape(shape))),
+        );
+        let blocks: Vec<(&GridPosition, &Shape)> = tetromino_query.iter().collect();
+        let piece = match blocks[..] {
+            [(a, shape), (b, _), (c, _), (d, _)] => Some(ActivePiece {
+                shape: *shape,
+                blocks: [*a, *b, *c, *d],
+            }),
+            _ => None,
+        };
+        let compact = CompactBoard::encode(&board, piece.as_ref(), &hold_piece);
+        println!(
+            "Current board:\n{}\nCompact: {}",
+            board_to_string(&board),
+            compact.to_hex()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compact_board_round_trips_the_piece_and_hold_box() {
+        let board = Board::from_blocks(
+            [(0, 0), (1, 0), (9, 0), (4, 3)].map(|(x, y)| (GridPosition { x, y }, Cell::Garbage)),
+        );
+        let piece = ActivePiece {
+            shape: Shape::T,
+            blocks: [(4, 18), (5, 18), (6, 18), (5, 19)].map(|(x, y)| GridPosition { x, y }),
+        };
+        let hold_piece = HoldPiece {
+            shape: Some(Shape::I),
+            pending: None,
+            can_hold: false,
+        };
+
+        let compact = CompactBoard::encode(&board, Some(&piece), &hold_piece);
+        let reread = CompactBoard::from_hex(&compact.to_hex()).unwrap();
+        assert_eq!(reread, compact);
+        assert_eq!(reread.decode(), Ok((board, Some(piece), hold_piece)));
+    }
+
+    #[test]
+    fn compact_board_round_trips_an_empty_hold_box() {
+        let board = Board::default();
+        let hold_piece = HoldPiece::default();
+
+        let compact = CompactBoard::encode(&board, None, &hold_piece);
+        assert_eq!(compact.decode(), Ok((board, None, hold_piece)));
+    }
+
+    #[test]
+    fn compact_board_stores_a_pending_hold_as_held() {
+        let hold_piece = HoldPiece {
+            shape: Some(Shape::O),
+            pending: Some(Shape::S),
+            can_hold: false,
+        };
+
+        let compact = CompactBoard::encode(&Board::default(), None, &hold_piece);
+        let (_, _, decoded) = compact.decode().unwrap();
+        assert_eq!(decoded.shape, Some(Shape::S));
+        assert_eq!(decoded.pending, None);
+        assert!(!decoded.can_hold);
+    }
+
+    #[test]
+    fn board_strings_round_trip() {
+        let tall_stack = vec![".........G"; GRID_SIZE_Y as usize].join("\n");
+        for text in [
+            "",
+            "....I.....\n....I.....\n....I.....\n....I.....",
+            ".T........\nTTT.OO.SSG\nLLLJOOSS.G\nL..JJJZZ.G",
+            &tall_stack,
+        ] {
+            let board = board_from_string(text).unwrap();
+            assert_eq!(board_from_string(&board_to_string(&board)), Ok(board));
+        }
+    }
+
+    #[test]
+    fn short_board_strings_fill_from_the_bottom() {
+        let board = board_from_string("\n  ....I.....\nIIII......  \n\n").unwrap();
+        let cells: Vec<(i32, i32)> = board
+            .occupied()
+            .map(|(position, _)| (position.x, position.y))
+            .collect();
+        assert_eq!(cells, [(0, 0), (1, 0), (2, 0), (3, 0), (4, 1)]);
+    }
+
+    #[test]
+    fn malformed_board_strings_are_rejected() {
+        let too_tall = vec![".........."; GRID_SIZE_Y as usize + 1].join("\n");
+        assert_eq!(
+            board_from_string(&too_tall),
+            Err(BoardParseError::TooManyRows(GRID_SIZE_Y as usize + 1))
+        );
+        assert_eq!(
+            board_from_string("..........\n....."),
+            Err(BoardParseError::WrongRowWidth { row: 1, width: 5 })
+        );
+        assert_eq!(
+            board_from_string("....X....."),
+            Err(BoardParseError::UnknownCell {
+                row: 0,
+                column: 4,
+                ch: 'X'
+            })
+        );
+    }
+
+    #[test]
+    fn compact_board_reconstructs_every_cell_and_block() {
+        // A checkerboard over the whole field sets bits in every byte of the bitset
+        let board = Board::from_blocks((0..GRID_SIZE_Y).flat_map(|y| {
+            (0..GRID_SIZE_X)
+                .filter(move |x| (x + y) % 2 == 0)
+                .map(move |x| (GridPosition { x, y }, Cell::Garbage))
+        }));
+        let piece = ActivePiece {
+            shape: Shape::I,
+            blocks: [(3, 21), (4, 21), (5, 21), (6, 21)].map(|(x, y)| GridPosition { x, y }),
+        };
+
+        let compact = CompactBoard::encode(&board, Some(&piece), &HoldPiece::default());
+        let (decoded, decoded_piece, _) = compact.decode().unwrap();
+        assert_eq!(
+            occupied_cell_count(&decoded),
+            (GRID_SIZE_X * GRID_SIZE_Y / 2) as usize
+        );
+        assert_eq!(decoded, board);
+        assert_eq!(decoded_piece, Some(piece));
+    }
+
+    #[test]
+    fn full_rows_are_rejected() {
+        assert_eq!(
+            board_from_string("..........\nGGGGGGGGGG"),
+            Err(BoardParseError::FullRow(1))
+        );
+        assert_eq!(
+            board_from_string("IIIIOOTTTT\nG.GGGGGGGG"),
+            Err(BoardParseError::FullRow(0))
+        );
+        assert!(board_from_string("GGGGGGGGG.\nG.GGGGGGGG").is_ok());
+    }
+
+    #[test]
+    fn occupied_cells_are_counted_on_any_board() {
+        let empty = Board::default();
+        assert!(board_is_empty(&empty));
+        assert_eq!(occupied_cell_count(&empty), 0);
+
+        let partial = board_from_string("....T.....\nG..TTT...G").unwrap();
+        assert!(!board_is_empty(&partial));
+        assert_eq!(occupied_cell_count(&partial), 6);
+
+        let full =
+            Board::from_blocks((0..GRID_SIZE_Y).flat_map(|y| {
+                (0..GRID_SIZE_X).map(move |x| (GridPosition { x, y }, Cell::Garbage))
+            }));
+        assert!(!board_is_empty(&full));
+        assert_eq!(
+            occupied_cell_count(&full),
+            (GRID_SIZE_X * GRID_SIZE_Y) as usize
+        );
+    }
+
+    #[test]
+    fn compact_board_rejects_a_piece_off_the_board() {
+        let hold_piece = HoldPiece::default();
+        let top = GRID_SIZE_Y + GRID_BUFFER_ROWS - 1;
+        let in_bounds = ActivePiece {
+            shape: Shape::O,
+            blocks: [(8, top - 1), (9, top - 1), (8, top), (9, top)]
+                .map(|(x, y)| GridPosition { x, y }),
+        };
+        let compact = CompactBoard::encode(&Board::default(), Some(&in_bounds), &hold_piece);
+        assert!(compact.decode().is_ok());
+
+        for (i, block) in [
+            GridPosition {
+                x: GRID_SIZE_X,
+                y: 0,
+            },
+            GridPosition { x: 0, y: top + 1 },
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let mut piece = in_bounds;
+            piece.blocks[i] = block;
+            let compact = CompactBoard::encode(&Board::default(), Some(&piece), &hold_piece);
+            assert_eq!(
+                compact.decode(),
+                Err(CompactBoardError::PieceOutOfBounds(block))
+            );
+        }
+    }
+
+    #[test]
+    fn blocks_in_the_buffer_rows_keep_the_stack_from_being_empty() {
+        assert!(stack_is_empty([]));
+        let above = GridPosition {
+            x: 4,
+            y: GRID_SIZE_Y,
+        };
+        assert!(board_is_empty(&Board::from_blocks([(
+            above,
+            Cell::Garbage
+        )])));
+        assert!(!stack_is_empty([(above, Cell::Garbage)]));
+    }
+
+    #[test]
+    fn compact_boards_with_a_full_row_are_rejected() {
+        let board =
+            Board::from_blocks((0..GRID_SIZE_X).map(|x| (GridPosition { x, y: 1 }, Cell::Garbage)));
+        let compact = CompactBoard::encode(&board, None, &HoldPiece::default());
+        let hex = compact.to_hex();
+        assert_eq!(
+            CompactBoard::from_hex(&hex).unwrap().decode(),
+            Err(CompactBoardError::FullRow(1))
+        );
+    }
+}
@@ -1,5 +1,7 @@
 use bevy::prelude::*;
 
+use crate::constants::HOLD_SWAP_SECONDS;
+
 /// Represents the position of a block on the game grid.
 /// This is different from the world transform.
 #[derive(Component, Debug, PartialEq, Eq, Clone, Copy)]
@@ -8,6 +10,18 @@ pub struct GridPosition {
     pub y: i32,
 }
 
+/// Marker for the background of the playfield, a single sprite drawing every empty cell.
+#[derive(Component)]
+pub struct GridBackground;
+
+/// Marker for the translucent blocks showing where the falling piece will land.
+#[derive(Component)]
+pub struct GhostBlock;
+
+/// Marker for the sprite brightening the background rows the falling piece will land in.
+#[derive(Component)]
+pub struct LandingRow;
+
 /// Represents the different shapes a tetromino can have.
 #[derive(Component, Debug, PartialEq, Eq, Clone, Copy)]
 pub enum Shape {
@@ -20,14 +34,107 @@ pub enum Shape {
     Z,
 }
 
+impl Shape {
+    /// Every tetromino shape, in a fixed order.
+    pub const ALL: [Shape; 7] = [
+        Shape::I,
+        Shape::O,
+        Shape::T,
+        Shape::L,
+        Shape::J,
+        Shape::S,
+        Shape::Z,
+    ];
+}
+
 /// A "marker" component to identify the active tetromino.
 /// Its presence on an entity signals that it is part of the currently falling piece.
 #[derive(Component)]
 pub struct Tetromino;
 
-/// A marker component to define the rotation center of a tetromino.
+/// Which way a piece faces, by quarter turns clockwise from how it spawned.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum RotationState {
+    #[default]
+    Spawn,
+    Right,
+    Flipped,
+    Left,
+}
+
+impl RotationState {
+    /// Returns the state a quarter turn clockwise from this one.
+    pub fn clockwise(self) -> Self {
+        match self {
+            RotationState::Spawn => RotationState::Right,
+            RotationState::Right => RotationState::Flipped,
+            RotationState::Flipped => RotationState::Left,
+            RotationState::Left => RotationState::Spawn,
+        }
+    }
+
+    /// Returns how many quarter turns clockwise this state is from the spawn state.
+    pub fn quarter_turns(self) -> i32 {
+        match self {
+            RotationState::Spawn => 0,
+            RotationState::Right => 1,
+            RotationState::Flipped => 2,
+            RotationState::Left => 3,
+        }
+    }
+
+    /// Returns the state the given number of quarter turns from this one, clockwise when
+    /// positive and counterclockwise when negative.
+    pub fn turned(self, quarter_turns: i32) -> Self {
+        (0..quarter_turns.rem_euclid(4)).fold(self, |state, _| state.clockwise())
+    }
+}
+
+/// A component to define the rotation center of a tetromino. It also keeps track of which way
+/// the piece faces, to pick the wall kicks of its next turn.
 #[derive(Component, Debug, PartialEq, Eq, Clone, Copy)]
-pub struct RotationCenter(pub GridPosition);
+pub struct RotationCenter {
+    pub shape: Shape,
+    pub state: RotationState,
+    /// Whether the last thing that moved the piece was a turn, rather than a move or a drop.
+    pub rotated_last: bool,
+}
+
+/// A component to mark the entity playing the background music.
+#[derive(Component)]
+pub struct BackgroundMusic;
+
+/// A component for the blocks of a cleared row while they flash and fade away. They are no longer
+/// part of the board, and are despawned once the timer runs out.
+#[derive(Component)]
+pub struct ClearingRow(pub Timer);
+
+/// A component to mark the blocks of the piece that locked last, until the checks that run
+/// before the next piece spawns have looked at them.
+#[derive(Component)]
+pub struct JustLocked;
+
+/// A component for the blocks of a piece going into or coming out of the hold box. The swap
+/// itself has already happened; only the sprite is still animating.
+#[derive(Component)]
+pub struct HoldSwap {
+    pub timer: Timer,
+    /// True for a block of the piece shrinking into the hold box, which is no longer part of the
+    /// game and is despawned at the end. False for a block of the piece growing back out.
+    pub into_hold: bool,
+    /// Where a block shrinking into the hold box started from, taken on its first frame.
+    pub start: Option<Vec3>,
+}
+
+impl HoldSwap {
+    pub fn new(into_hold: bool) -> Self {
+        Self {
+            timer: Timer::from_seconds(HOLD_SWAP_SECONDS, TimerMode::Once),
+            into_hold,
+            start: None,
+        }
+    }
+}
 
 /// A component to mark the entities that display the score and lines.
 #[derive(Component)]
@@ -35,12 +142,21 @@ pub enum Scoreboard {
     Score,
     Lines,
     Level,
+    Multiplier,
+    Efficiency,
+    Pieces,
+    Combo,
+    BackToBack,
 }
 
 /// A component to identify all entities on the title screen
 #[derive(Component)]
 pub struct TitleScreen;
 
+/// Marker for the countdown shown before a game starts.
+#[derive(Component)]
+pub struct CountdownText;
+
 /// A component to identify all entities on the pause screen
 #[derive(Component)]
 pub struct PauseMenu;
@@ -49,6 +165,107 @@ pub struct PauseMenu;
 #[derive(Component)]
 pub struct GameOverOverlay;
 
+/// A component to identify victory overlay entities
+#[derive(Component)]
+pub struct VictoryOverlay;
+
+/// A confetti particle thrown up when a mode is completed. It falls under gravity, spinning,
+/// and despawns itself once its lifetime is over.
+#[derive(Component)]
+pub struct Confetti {
+    pub velocity: Vec2,
+    pub spin: f32,
+    pub lifetime: Timer,
+}
+
+/// The "NEW BEST" banner flashed when a run beats a personal best, removed when its timer ends.
+#[derive(Component)]
+pub struct BestBanner(pub Timer);
+
+/// The brightening laid over the whole grid after a line clear, fading out over its timer.
+/// `strength` is its starting opacity, higher the more rows were cleared at once.
+#[derive(Component)]
+pub struct GridPulse {
+    pub timer: Timer,
+    pub strength: f32,
+}
+
 /// Marker for blocks that are part of the next piece preview
 #[derive(Component)]
-pub struct PreviewBlock;
\ No newline at end of file
+pub struct PreviewBlock;
+
+/// Marker for blocks that show the held piece
+#[derive(Component)]
+pub struct HoldBlock;
+
+/// A component to mark the static parts of the next piece preview and the hold box below it.
+#[derive(Component)]
+pub enum PreviewFrame {
+    Box,
+    Label,
+    HoldBox,
+    HoldLabel,
+}
+
+/// Marker for the controls legend panel that can be toggled during play
+#[derive(Component)]
+pub struct ControlsLegend;
+
+/// Marker for the text of the controls legend, rebuilt whenever the key bindings change
+#[derive(Component)]
+pub struct ControlsLegendText;
+
+/// Marker for the blocks of the read-only spectator board
+#[derive(Component)]
+pub struct SpectatorBlock;
+
+/// Marker for the full-screen overlay that fades out when the game changes screens
+#[derive(Component)]
+pub struct TransitionOverlay;
+
+/// Marker for the backdrop image drawn behind the playfield for the whole session
+#[derive(Component)]
+pub struct Backdrop;
+
+/// Marker for the camera that only clears the letterbox bars around the game camera
+#[derive(Component)]
+pub struct LetterboxBars;
+
+/// Marker for the debug panel showing the game log
+#[derive(Component)]
+pub struct DebugPanel;
+
+/// A component to mark the title screen texts showing the selectable settings.
+#[derive(Component)]
+pub enum TitleSetting {
+    GameMode,
+    RuleProfile,
+    Randomizer,
+    HudSide,
+    ColorScheme,
+    Seed,
+    /// Shares its row with `Puzzle`; only one of the two applies to a mode.
+    StartingLevel,
+    Puzzle,
+    Bests,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn all_lists_every_shape_once() {
+        assert_eq!(Shape::ALL.len(), 7);
+        for shape in Shape::ALL {
+            // Adding a shape breaks this match until it is added to ALL as well
+            match shape {
+                Shape::I | Shape::O | Shape::T | Shape::L | Shape::J | Shape::S | Shape::Z => {}
+            }
+            assert_eq!(
+                Shape::ALL.iter().filter(|other| **other == shape).count(),
+                1
+            );
+        }
+    }
+}
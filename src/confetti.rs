@@ -0,0 +1,67 @@
+use bevy::prelude::*;
+use rand::{Rng, seq::IndexedRandom};
+
+use crate::{
+    atlas::BlockAtlas,
+    components::{Confetti, GridPosition, Shape},
+    constants::{CONFETTI_COUNT, CONFETTI_GRAVITY, CONFETTI_LIFETIME, GRID_SIZE_X, GRID_SIZE_Y},
+    resources::{BoardMetrics, ColorScheme},
+    tetromino::get_tetromino_color,
+};
+
+/// A system that throws a burst of confetti up from the top of the playfield, in the colors of
+/// the pieces.
+pub fn spawn_confetti(
+    mut commands: Commands,
+    metrics: Res<BoardMetrics>,
+    color_scheme: Res<ColorScheme>,
+    block_atlas: Res<BlockAtlas>,
+) {
+    let mut rng = rand::rng();
+    let origin = metrics.grid_to_world(GridPosition {
+        x: GRID_SIZE_X / 2,
+        y: GRID_SIZE_Y,
+    });
+    let size = Vec2::splat(metrics.block_size * 0.3);
+
+    for _ in 0..CONFETTI_COUNT {
+        let shape = *Shape::ALL.choose(&mut rng).unwrap();
+        let velocity = Vec2::new(
+            rng.random_range(-250.0..250.0),
+            rng.random_range(150.0..450.0),
+        );
+        commands.spawn((
+            block_atlas.sprite(get_tetromino_color(shape, *color_scheme), size),
+            Transform::from_xyz(origin.x, origin.y, 10.0),
+            Confetti {
+                velocity,
+                spin: rng.random_range(-8.0..8.0),
+                lifetime: Timer::from_seconds(
+                    rng.random_range(CONFETTI_LIFETIME * 0.6..CONFETTI_LIFETIME),
+                    TimerMode::Once,
+                ),
+            },
+        ));
+    }
+}
+
+/// A system that moves every confetti particle, pulling it down and spinning it, and despawns
+/// it when its lifetime runs out.
+pub fn update_confetti(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut Transform, &mut Confetti)>,
+) {
+    let delta = time.delta_secs();
+    for (entity, mut transform, mut confetti) in query.iter_mut() {
+        confetti.lifetime.tick(time.delta());
+        if confetti.lifetime.finished() {
+            // The title screen may have cleared it away along with the board already
+            commands.entity(entity).try_despawn();
+            continue;
+        }
+        confetti.velocity.y += CONFETTI_GRAVITY * delta;
+        transform.translation += confetti.velocity.extend(0.0) * delta;
+        transform.rotate_z(confetti.spin * delta);
+    }
+}
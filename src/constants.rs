@@ -5,7 +5,141 @@ pub const GRID_SIZE_X: i32 = 10;
 pub const GRID_SIZE_Y: i32 = 20;
 pub const BLOCK_SIZE: f32 = 25.0;
 
+/// Hidden rows above the visible field where pieces spawn. Blocks in them are never drawn, and
+/// nothing can move above them.
+pub const GRID_BUFFER_ROWS: i32 = 2;
+
+/// The shortest time a piece can take to fall one row, one frame at 60 frames per second.
+/// Gravity moves a piece at most one row per frame, so falling any faster changes nothing.
+pub const MIN_FALL_SECONDS: f32 = 1.0 / 60.0;
+
+/// Frames a piece takes to fall one row at 60 frames per second, for each level from 1 up, on the
+/// guideline gravity curve rounded to whole frames. Levels past the last one keep its speed.
+pub const GRAVITY_FRAMES: [u32; 20] = [
+    60, 48, 37, 28, 21, 16, 11, 8, 6, 4, 3, 2, 1, 1, 1, 1, 1, 1, 1, 1,
+];
+pub const MAX_GRAVITY_LEVEL: u32 = GRAVITY_FRAMES.len() as u32;
+
+/// The highest level a game can be started at from the title screen.
+pub const MAX_STARTING_LEVEL: u32 = 20;
+
+/// The render layers of the game sprites and of the UI cameras. Sprites without `RenderLayers`
+/// are on layer 0, so only the game camera ever draws them.
+pub const GAME_RENDER_LAYER: usize = 0;
+pub const UI_RENDER_LAYER: usize = 1;
+
+/// The virtual resolution the game is drawn at when letterboxed
+pub const VIRTUAL_WIDTH: f32 = 1280.0;
+pub const VIRTUAL_HEIGHT: f32 = 720.0;
+
 /// Constants for the Scoreboard UI
 pub const SCOREBOARD_FONT_SIZE: f32 = 25.0;
 pub const SCOREBOARD_TEXT_PADDING: Val = Val::Px(50.0);
-pub const SCOREBOARD_LINE_TEXT_PADDING: Val = Val::Px(50.0 + SCOREBOARD_FONT_SIZE);
\ No newline at end of file
+pub const SCOREBOARD_LINE_TEXT_PADDING: Val = Val::Px(50.0 + SCOREBOARD_FONT_SIZE);
+
+/// The digits the classic score display pads the score, lines and level to
+pub const CLASSIC_SCORE_DIGITS: usize = 6;
+pub const CLASSIC_LINES_DIGITS: usize = 3;
+pub const CLASSIC_LEVEL_DIGITS: usize = 2;
+
+/// The backdrop image is darkened to this tint so the playfield and HUD stay readable over it
+pub const BACKDROP_TINT: Color = Color::srgb(0.35, 0.35, 0.35);
+
+/// The background of the next piece preview and the hold box
+pub const PREVIEW_BOX_COLOR: Color = Color::srgba(0.1, 0.1, 0.1, 0.9);
+/// How much the hold box and the piece in it are faded while the falling piece cannot be held
+pub const HOLD_UNAVAILABLE_ALPHA: f32 = 0.35;
+
+/// The size in pixels of each cell of the final board thumbnail on the game over screen
+pub const THUMBNAIL_CELL_SIZE: f32 = 10.0;
+
+/// How long a piece can rest on the stack before it locks, in seconds, and how many times
+/// moving or rotating it can restart that time before it reaches a new lowest row
+pub const LOCK_DELAY_SECONDS: f32 = 0.5;
+pub const LOCK_DELAY_MAX_RESETS: u32 = 15;
+
+/// Points awarded for each cell a piece is soft dropped or hard dropped, as in the guideline
+pub const SOFT_DROP_POINTS_PER_CELL: u32 = 1;
+pub const HARD_DROP_POINTS_PER_CELL: u32 = 2;
+
+/// How many upcoming pieces the preview shows, the next piece included. The pieces after the
+/// next one are drawn at this fraction of its size, this many of its blocks apart.
+pub const NEXT_QUEUE_SIZE: usize = 5;
+pub const QUEUE_PREVIEW_SCALE: f32 = 0.5;
+pub const QUEUE_PREVIEW_SPACING: f32 = 1.5;
+
+/// Points awarded for each step of a combo, times the combo count and the level
+pub const COMBO_POINTS: u32 = 50;
+
+/// Points awarded for a T-spin that clears one, two or three lines, times the level
+pub const T_SPIN_POINTS: [u32; 3] = [400, 800, 1200];
+
+/// How long cleared rows take to flash and fade away, in seconds. The next piece waits for it,
+/// on top of any entry delay, and the rows above only drop once it is over.
+pub const LINE_CLEAR_SECONDS: f32 = 0.3;
+
+/// How long a held piece takes to shrink into the hold box, and the piece coming out of it to
+/// grow to full size, in seconds
+pub const HOLD_SWAP_SECONDS: f32 = 0.15;
+
+/// What the countdown before each game shows, one word per beat, and how long each beat lasts
+/// in seconds.
+pub const COUNTDOWN_STEPS: [&str; 4] = ["3", "2", "1", "GO"];
+pub const COUNTDOWN_STEP_SECONDS: f32 = 0.5;
+
+/// How much + and - change the volume by, and the file the volume is kept in between sessions
+pub const VOLUME_STEP: f32 = 0.1;
+pub const SETTINGS_FILE: &str = "settings.txt";
+
+/// The optional file of key binding overrides read at startup, one `<action>=<key>` per line.
+pub const KEY_BINDINGS_FILE: &str = "keys.txt";
+
+/// How opaque the ghost piece is drawn, compared to the falling piece
+pub const GHOST_ALPHA: f32 = 0.3;
+
+/// How many presses made while the next piece is on its way are kept for it by default
+pub const INPUT_BUFFER_SIZE: usize = 2;
+
+/// How long the fade between screens lasts, in seconds
+pub const SCREEN_TRANSITION_SECONDS: f32 = 0.25;
+
+/// How often the APM and PPS stats are worked out again, in seconds of play
+pub const EFFICIENCY_UPDATE_SECONDS: f32 = 0.5;
+
+/// A pointer press counts as a tap when it is released within this many seconds, having moved
+/// no more than this many screen pixels
+pub const POINTER_TAP_SECONDS: f32 = 0.25;
+pub const POINTER_TAP_DISTANCE: f32 = 10.0;
+/// A pointer press counts as a swipe down when it travels at least this many screen pixels down
+/// within this many seconds
+pub const POINTER_SWIPE_DISTANCE: f32 = 80.0;
+pub const POINTER_SWIPE_SECONDS: f32 = 0.4;
+
+/// How far the gamepad's left stick must be pushed, from 0 to 1, to count as a direction
+pub const GAMEPAD_STICK_THRESHOLD: f32 = 0.5;
+
+/// Constants for the confetti burst on the victory screen
+pub const CONFETTI_COUNT: usize = 120;
+pub const CONFETTI_LIFETIME: f32 = 2.5;
+pub const CONFETTI_GRAVITY: f32 = -600.0;
+
+/// How long the "NEW BEST" banner stays on screen, in seconds
+pub const BEST_BANNER_SECONDS: f32 = 2.0;
+
+/// How long the grid pulse after a line clear takes to fade, in seconds, and how bright it
+/// starts for each row cleared
+pub const GRID_PULSE_SECONDS: f32 = 0.35;
+pub const GRID_PULSE_STRENGTH_PER_ROW: f32 = 0.06;
+
+/// How many game events the debug panel keeps
+pub const DEBUG_LOG_CAPACITY: usize = 12;
+
+/// How many pieces back a casual mode rewind can go
+pub const REWIND_HISTORY_PIECES: usize = 4;
+/// How many times a casual game can be rewound
+pub const REWINDS_PER_GAME: u32 = 3;
+
+/// Constants for the survival mode garbage timer
+pub const SURVIVAL_GARBAGE_INTERVAL: f32 = 10.0;
+pub const SURVIVAL_MIN_GARBAGE_INTERVAL: f32 = 2.0;
+pub const GARBAGE_COLOR: Color = Color::srgb(0.5, 0.5, 0.5);
@@ -0,0 +1,163 @@
+use bevy::prelude::*;
+use std::collections::VecDeque;
+
+use crate::{
+    components::{DebugPanel, Shape, Tetromino},
+    constants::{DEBUG_LOG_CAPACITY, SCOREBOARD_TEXT_PADDING},
+    game_state::GameState,
+    resources::{GravityEnabled, Level, LinesCleared},
+};
+
+/// Resource that turns the on-screen debug tools on and off. Toggled with F3, or enabled from
+/// the start with `--debug`.
+#[derive(Resource, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DebugMode(pub bool);
+
+/// Resource holding the most recent game events, newest last, each stamped with the time in
+/// seconds since startup. Only the last `DEBUG_LOG_CAPACITY` entries are kept.
+#[derive(Resource, Default)]
+pub struct GameLog(pub VecDeque<(f32, String)>);
+
+impl GameLog {
+    /// Records an event, dropping the oldest one when the log is full.
+    pub fn push(&mut self, time: f32, event: impl Into<String>) {
+        if self.0.len() == DEBUG_LOG_CAPACITY {
+            self.0.pop_front();
+        }
+        self.0.push_back((time, event.into()));
+    }
+}
+
+/// A system that records game flow into the `GameLog`: state transitions, spawned pieces,
+/// line clears and level ups. It records even while the panel is hidden, so turning debug
+/// mode on shows what just happened.
+#[allow(clippy::too_many_arguments)]
+pub fn record_game_log(
+    time: Res<Time>,
+    mut log: ResMut<GameLog>,
+    mut transitions: EventReader<StateTransitionEvent<GameState>>,
+    tetromino_query: Query<&Shape, With<Tetromino>>,
+    lines_cleared: Res<LinesCleared>,
+    level: Res<Level>,
+    mut last_lines_cleared: Local<u32>,
+    mut last_level: Local<Option<u32>>,
+) {
+    let now = time.elapsed_secs();
+
+    for transition in transitions.read() {
+        let event = match (transition.exited, transition.entered) {
+            (Some(GameState::Title), Some(GameState::Countdown)) => "Game started".to_string(),
+            (Some(GameState::Countdown), Some(GameState::Spawning)) => "Go".to_string(),
+            (Some(GameState::Playing), Some(GameState::Spawning)) => "Piece locked".to_string(),
+            (Some(GameState::Spawning), Some(GameState::Playing)) => {
+                match tetromino_query.iter().next() {
+                    Some(shape) => format!("Spawned {shape:?}"),
+                    None => "Spawned piece".to_string(),
+                }
+            }
+            (Some(GameState::Playing | GameState::Countdown), Some(GameState::Paused)) => {
+                "Paused".to_string()
+            }
+            (Some(GameState::Paused), Some(GameState::Playing)) => "Resumed".to_string(),
+            (_, Some(GameState::GameOver)) => "Game over".to_string(),
+            (_, Some(GameState::Victory)) => "Victory".to_string(),
+            (Some(GameState::GameOver), Some(GameState::Spawning)) => "Rewound".to_string(),
+            (_, Some(GameState::Title)) => "Back to title".to_string(),
+            _ => continue,
+        };
+        log.push(now, event);
+    }
+
+    // Line counts only ever grow during a game; a reset puts them back to zero
+    if lines_cleared.0 > *last_lines_cleared {
+        let lines = lines_cleared.0 - *last_lines_cleared;
+        log.push(now, format!("Cleared {lines} line(s)"));
+    }
+    *last_lines_cleared = lines_cleared.0;
+
+    if last_level.is_some_and(|last_level| level.0 > last_level) {
+        log.push(now, format!("Level up to {}", level.0));
+    }
+    *last_level = Some(level.0);
+}
+
+/// A system that spawns the debug panel in the top-right corner, hidden until debug mode is on.
+pub fn setup_debug_panel(mut commands: Commands) {
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                top: SCOREBOARD_TEXT_PADDING,
+                right: SCOREBOARD_TEXT_PADDING,
+                padding: UiRect::all(Val::Px(10.0)),
+                ..default()
+            },
+            BackgroundColor(bevy::prelude::Color::srgba(0.0, 0.0, 0.0, 0.6)),
+            Visibility::Hidden,
+            DebugPanel,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new(""),
+                TextFont {
+                    font_size: 14.0,
+                    ..default()
+                },
+                TextColor(bevy::prelude::Color::WHITE),
+            ));
+        });
+}
+
+/// A system that toggles debug mode when F3 is pressed.
+pub fn toggle_debug_mode(input: Res<ButtonInput<KeyCode>>, mut debug_mode: ResMut<DebugMode>) {
+    if input.just_pressed(KeyCode::F3) {
+        debug_mode.0 = !debug_mode.0;
+        info!("Debug mode: {}", if debug_mode.0 { "on" } else { "off" });
+    }
+}
+
+/// A system that freezes or resumes gravity when F4 is pressed in debug mode.
+pub fn toggle_gravity(
+    input: Res<ButtonInput<KeyCode>>,
+    debug_mode: Res<DebugMode>,
+    mut gravity_enabled: ResMut<GravityEnabled>,
+) {
+    if debug_mode.0 && input.just_pressed(KeyCode::F4) {
+        gravity_enabled.0 = !gravity_enabled.0;
+        info!(
+            "Gravity: {}",
+            if gravity_enabled.0 { "on" } else { "frozen" }
+        );
+    }
+}
+
+/// A system that shows the debug panel in debug mode and fills it with the game log.
+pub fn update_debug_panel(
+    debug_mode: Res<DebugMode>,
+    log: Res<GameLog>,
+    mut panel_query: Query<(&mut Visibility, &Children), With<DebugPanel>>,
+    mut text_query: Query<&mut Text>,
+) {
+    if !debug_mode.is_changed() && !log.is_changed() {
+        return;
+    }
+
+    let contents = log
+        .0
+        .iter()
+        .map(|(time, event)| format!("[{time:7.2}] {event}"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    for (mut visibility, children) in panel_query.iter_mut() {
+        *visibility = if debug_mode.0 {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+        for child in children.iter() {
+            if let Ok(mut text) = text_query.get_mut(child) {
+                *text = Text::new(contents.clone());
+            }
+        }
+    }
+}
@@ -1,140 +1,1506 @@
 use bevy::prelude::*;
-use std::collections::HashMap;
+use rand::Rng;
+use std::{
+    collections::{BTreeMap, HashSet},
+    time::Duration,
+};
 
 use crate::{
-    components::{GridPosition, Tetromino},
-    constants::{GRID_SIZE_X, GRID_SIZE_Y},
+    atlas::BlockAtlas,
+    board::{Cell, CollisionGrid, stack_is_empty},
+    components::{
+        ClearingRow, GhostBlock, GridBackground, GridPosition, JustLocked, LandingRow,
+        RotationCenter, Shape, Tetromino,
+    },
+    constants::{
+        COMBO_POINTS, EFFICIENCY_UPDATE_SECONDS, GARBAGE_COLOR, GHOST_ALPHA, GRID_BUFFER_ROWS,
+        GRID_SIZE_X, GRID_SIZE_Y, LINE_CLEAR_SECONDS, SURVIVAL_GARBAGE_INTERVAL,
+        SURVIVAL_MIN_GARBAGE_INTERVAL, T_SPIN_POINTS,
+    },
     game_state::GameState,
-    resources::{FallTimer, Level, LinesCleared, Score},
+    randomizer::GameRng,
+    resources::{
+        AllClearStreak, BackToBack, BoardMetrics, CollapseDirection, ColorScheme, Combo, Efficiency,
+        EntryDelaySeconds, EntryDelayTimer, FallTimer, GameMode, GameOverReason, GarbageTimer,
+        GravityEnabled, HoldPiece, LastLock, Level, LifetimeStats, LinesCleared, LockDelay,
+        PlayCounters, PlayTime, RuleProfile, Score, ScoreMultiplier, StartCountdown, TimedLevels,
+    },
+    setup::{grid_cell_color, landing_row_color},
+    sound::{SoundEffect, play_sound},
+    tetromino::get_tetromino_color,
 };
 
-/// Checks for collisions with the game board boundaries or other pieces.
-pub fn check_collision(new_pos: GridPosition, static_blocks: &[GridPosition]) -> bool {
-    // Check for collisions with the floor or walls
-    if new_pos.x < 0 || new_pos.x >= GRID_SIZE_X || new_pos.y < 0 {
+/// Checks for collisions with the game board boundaries or other pieces. The ceiling is the top
+/// of the buffer rows, not of the visible field: pieces spawn in the buffer rows at `GRID_SIZE_Y`,
+/// so cells with `y >= GRID_SIZE_Y` stay free up to `GRID_SIZE_Y + GRID_BUFFER_ROWS`. A piece that
+/// locks entirely up there is a lock out instead.
+pub fn check_collision(new_pos: GridPosition, collision_grid: &CollisionGrid) -> bool {
+    // Check for collisions with the floor, walls or the top of the buffer rows
+    if new_pos.x < 0
+        || new_pos.x >= GRID_SIZE_X
+        || new_pos.y < 0
+        || new_pos.y >= GRID_SIZE_Y + GRID_BUFFER_ROWS
+    {
         return true;
     }
     // Check for collisions with other static blocks
-    for static_pos in static_blocks.iter() {
-        if static_pos.x == new_pos.x && static_pos.y == new_pos.y {
-            return true;
-        }
+    collision_grid.is_occupied(new_pos)
+}
+
+/// Returns how many rows a piece with the given blocks can fall before it lands.
+pub fn drop_distance(blocks: &[GridPosition], collision_grid: &CollisionGrid) -> i32 {
+    let mut distance = 0;
+    while blocks.iter().all(|block| {
+        let new_pos = GridPosition {
+            x: block.x,
+            y: block.y - distance - 1,
+        };
+        !check_collision(new_pos, collision_grid)
+    }) {
+        distance += 1;
     }
-    false
+    distance
+}
+
+/// Returns whether a block at the given position is drawn. Blocks in the hidden buffer rows
+/// above the visible field are not.
+pub fn row_visibility(position: GridPosition) -> Visibility {
+    if position.y < GRID_SIZE_Y {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    }
+}
+
+/// Returns true when a system earlier this frame has already decided the next state, such as a
+/// hard drop locking the piece. Systems that lock the piece or end the game check it first, so
+/// only one lock or transition happens per frame.
+pub fn transition_pending(next_state: &NextState<GameState>) -> bool {
+    matches!(next_state, NextState::Pending(_))
+}
+
+/// Returns true when a piece locked with every block above the playfield.
+pub fn is_lock_out<'a>(positions: impl IntoIterator<Item = &'a GridPosition>) -> bool {
+    positions
+        .into_iter()
+        .all(|position| position.y >= GRID_SIZE_Y)
 }
 
-/// A system to make the tetrominoes fall automatically.
+/// A system to make the tetrominoes fall automatically. A piece that has landed locks once the
+/// `LockDelay` has run out.
+#[allow(clippy::too_many_arguments)]
 pub fn gravity_system(
     mut commands: Commands,
     time: Res<Time>,
     mut fall_timer: ResMut<FallTimer>,
-    mut tetromino_query: Query<(Entity, &mut GridPosition), With<Tetromino>>,
-    grid_query: Query<&GridPosition, Without<Tetromino>>,
+    mut lock_delay: ResMut<LockDelay>,
+    mut tetromino_query: Query<
+        (Entity, &mut GridPosition, Option<&mut RotationCenter>),
+        With<Tetromino>,
+    >,
+    collision_grid: Res<CollisionGrid>,
     mut next_state: ResMut<NextState<GameState>>,
+    mut game_over_reason: ResMut<GameOverReason>,
+    gravity_enabled: Res<GravityEnabled>,
 ) {
+    // While gravity is frozen the fall timer is left alone, so falling picks up where it stopped
+    if !gravity_enabled.0 {
+        return;
+    }
+    // A hard drop this frame has already locked the piece and picked the next state
+    if transition_pending(&next_state) {
+        return;
+    }
     fall_timer.tick(time.delta());
-    if fall_timer.finished() {
-        // Collect the positions of all static blocks once for collision checks
-        let static_blocks: Vec<GridPosition> = grid_query.iter().cloned().collect();
-        let mut can_move = true;
-        for (_entity, position) in tetromino_query.iter() {
-            let new_pos = GridPosition {
-                x: position.x,
-                y: position.y - 1,
-            };
-            if check_collision(new_pos, &static_blocks) {
-                can_move = false;
-                break;
-            }
-        }
 
-        if can_move {
-            for (_entity, mut position) in tetromino_query.iter_mut() {
+    let blocks: Vec<GridPosition> = tetromino_query
+        .iter()
+        .map(|(_, position, _)| *position)
+        .collect();
+    let Some(bottom_row) = blocks.iter().map(|position| position.y).min() else {
+        return;
+    };
+    lock_delay.follow(bottom_row);
+
+    lock_delay.grounded = drop_distance(&blocks, &collision_grid) == 0;
+    if !lock_delay.grounded {
+        if fall_timer.finished() {
+            for (_entity, mut position, center) in tetromino_query.iter_mut() {
                 position.y -= 1;
+                if let Some(mut center) = center {
+                    center.rotated_last = false;
+                }
             }
+        }
+        return;
+    }
+
+    // The lock delay only runs down while the piece rests on something
+    lock_delay.timer.tick(time.delta());
+    if !lock_delay.timer.finished() {
+        return;
+    }
+    info!("Piece landed!");
+    // Remove the Tetromino component from the landed pieces, marking them as the last lock
+    for (entity, _, _) in tetromino_query.iter() {
+        commands
+            .entity(entity)
+            .remove::<Tetromino>()
+            .insert(JustLocked);
+    }
+    if is_lock_out(tetromino_query.iter().map(|(_, position, _)| position)) {
+        info!("Lock out!");
+        *game_over_reason = GameOverReason::LockOut;
+        next_state.set(GameState::GameOver);
+    } else {
+        next_state.set(GameState::Spawning);
+    }
+}
+
+/// A system that ends the game when a lock leaves the stack above the playfield once full rows
+/// have been cleared. Blocks in the buffer rows are never drawn, so the stack may only reach
+/// into them until the next lock.
+pub fn check_top_out(
+    grid_query: Query<&GridPosition, Without<Tetromino>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut game_over_reason: ResMut<GameOverReason>,
+) {
+    if transition_pending(&next_state) {
+        return;
+    }
+    if grid_query.iter().any(|position| position.y >= GRID_SIZE_Y) {
+        info!("Top out!");
+        *game_over_reason = GameOverReason::TopOut;
+        next_state.set(GameState::GameOver);
+    }
+}
+
+/// Run condition that is true when the piece left play by locking, and false when it was put in
+/// the hold box.
+pub fn piece_locked(hold_piece: Res<HoldPiece>) -> bool {
+    hold_piece.pending.is_none()
+}
+
+/// A system that restarts the entry delay when a piece has locked. When rows were cleared it
+/// also waits for them to fade away.
+pub fn start_entry_delay(
+    entry_delay: Res<EntryDelaySeconds>,
+    mut entry_delay_timer: ResMut<EntryDelayTimer>,
+    clearing_query: Query<(), With<ClearingRow>>,
+) {
+    let mut seconds = entry_delay.0.max(0.0);
+    if !clearing_query.is_empty() {
+        seconds += LINE_CLEAR_SECONDS;
+    }
+    entry_delay_timer.0 = Timer::from_seconds(seconds, TimerMode::Once);
+}
+
+/// Run condition that is true while no cleared rows are fading away.
+pub fn no_rows_clearing(clearing_query: Query<(), With<ClearingRow>>) -> bool {
+    clearing_query.is_empty()
+}
+
+/// A system that animates cleared rows: their blocks flash white for the first half of the
+/// animation, then shrink and fade out, and are despawned at the end.
+pub fn animate_clearing_rows(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut ClearingRow, &mut Sprite, &mut Transform)>,
+) {
+    for (entity, mut clearing, mut sprite, mut transform) in query.iter_mut() {
+        clearing.0.tick(time.delta());
+        if clearing.0.finished() {
+            commands.entity(entity).try_despawn();
+            continue;
+        }
+        let progress = clearing.0.fraction();
+        if progress < 0.5 {
+            sprite.color = Color::WHITE;
         } else {
-            println!("Piece landed!");
-            // Remove the Tetromino component from the landed pieces
-            for (entity, _) in tetromino_query.iter() {
-                commands.entity(entity).remove::<Tetromino>();
-            }
-            next_state.set(GameState::Spawning);
+            let remaining = (1.0 - progress) * 2.0;
+            sprite.color = Color::srgba(1.0, 1.0, 1.0, remaining);
+            transform.scale = Vec3::splat(remaining);
         }
     }
 }
 
-/// This system keeps the visual transforms in sync with the logical grid positions.
-pub fn update_transforms(mut query: Query<(&GridPosition, &mut Transform)>) {
-    for (grid_position, mut transform) in query.iter_mut() {
-        transform.translation.x =
-            (grid_position.x as f32 - (GRID_SIZE_X as f32 / 2.0)) * crate::constants::BLOCK_SIZE + (crate::constants::BLOCK_SIZE / 2.0);
-        transform.translation.y =
-            (grid_position.y as f32 - (GRID_SIZE_Y as f32 / 2.0)) * crate::constants::BLOCK_SIZE + (crate::constants::BLOCK_SIZE / 2.0);
+/// A system that counts down the entry delay.
+pub fn tick_entry_delay(time: Res<Time>, mut entry_delay_timer: ResMut<EntryDelayTimer>) {
+    entry_delay_timer.tick(time.delta());
+}
+
+/// A system that runs the countdown before a game, and lets the first piece in once it is over.
+pub fn tick_countdown(
+    time: Res<Time>,
+    mut countdown: ResMut<StartCountdown>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if countdown.tick(time.delta()).just_finished() {
+        next_state.set(GameState::Spawning);
+    }
+}
+
+/// Run condition that is true once the entry delay has run out.
+pub fn entry_delay_elapsed(entry_delay_timer: Res<EntryDelayTimer>) -> bool {
+    entry_delay_timer.elapsed() >= entry_delay_timer.duration()
+}
+
+/// A run condition that holds while no state transition is waiting, so a piece never spawns over
+/// a game that has just ended.
+pub fn no_transition_pending(next_state: Res<NextState<GameState>>) -> bool {
+    !transition_pending(&next_state)
+}
+
+/// A system that adds the frame time to the time played. It only runs while playing.
+pub fn tick_play_time(time: Res<Time>, mut play_time: ResMut<PlayTime>) {
+    play_time.0 += time.delta_secs();
+}
+
+/// A system that raises the level as time is played, when timed levels are on. The line rule in
+/// `clear_lines` only levels up once the lines pass the current level, so a level reached by
+/// time is never counted again by lines; the game simply sits at the higher of the two.
+pub fn level_up_by_time(
+    timed_levels: Res<TimedLevels>,
+    play_time: Res<PlayTime>,
+    mut level: ResMut<Level>,
+) {
+    let Some(interval) = timed_levels.0 else {
+        return;
+    };
+    let time_level = 1 + (play_time.0 / interval) as u32;
+    if time_level > level.0 {
+        level.0 = time_level;
+        info!("Level up! Current Level: {}", level.0);
+    }
+}
+
+/// A system that counts a piece each time one locks.
+pub fn count_locked_piece(mut counters: ResMut<PlayCounters>) {
+    counters.pieces += 1;
+}
+
+/// A system that refreshes the APM and PPS every `EFFICIENCY_UPDATE_SECONDS` of play.
+pub fn update_efficiency(
+    play_time: Res<PlayTime>,
+    counters: Res<PlayCounters>,
+    mut efficiency: ResMut<Efficiency>,
+    mut last_update: Local<f32>,
+) {
+    // A new game starts the clock again from zero
+    if play_time.0 < *last_update {
+        *last_update = 0.0;
+    }
+    if play_time.0 - *last_update >= EFFICIENCY_UPDATE_SECONDS {
+        *efficiency = Efficiency::measure(&counters, &play_time);
+        *last_update = play_time.0;
+    }
+}
+
+/// A system that records the finished game in the session's personal bests for its mode.
+pub fn commit_personal_bests(
+    score: Res<Score>,
+    lines_cleared: Res<LinesCleared>,
+    level: Res<Level>,
+    game_mode: Res<GameMode>,
+    mut lifetime_stats: ResMut<LifetimeStats>,
+) {
+    lifetime_stats.record(*game_mode, score.0, lines_cleared.0, level.0);
+}
+
+/// This system keeps the visual transforms in sync with the logical grid positions,
+/// and hides the blocks that are still in the buffer rows.
+pub fn update_transforms(
+    metrics: Res<BoardMetrics>,
+    mut query: Query<(&GridPosition, &mut Transform, &mut Visibility)>,
+) {
+    for (grid_position, mut transform, mut visibility) in query.iter_mut() {
+        let world = metrics.grid_to_world(*grid_position);
+        transform.translation.x = world.x;
+        transform.translation.y = world.y;
         transform.translation.z = 1.0; // Ensure tetrominoes are rendered above the grid
+        visibility.set_if_neq(row_visibility(*grid_position));
+    }
+}
+
+/// A system that draws the ghost piece: the falling piece's blocks, faded, where a hard drop
+/// would put them. It follows every move and rotation, and is removed once the piece locks.
+pub fn update_ghost_piece(
+    mut commands: Commands,
+    metrics: Res<BoardMetrics>,
+    color_scheme: Res<ColorScheme>,
+    block_atlas: Res<BlockAtlas>,
+    tetromino_query: Query<(&GridPosition, &Shape), With<Tetromino>>,
+    collision_grid: Res<CollisionGrid>,
+    mut ghost_query: Query<
+        (Entity, &mut Transform, &mut Sprite, &mut Visibility),
+        With<GhostBlock>,
+    >,
+) {
+    let blocks: Vec<GridPosition> = tetromino_query
+        .iter()
+        .map(|(position, _)| *position)
+        .collect();
+    let Some((_, shape)) = tetromino_query.iter().next() else {
+        for (entity, ..) in ghost_query.iter() {
+            commands.entity(entity).try_despawn();
+        }
+        return;
+    };
+
+    let distance = drop_distance(&blocks, &collision_grid);
+    let ghost_positions = blocks.iter().map(|block| GridPosition {
+        x: block.x,
+        y: block.y - distance,
+    });
+    let mut color = get_tetromino_color(*shape, *color_scheme);
+    color.set_alpha(color.alpha() * GHOST_ALPHA);
+    let sprite = block_atlas.sprite(color, Vec2::splat(metrics.block_size));
+
+    // The same four ghost blocks are moved along with the piece, and only respawned when the
+    // piece changes
+    if ghost_query.iter().len() != blocks.len() {
+        for (entity, ..) in ghost_query.iter() {
+            commands.entity(entity).try_despawn();
+        }
+        for position in ghost_positions {
+            let world = metrics.grid_to_world(position);
+            commands.spawn((
+                sprite.clone(),
+                // Between the background and the blocks
+                Transform::from_xyz(world.x, world.y, 0.75),
+                row_visibility(position),
+                GhostBlock,
+            ));
+        }
+        return;
+    }
+    for ((_, mut transform, mut ghost_sprite, mut visibility), position) in
+        ghost_query.iter_mut().zip(ghost_positions)
+    {
+        let world = metrics.grid_to_world(position);
+        transform.translation.x = world.x;
+        transform.translation.y = world.y;
+        *ghost_sprite = sprite.clone();
+        visibility.set_if_neq(row_visibility(position));
+    }
+}
+
+/// A system that brightens the background rows the falling piece will land in, following it as
+/// it moves and rotates. The rows go back to normal once the piece has locked.
+pub fn update_landing_highlight(
+    metrics: Res<BoardMetrics>,
+    color_scheme: Res<ColorScheme>,
+    block_atlas: Res<BlockAtlas>,
+    tetromino_query: Query<&GridPosition, With<Tetromino>>,
+    collision_grid: Res<CollisionGrid>,
+    mut highlight_query: Query<(&mut Sprite, &mut Transform, &mut Visibility), With<LandingRow>>,
+) {
+    let blocks: Vec<GridPosition> = tetromino_query.iter().copied().collect();
+    // A piece always covers a run of neighbouring rows, so one sprite covers them all. Rows
+    // above the visible grid have no background to brighten.
+    let landing_rows = if blocks.is_empty() {
+        None
+    } else {
+        let distance = drop_distance(&blocks, &collision_grid);
+        let bottom = blocks.iter().map(|block| block.y).min().unwrap_or(0) - distance;
+        let top = blocks.iter().map(|block| block.y).max().unwrap_or(0) - distance;
+        (bottom < GRID_SIZE_Y).then_some((bottom, top.min(GRID_SIZE_Y - 1)))
+    };
+
+    for (mut sprite, mut transform, mut visibility) in highlight_query.iter_mut() {
+        let Some((bottom, top)) = landing_rows else {
+            visibility.set_if_neq(Visibility::Hidden);
+            continue;
+        };
+        visibility.set_if_neq(Visibility::Inherited);
+        // Only rebuilt when the rows move or the board is resized
+        let rect = metrics.rows_rect(bottom, top);
+        if sprite.custom_size != Some(rect.size())
+            || transform.translation.truncate() != rect.center()
+        {
+            *sprite = block_atlas.grid_sprite(
+                landing_row_color(*color_scheme),
+                rect.size(),
+                metrics.block_size,
+            );
+            transform.translation = rect.center().extend(transform.translation.z);
+        }
+    }
+}
+
+/// A system that re-centers the whole board whenever the `BoardMetrics` change.
+/// Unlike `update_transforms` this runs in every state, so landed blocks and the
+/// background follow a resize even while paused or on the game over screen.
+pub fn recenter_board(
+    metrics: Res<BoardMetrics>,
+    color_scheme: Res<ColorScheme>,
+    block_atlas: Res<BlockAtlas>,
+    mut block_query: Query<(&GridPosition, &mut Transform, &mut Sprite), Without<GridBackground>>,
+    mut background_query: Query<(&mut Transform, &mut Sprite), With<GridBackground>>,
+) {
+    let block_size = Some(Vec2::splat(metrics.block_size));
+    for (grid_position, mut transform, mut sprite) in block_query.iter_mut() {
+        let world = metrics.grid_to_world(*grid_position);
+        transform.translation.x = world.x;
+        transform.translation.y = world.y;
+        sprite.custom_size = block_size;
+    }
+    let rect = metrics.rows_rect(0, GRID_SIZE_Y - 1);
+    for (mut transform, mut sprite) in background_query.iter_mut() {
+        *sprite = block_atlas.grid_sprite(
+            grid_cell_color(*color_scheme),
+            rect.size(),
+            metrics.block_size,
+        );
+        transform.translation = rect.center().extend(transform.translation.z);
     }
 }
 
 /// A system that updates the fall speed based on the current level.
-pub fn update_fall_speed(level: Res<Level>, mut fall_timer: ResMut<FallTimer>) {
-    let speed_multiplier = 0.9_f32.powf((level.0 - 1) as f32);
-    fall_timer.set_duration(std::time::Duration::from_secs_f32(1.0 * speed_multiplier));
+pub fn update_fall_speed(
+    level: Res<Level>,
+    rule_profile: Res<RuleProfile>,
+    mut fall_timer: ResMut<FallTimer>,
+) {
+    let fall_seconds = rule_profile.fall_seconds(level.0);
+    fall_timer.set_duration(Duration::from_secs_f32(fall_seconds));
+}
+
+/// A system that records how the last piece locked, before any rows are cleared, and clears the
+/// marks it left on the piece's blocks. A T piece that was turned into place last is a T-spin
+/// when at least three of the cells diagonal to its center are filled or off the playfield.
+pub fn record_last_lock(
+    mut commands: Commands,
+    locked_query: Query<
+        (Entity, &GridPosition, &Shape, Option<&RotationCenter>),
+        With<JustLocked>,
+    >,
+    collision_grid: Res<CollisionGrid>,
+    mut last_lock: ResMut<LastLock>,
+) {
+    *last_lock = LastLock::default();
+    let mut center_position = None;
+    for (entity, position, shape, center) in locked_query.iter() {
+        commands.entity(entity).remove::<JustLocked>();
+        last_lock.shape = Some(*shape);
+        if let Some(center) = center {
+            last_lock.was_rotation = center.rotated_last;
+            center_position = Some(*position);
+        }
+    }
+
+    let Some(center) = center_position else {
+        return;
+    };
+    if last_lock.shape != Some(Shape::T) || !last_lock.was_rotation {
+        return;
+    }
+    let filled_corners = [(-1, -1), (-1, 1), (1, -1), (1, 1)]
+        .into_iter()
+        .filter(|(dx, dy)| {
+            let corner = GridPosition {
+                x: center.x + dx,
+                y: center.y + dy,
+            };
+            check_collision(corner, &collision_grid)
+        })
+        .count();
+    last_lock.t_spin = filled_corners >= 3;
 }
 
 /// A system that checks for and clears full rows, and shifts blocks down.
+#[allow(clippy::too_many_arguments)]
 pub fn clear_lines(
     mut commands: Commands,
     mut score: ResMut<Score>,
     mut lines_cleared: ResMut<LinesCleared>,
     mut level: ResMut<Level>,
-    mut grid_query: Query<(Entity, &mut GridPosition), Without<Tetromino>>,
+    mut all_clear_streak: ResMut<AllClearStreak>,
+    mut combo: ResMut<Combo>,
+    hold_piece: Res<HoldPiece>,
+    last_lock: Res<LastLock>,
+    mut back_to_back: ResMut<BackToBack>,
+    score_multiplier: Res<ScoreMultiplier>,
+    collapse_direction: Res<CollapseDirection>,
+    rule_profile: Res<RuleProfile>,
+    mut grid_query: Query<(Entity, &mut GridPosition, Option<&Shape>), Without<Tetromino>>,
 ) {
-    // Group all static blocks by their Y coordinate.
-    let mut rows: HashMap<i32, Vec<Entity>> = HashMap::new();
-    for (entity, position) in grid_query.iter() {
-        rows.entry(position.y).or_insert_with(Vec::new).push(entity);
+    // Group all static blocks by their Y coordinate, each row ordered left to right, so the
+    // result never depends on the order the query hands the blocks out in.
+    let mut rows: BTreeMap<i32, Vec<(i32, Entity)>> = BTreeMap::new();
+    for (entity, position, _) in grid_query.iter() {
+        rows.entry(position.y).or_default().push((position.x, entity));
+    }
+    for row in rows.values_mut() {
+        row.sort_unstable();
     }
 
+    // Scan away from the side rows collapse towards: bottom up when rows fall down,
+    // top down when they are pushed up. The buffer rows can fill up and clear like any other.
+    let height = GRID_SIZE_Y + GRID_BUFFER_ROWS;
+    let (scan, shift): (Vec<i32>, i32) = match *collapse_direction {
+        CollapseDirection::Down => ((0..height).collect(), -1),
+        CollapseDirection::Up => ((0..height).rev().collect(), 1),
+    };
+
     let mut cleared_rows = 0;
-    // Iterate from the bottom up to check for full rows.
-    for y in 0..GRID_SIZE_Y {
+    let mut cleared_blocks = HashSet::new();
+    for y in scan {
         if let Some(entities) = rows.get(&y) {
             if entities.len() == GRID_SIZE_X as usize {
                 cleared_rows += 1;
-                // Despawn all entities in the cleared row.
-                for entity in entities {
-                    commands.entity(*entity).despawn();
+                // Take the row off the board and let it fade away. The blocks that move into
+                // its place are only drawn there once it has gone.
+                for (_, entity) in entities {
+                    commands
+                        .entity(*entity)
+                        .remove::<GridPosition>()
+                        .insert(ClearingRow(Timer::from_seconds(
+                            LINE_CLEAR_SECONDS,
+                            TimerMode::Once,
+                        )));
                 }
+                cleared_blocks.extend(entities.iter().map(|(_, entity)| *entity));
             } else if cleared_rows > 0 {
-                // If this row is not full, and we've cleared rows below it,
-                // move all blocks in this row down.
-                for entity in entities {
-                    if let Ok((_, mut position)) = grid_query.get_mut(*entity) {
-                        position.y -= cleared_rows;
+                // If this row is not full, and we've already cleared rows,
+                // move all blocks in this row into the gap.
+                for (_, entity) in entities {
+                    if let Ok((_, mut position, _)) = grid_query.get_mut(*entity) {
+                        position.y += shift * cleared_rows;
                     }
                 }
             }
         }
     }
 
+    // A lock that clears nothing ends the combo. Holding a piece also passes through here,
+    // but nothing has locked, so the combo carries on.
+    if cleared_rows == 0 && hold_piece.pending.is_none() {
+        combo.0 = -1;
+    }
+
     // Update the score based on the number of lines cleared and the current level
     if cleared_rows > 0 {
-        println!("Cleared {} lines!", cleared_rows);
-        let points = match cleared_rows {
-            1 => 40,
-            2 => 100,
-            3 => 300,
-            4 => 1200,
-            _ => 0,
+        info!("Cleared {} lines!", cleared_rows);
+        let effect = if cleared_rows == 4 {
+            SoundEffect::Tetris
+        } else {
+            SoundEffect::LineClear
         };
-        score.0 += points * (level.0 + 1);
+        play_sound(&mut commands, effect);
+        let mut awarded = rule_profile.line_clear_points(cleared_rows, level.0);
         lines_cleared.0 += cleared_rows as u32;
 
+        if last_lock.t_spin {
+            let bonus = T_SPIN_POINTS
+                .get(cleared_rows as usize - 1)
+                .copied()
+                .unwrap_or(0);
+            awarded += bonus * level.0;
+            info!("T-spin clearing {} lines!", cleared_rows);
+        }
+
+        // A tetris or T-spin straight after another is worth half as much again, rounded
+        let difficult = cleared_rows == 4 || last_lock.t_spin;
+        if difficult && back_to_back.0 {
+            awarded = (awarded * 3).div_ceil(2);
+            info!("Back-to-back!");
+        }
+        back_to_back.0 = difficult;
+
+        // Each clear that follows another straight away adds to the combo bonus
+        combo.0 += 1;
+        if combo.0 > 0 {
+            awarded += COMBO_POINTS * combo.0 as u32 * level.0;
+            info!("Combo: {}", combo.0);
+        }
+
+        // A perfect clear leaves no blocks behind; consecutive ones escalate the bonus.
+        // The cleared blocks only leave the board once the commands run, so leave them out.
+        let perfect_clear = stack_is_empty(
+            grid_query
+                .iter()
+                .filter(|(entity, _, _)| !cleared_blocks.contains(entity))
+                .map(|(_, position, shape)| (*position, Cell::from_shape(shape))),
+        );
+        if perfect_clear {
+            all_clear_streak.0 += 1;
+            let bonus = match cleared_rows {
+                1 => 800,
+                2 => 1200,
+                3 => 1800,
+                4 => 2000,
+                _ => 0,
+            };
+            awarded += bonus * level.0 * all_clear_streak.0;
+            info!("Perfect clear! Streak: {}", all_clear_streak.0);
+        } else {
+            all_clear_streak.0 = 0;
+        }
+
+        score.0 += score_multiplier.apply(awarded);
+
         // Check if the level needs to be increased
-        if lines_cleared.0 / 5 > (level.0 - 1) {
+        if lines_cleared.0 / rule_profile.lines_per_level() > (level.0 - 1) {
             level.0 += 1;
-            println!("Level up! Current Level: {}", level.0);
+            info!("Level up! Current Level: {}", level.0);
+        }
+
+        info!("Current Score: {}", score.0);
+    }
+}
+
+/// Pushes every static block up by `count` rows and fills the freed rows at the bottom
+/// with garbage, leaving a gap drawn from `rng` in each garbage row. A garbage row is never full,
+/// so it only clears once the player fills its gap, like any other row.
+/// Returns the positions of all static blocks after the garbage has been added.
+pub fn add_garbage_lines(
+    commands: &mut Commands,
+    metrics: &BoardMetrics,
+    block_atlas: &BlockAtlas,
+    static_query: &mut Query<&mut GridPosition, Without<Tetromino>>,
+    rng: &mut impl Rng,
+    count: i32,
+) -> Vec<GridPosition> {
+    let mut static_blocks = Vec::new();
+    for mut position in static_query.iter_mut() {
+        position.y += count;
+        static_blocks.push(*position);
+    }
+
+    for y in 0..count {
+        let gap = rng.random_range(0..GRID_SIZE_X);
+        for x in (0..GRID_SIZE_X).filter(|x| *x != gap) {
+            let position = GridPosition { x, y };
+            let world = metrics.grid_to_world(position);
+            commands.spawn((
+                block_atlas.sprite(GARBAGE_COLOR, Vec2::splat(metrics.block_size)),
+                Transform::from_xyz(world.x, world.y, 1.0),
+                position,
+            ));
+            static_blocks.push(position);
+        }
+    }
+    static_blocks
+}
+
+/// A system that raises a garbage line on a timer in survival mode.
+/// The interval shrinks as the level rises, and the game ends once the garbage
+/// pushes the stack past the top of the board, or leaves the falling piece no room above it.
+#[allow(clippy::too_many_arguments)]
+pub fn survival_garbage_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    game_mode: Res<GameMode>,
+    level: Res<Level>,
+    metrics: Res<BoardMetrics>,
+    block_atlas: Res<BlockAtlas>,
+    mut garbage_timer: ResMut<GarbageTimer>,
+    mut game_rng: ResMut<GameRng>,
+    mut static_query: Query<&mut GridPosition, Without<Tetromino>>,
+    mut tetromino_query: Query<&mut GridPosition, With<Tetromino>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut game_over_reason: ResMut<GameOverReason>,
+) {
+    if *game_mode != GameMode::Survival || transition_pending(&next_state) {
+        return;
+    }
+
+    let interval = (SURVIVAL_GARBAGE_INTERVAL * 0.9_f32.powf((level.0 - 1) as f32))
+        .max(SURVIVAL_MIN_GARBAGE_INTERVAL);
+    garbage_timer.set_duration(Duration::from_secs_f32(interval));
+    garbage_timer.tick(time.delta());
+    if !garbage_timer.just_finished() {
+        return;
+    }
+
+    let static_blocks = add_garbage_lines(
+        &mut commands,
+        &metrics,
+        &block_atlas,
+        &mut static_query,
+        &mut game_rng.0,
+        1,
+    );
+    info!("Garbage rising!");
+
+    // Garbage may push the stack into the buffer rows, but not past them
+    if static_blocks
+        .iter()
+        .any(|position| position.y >= GRID_SIZE_Y + GRID_BUFFER_ROWS)
+    {
+        info!("Game Over!");
+        *game_over_reason = GameOverReason::TopOut;
+        next_state.set(GameState::GameOver);
+        return;
+    }
+
+    // Lift the active piece out of the way if the garbage pushed into it. Once that would push
+    // it past the buffer rows there is no room left for it, and the game is over.
+    while tetromino_query
+        .iter()
+        .any(|position| static_blocks.contains(position))
+    {
+        if tetromino_query
+            .iter()
+            .any(|position| position.y + 1 >= GRID_SIZE_Y + GRID_BUFFER_ROWS)
+        {
+            info!("Game Over!");
+            *game_over_reason = GameOverReason::TopOut;
+            next_state.set(GameState::GameOver);
+            return;
+        }
+        for mut position in tetromino_query.iter_mut() {
+            position.y += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+
+    use crate::{
+        board::sync_collision_grid, components::RotationState, placement::rotated,
+        tetromino::rotation_pivot,
+    };
+
+    /// The first row above the buffer rows, where every cell collides.
+    const CEILING: i32 = GRID_SIZE_Y + GRID_BUFFER_ROWS;
+
+    fn grid_with_block(position: GridPosition) -> CollisionGrid {
+        CollisionGrid::from_blocks([(Entity::PLACEHOLDER, position)])
+    }
+
+    /// Inserts everything `clear_lines` reads, as at the start of a game.
+    pub(crate) fn insert_scoring_resources(world: &mut World) {
+        world.insert_resource(Score(0));
+        world.insert_resource(LinesCleared(0));
+        world.insert_resource(Level(1));
+        world.insert_resource(AllClearStreak(0));
+        world.init_resource::<Combo>();
+        world.init_resource::<HoldPiece>();
+        world.init_resource::<LastLock>();
+        world.init_resource::<BackToBack>();
+        world.insert_resource(ScoreMultiplier(1.0));
+        world.init_resource::<CollapseDirection>();
+        world.init_resource::<RuleProfile>();
+    }
+
+    /// Spawns settled blocks, as a piece locking in those cells leaves behind.
+    fn lock_blocks(world: &mut World, cells: &[(i32, i32)]) {
+        for &(x, y) in cells {
+            world.spawn(GridPosition { x, y });
+        }
+    }
+
+    /// Returns the cells of the settled blocks, sorted.
+    fn settled_cells(world: &mut World) -> Vec<(i32, i32)> {
+        let mut cells: Vec<(i32, i32)> = world
+            .query_filtered::<&GridPosition, Without<Tetromino>>()
+            .iter(world)
+            .map(|position| (position.x, position.y))
+            .collect();
+        cells.sort_unstable();
+        cells
+    }
+
+    /// Returns the cells the collision grid holds a block in, in the same order.
+    fn grid_cells(grid: &CollisionGrid) -> Vec<(i32, i32)> {
+        (0..GRID_SIZE_X)
+            .flat_map(|x| (0..CEILING).map(move |y| (x, y)))
+            .filter(|&(x, y)| grid.is_occupied(GridPosition { x, y }))
+            .collect()
+    }
+
+    #[test]
+    fn top_visible_row_is_free_until_a_block_settles_there() {
+        let position = GridPosition {
+            x: 4,
+            y: GRID_SIZE_Y - 1,
+        };
+        assert!(!check_collision(position, &CollisionGrid::default()));
+        assert!(check_collision(position, &grid_with_block(position)));
+    }
+
+    #[test]
+    fn buffer_rows_are_free_up_to_the_ceiling() {
+        let empty = CollisionGrid::default();
+        for y in GRID_SIZE_Y..CEILING {
+            assert!(!check_collision(GridPosition { x: 4, y }, &empty));
+        }
+        assert!(check_collision(GridPosition { x: 4, y: CEILING }, &empty));
+
+        let spawn_row = GridPosition {
+            x: 4,
+            y: GRID_SIZE_Y,
+        };
+        assert!(check_collision(spawn_row, &grid_with_block(spawn_row)));
+    }
+
+    #[test]
+    fn rotation_spilling_over_the_top_collides() {
+        // A flat I piece in the top buffer row stands up across the ceiling when turned
+        let blocks: Vec<GridPosition> =
+            (3..7).map(|x| GridPosition { x, y: CEILING - 1 }).collect();
+        let pivot = rotation_pivot(Shape::I, blocks[1], RotationState::Spawn);
+        let turned = rotated(&blocks, pivot, 1);
+
+        assert!(turned.iter().any(|block| block.y >= CEILING));
+        assert!(
+            turned
+                .iter()
+                .any(|block| check_collision(*block, &CollisionGrid::default()))
+        );
+    }
+
+    #[test]
+    fn stack_reaching_above_the_field_tops_out() {
+        let mut world = World::new();
+        world.init_resource::<NextState<GameState>>();
+        world.init_resource::<GameOverReason>();
+
+        // A column up to the top visible row is still in play, and so is a falling piece above it
+        for y in 0..GRID_SIZE_Y {
+            world.spawn(GridPosition { x: 0, y });
+        }
+        world.spawn((
+            GridPosition {
+                x: 4,
+                y: GRID_SIZE_Y,
+            },
+            Tetromino,
+        ));
+        world.run_system_once(check_top_out).unwrap();
+        assert!(!transition_pending(
+            world.resource::<NextState<GameState>>()
+        ));
+
+        // A settled block above the field ends the game
+        world.spawn(GridPosition {
+            x: 0,
+            y: GRID_SIZE_Y,
+        });
+        world.run_system_once(check_top_out).unwrap();
+        assert!(matches!(
+            world.resource::<NextState<GameState>>(),
+            NextState::Pending(GameState::GameOver)
+        ));
+        assert_eq!(*world.resource::<GameOverReason>(), GameOverReason::TopOut);
+    }
+
+    #[test]
+    fn collision_grid_follows_locks_and_clears() {
+        let mut world = World::new();
+        insert_scoring_resources(&mut world);
+        world.init_resource::<CollisionGrid>();
+        let mut lock = Schedule::default();
+        lock.add_systems((clear_lines, sync_collision_grid).chain());
+
+        // A flat I and an O beside it leave the bottom row four cells short
+        lock_blocks(&mut world, &[(0, 0), (1, 0), (2, 0), (3, 0)]);
+        lock.run(&mut world);
+        lock_blocks(&mut world, &[(4, 0), (5, 0), (4, 1), (5, 1)]);
+        lock.run(&mut world);
+        assert_eq!(settled_cells(&mut world).len(), 8);
+        assert_eq!(
+            grid_cells(world.resource::<CollisionGrid>()),
+            settled_cells(&mut world)
+        );
+
+        // Another flat I completes the row, which clears and lets the top of the O drop
+        lock_blocks(&mut world, &[(6, 0), (7, 0), (8, 0), (9, 0)]);
+        lock.run(&mut world);
+        assert_eq!(settled_cells(&mut world), [(4, 0), (5, 0)]);
+        assert_eq!(
+            grid_cells(world.resource::<CollisionGrid>()),
+            settled_cells(&mut world)
+        );
+    }
+
+    /// Returns the columns of the settled blocks in row `y`, left to right.
+    fn row_columns(world: &mut World, y: i32) -> Vec<i32> {
+        settled_cells(world)
+            .into_iter()
+            .filter(|&(_, row)| row == y)
+            .map(|(x, _)| x)
+            .collect()
+    }
+
+    #[test]
+    fn garbage_rows_keep_a_gap_until_it_is_filled() {
+        let mut world = World::new();
+        insert_scoring_resources(&mut world);
+        world.init_resource::<GameRng>();
+        lock_blocks(&mut world, &[(0, 0), (1, 0)]);
+        world
+            .run_system_once(
+                |mut commands: Commands,
+                 mut static_query: Query<&mut GridPosition, Without<Tetromino>>,
+                 mut game_rng: ResMut<GameRng>| {
+                    add_garbage_lines(
+                        &mut commands,
+                        &BoardMetrics::default(),
+                        &BlockAtlas::default(),
+                        &mut static_query,
+                        &mut game_rng.0,
+                        3,
+                    );
+                },
+            )
+            .unwrap();
+
+        // Every garbage row is one cell short, and the stack above rides up on top of them
+        let gaps: Vec<i32> = (0..3)
+            .map(|y| {
+                let columns = row_columns(&mut world, y);
+                assert_eq!(columns.len(), GRID_SIZE_X as usize - 1);
+                (0..GRID_SIZE_X).find(|x| !columns.contains(x)).unwrap()
+            })
+            .collect();
+        assert_eq!(row_columns(&mut world, 3), [0, 1]);
+        world.run_system_once(clear_lines).unwrap();
+        assert_eq!(world.resource::<LinesCleared>().0, 0);
+
+        // Filling the bottom gap clears that row alone and drops the rest by one
+        lock_blocks(&mut world, &[(gaps[0], 0)]);
+        world.run_system_once(clear_lines).unwrap();
+        assert_eq!(world.resource::<LinesCleared>().0, 1);
+        for (y, gap) in gaps[1..].iter().enumerate() {
+            let columns = row_columns(&mut world, y as i32);
+            assert_eq!(columns.len(), GRID_SIZE_X as usize - 1);
+            assert!(!columns.contains(gap));
+        }
+        assert_eq!(row_columns(&mut world, 2), [0, 1]);
+    }
+
+    #[test]
+    fn clearing_does_not_depend_on_spawn_order() {
+        // Two full rows under a ragged stack, with a partial row trapped between them
+        let mut cells: Vec<(i32, i32)> = (0..GRID_SIZE_X)
+            .flat_map(|x| [(x, 0), (x, 2)])
+            .chain([(0, 1), (3, 1), (4, 1), (1, 3), (5, 3), (6, 4)])
+            .collect();
+
+        let mut outcomes = Vec::new();
+        for order in 0..3 {
+            match order {
+                1 => cells.reverse(),
+                2 => cells.rotate_left(7),
+                _ => {}
+            }
+            let mut world = World::new();
+            insert_scoring_resources(&mut world);
+            lock_blocks(&mut world, &cells);
+            world.run_system_once(clear_lines).unwrap();
+            outcomes.push((
+                settled_cells(&mut world),
+                world.resource::<Score>().0,
+                world.resource::<LinesCleared>().0,
+            ));
+        }
+
+        assert_eq!(
+            outcomes[0].0,
+            [(0, 0), (1, 1), (3, 0), (4, 0), (5, 1), (6, 2)]
+        );
+        assert_eq!(outcomes[0].2, 2);
+        assert!(outcomes.iter().all(|outcome| *outcome == outcomes[0]));
+    }
+
+    #[test]
+    fn collapsing_up_lifts_the_rows_below_the_cleared_ones() {
+        let mut world = World::new();
+        insert_scoring_resources(&mut world);
+        world.insert_resource(CollapseDirection::Up);
+        // Two full rows in the middle of the stack, with blocks on either side of them
+        let cells: Vec<(i32, i32)> = (0..GRID_SIZE_X)
+            .flat_map(|x| [(x, 2), (x, 3)])
+            .chain([(0, 0), (2, 1), (1, 4), (5, 6)])
+            .collect();
+        lock_blocks(&mut world, &cells);
+        world.run_system_once(clear_lines).unwrap();
+
+        assert_eq!(settled_cells(&mut world), [(0, 2), (1, 4), (2, 3), (5, 6)]);
+        assert_eq!(world.resource::<LinesCleared>().0, 2);
+    }
+
+    #[test]
+    fn gravity_moves_at_most_one_row_per_tick() {
+        let mut world = World::new();
+        world.insert_resource(FallTimer(Timer::from_seconds(
+            RuleProfile::default().fall_seconds(100),
+            TimerMode::Repeating,
+        )));
+        world.init_resource::<LockDelay>();
+        world.init_resource::<CollisionGrid>();
+        world.init_resource::<NextState<GameState>>();
+        world.init_resource::<GameOverReason>();
+        world.init_resource::<GravityEnabled>();
+        for x in 3..7 {
+            world.spawn((GridPosition { x, y: 15 }, Tetromino));
+        }
+
+        // A long frame covers many falls at level 100, but the piece still drops a row at a time
+        let mut time = Time::<()>::default();
+        for expected_row in (10..15).rev() {
+            time.advance_by(Duration::from_millis(500));
+            world.insert_resource(time);
+            world.run_system_once(gravity_system).unwrap();
+            let rows: Vec<i32> = world
+                .query_filtered::<&GridPosition, With<Tetromino>>()
+                .iter(&world)
+                .map(|position| position.y)
+                .collect();
+            assert_eq!(rows, [expected_row; 4]);
         }
+    }
+
+    #[test]
+    fn tetrises_and_t_spins_share_one_back_to_back_chain() {
+        let mut world = World::new();
+        insert_scoring_resources(&mut world);
+        world.insert_resource(RuleProfile::Guideline);
+        // A lone block on the bottom row keeps the clears above it from being perfect clears
+        lock_blocks(&mut world, &[(0, 0)]);
 
-        println!("Current Score: {}", score.0);
+        for (clear, (rows, t_spin)) in [(4, false), (1, true), (4, false), (1, true)]
+            .into_iter()
+            .enumerate()
+        {
+            let cells: Vec<(i32, i32)> = (1..=rows)
+                .flat_map(|y| (0..GRID_SIZE_X).map(move |x| (x, y)))
+                .collect();
+            lock_blocks(&mut world, &cells);
+            world.insert_resource(LastLock {
+                shape: Some(if t_spin { Shape::T } else { Shape::I }),
+                was_rotation: t_spin,
+                t_spin,
+            });
+            // Nothing else in between keeps the combo bonus out of the score
+            world.insert_resource(Combo(-1));
+            let before = world.resource::<Score>().0;
+            world.run_system_once(clear_lines).unwrap();
+
+            let mut expected = RuleProfile::Guideline.line_clear_points(rows, 1);
+            if t_spin {
+                expected += T_SPIN_POINTS[rows as usize - 1];
+            }
+            if clear > 0 {
+                expected = (expected * 3).div_ceil(2);
+            }
+            assert_eq!(world.resource::<Score>().0 - before, expected);
+            assert!(world.resource::<BackToBack>().0);
+        }
+        assert_eq!(settled_cells(&mut world), [(0, 0)]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn consecutive_perfect_clears_escalate_the_bonus() {
+        let mut world = World::new();
+        insert_scoring_resources(&mut world);
+
+        // A single and then a double that each empty the board, and then a clear that does not
+        for (rows, leftover, streak, bonus) in
+            [(1, false, 1, 800), (2, false, 2, 1200 * 2), (1, true, 0, 0)]
+        {
+            let mut cells: Vec<(i32, i32)> = (0..rows)
+                .flat_map(|y| (0..GRID_SIZE_X).map(move |x| (x, y)))
+                .collect();
+            if leftover {
+                cells.push((0, rows));
+            }
+            lock_blocks(&mut world, &cells);
+            // Nothing else in between keeps the combo bonus out of the score
+            world.insert_resource(Combo(-1));
+            let before = world.resource::<Score>().0;
+            world.run_system_once(clear_lines).unwrap();
+
+            let expected = RuleProfile::default().line_clear_points(rows, 1) + bonus;
+            assert_eq!(world.resource::<Score>().0 - before, expected);
+            assert_eq!(world.resource::<AllClearStreak>().0, streak);
+        }
+    }
+
+    #[test]
+    fn cleared_lines_are_scaled_by_the_score_multiplier() {
+        let mut world = World::new();
+        insert_scoring_resources(&mut world);
+        world.insert_resource(ScoreMultiplier(1.5));
+        // A double with a block left over, so only the line clear itself scores
+        let cells: Vec<(i32, i32)> = (0..GRID_SIZE_X)
+            .flat_map(|x| [(x, 0), (x, 1)])
+            .chain([(0, 2)])
+            .collect();
+        lock_blocks(&mut world, &cells);
+        world.run_system_once(clear_lines).unwrap();
+
+        let points = RuleProfile::default().line_clear_points(2, 1);
+        assert_eq!(world.resource::<Score>().0, points * 3 / 2);
+    }
+
+    #[test]
+    fn timed_levels_go_up_with_play_time_without_counting_twice() {
+        let mut world = World::new();
+        insert_scoring_resources(&mut world);
+        world.insert_resource(TimedLevels(Some(30.0)));
+        world.init_resource::<PlayTime>();
+        world.init_resource::<Time>();
+        let mut play = Schedule::default();
+        play.add_systems((tick_play_time, level_up_by_time).chain());
+        let mut play_for = |world: &mut World, seconds: u32| {
+            for _ in 0..seconds {
+                let mut time = *world.resource::<Time>();
+                time.advance_by(Duration::from_secs(1));
+                world.insert_resource(time);
+                play.run(world);
+            }
+        };
+
+        play_for(&mut world, 29);
+        assert_eq!(world.resource::<Level>().0, 1);
+        play_for(&mut world, 1);
+        assert_eq!(world.resource::<Level>().0, 2);
+
+        // Reaching level 2 by lines as well is no second level up
+        let lines_per_level = world.resource::<RuleProfile>().lines_per_level();
+        world.insert_resource(LinesCleared(lines_per_level - 1));
+        let full_row: Vec<(i32, i32)> = (0..GRID_SIZE_X).map(|x| (x, 0)).collect();
+        lock_blocks(&mut world, &full_row);
+        world.run_system_once(clear_lines).unwrap();
+        assert_eq!(world.resource::<Level>().0, 2);
+
+        play_for(&mut world, 30);
+        assert_eq!(world.resource::<Level>().0, 3);
+    }
+
+    #[test]
+    fn a_block_left_in_the_buffer_rows_is_no_perfect_clear() {
+        let mut world = World::new();
+        insert_scoring_resources(&mut world);
+        let full_row: Vec<(i32, i32)> = (0..GRID_SIZE_X).map(|x| (x, 0)).collect();
+
+        lock_blocks(&mut world, &full_row);
+        world.run_system_once(clear_lines).unwrap();
+        assert_eq!(world.resource::<AllClearStreak>().0, 1);
+
+        lock_blocks(&mut world, &full_row);
+        lock_blocks(&mut world, &[(0, GRID_SIZE_Y + 1)]);
+        world.run_system_once(clear_lines).unwrap();
+        assert_eq!(world.resource::<LinesCleared>().0, 2);
+        assert_eq!(world.resource::<AllClearStreak>().0, 0);
+    }
+
+    /// Locks a T piece pointing down with its center at `center`, then records how it locked
+    /// against the given settled blocks.
+    fn lock_t_piece(center: (i32, i32), rotated_last: bool, settled: &[(i32, i32)]) -> LastLock {
+        let mut world = World::new();
+        world.init_resource::<LastLock>();
+        world.insert_resource(CollisionGrid::from_blocks(
+            settled
+                .iter()
+                .map(|&(x, y)| (Entity::PLACEHOLDER, GridPosition { x, y })),
+        ));
+        let (x, y) = center;
+        world.spawn((
+            GridPosition { x, y },
+            Shape::T,
+            JustLocked,
+            RotationCenter {
+                shape: Shape::T,
+                state: RotationState::Flipped,
+                rotated_last,
+            },
+        ));
+        for (x, y) in [(x - 1, y), (x + 1, y), (x, y - 1)] {
+            world.spawn((GridPosition { x, y }, Shape::T, JustLocked));
+        }
+        world.run_system_once(record_last_lock).unwrap();
+        *world.resource::<LastLock>()
+    }
+
+    #[test]
+    fn turning_a_t_into_a_three_corner_slot_is_a_t_spin() {
+        // The floor fills both lower corners and an overhang the upper left one
+        let slot = [(3, 0), (5, 0), (3, 2)];
+        let last_lock = lock_t_piece((4, 1), true, &slot);
+        assert_eq!(
+            last_lock,
+            LastLock {
+                shape: Some(Shape::T),
+                was_rotation: true,
+                t_spin: true,
+            }
+        );
+    }
+
+    #[test]
+    fn dropping_a_t_into_the_slot_is_no_t_spin() {
+        let last_lock = lock_t_piece((4, 1), false, &[(3, 0), (5, 0), (3, 2)]);
+        assert_eq!(last_lock.shape, Some(Shape::T));
+        assert!(!last_lock.was_rotation);
+        assert!(!last_lock.t_spin);
+    }
+
+    #[test]
+    fn two_filled_corners_are_no_t_spin() {
+        let last_lock = lock_t_piece((4, 1), true, &[(3, 0), (5, 0)]);
+        assert!(last_lock.was_rotation);
+        assert!(!last_lock.t_spin);
+    }
+
+    /// Sets up a survival game over the given settled blocks.
+    fn survival_world(cells: &[(i32, i32)]) -> World {
+        let mut world = World::new();
+        world.insert_resource(GameMode::Survival);
+        world.insert_resource(Level(1));
+        world.init_resource::<BoardMetrics>();
+        world.init_resource::<BlockAtlas>();
+        world.insert_resource(GarbageTimer(Timer::from_seconds(
+            SURVIVAL_GARBAGE_INTERVAL,
+            TimerMode::Repeating,
+        )));
+        world.init_resource::<GameRng>();
+        world.init_resource::<NextState<GameState>>();
+        // Anything but a top out, to see the garbage set it
+        world.insert_resource(GameOverReason::BlockOut);
+        lock_blocks(&mut world, cells);
+        world
+    }
+
+    /// Runs `survival_garbage_system` over a frame long enough to raise one garbage line.
+    fn raise_garbage(world: &mut World) {
+        let mut time = Time::<()>::default();
+        time.advance_by(Duration::from_secs_f32(SURVIVAL_GARBAGE_INTERVAL));
+        world.insert_resource(time);
+        world.run_system_once(survival_garbage_system).unwrap();
+    }
+
+    #[test]
+    fn garbage_may_push_the_stack_into_the_buffer_rows() {
+        let mut world = survival_world(&[(0, CEILING - 2)]);
+        raise_garbage(&mut world);
+        assert!(!transition_pending(
+            world.resource::<NextState<GameState>>()
+        ));
+        assert!(settled_cells(&mut world).contains(&(0, CEILING - 1)));
+        assert_eq!(
+            *world.resource::<GameOverReason>(),
+            GameOverReason::BlockOut
+        );
+    }
+
+    #[test]
+    fn garbage_pushing_the_stack_past_the_buffer_rows_tops_out() {
+        let mut world = survival_world(&[(0, CEILING - 1)]);
+        raise_garbage(&mut world);
+        assert!(matches!(
+            world.resource::<NextState<GameState>>(),
+            NextState::Pending(GameState::GameOver)
+        ));
+        assert_eq!(*world.resource::<GameOverReason>(), GameOverReason::TopOut);
+    }
+
+    #[test]
+    fn changing_the_metrics_moves_every_block() {
+        let mut world = World::new();
+        world.init_resource::<BoardMetrics>();
+        world.init_resource::<ColorScheme>();
+        world.init_resource::<BlockAtlas>();
+        let cells = [(0, 0), (9, 0), (4, 7), (5, GRID_SIZE_Y)];
+        for (x, y) in cells {
+            world.spawn((
+                GridPosition { x, y },
+                Transform::default(),
+                Sprite::default(),
+            ));
+        }
+        world.spawn((
+            GridPosition { x: 4, y: 18 },
+            Tetromino,
+            Transform::default(),
+            Sprite::default(),
+        ));
+
+        let mut schedule = Schedule::default();
+        schedule.add_systems(recenter_board.run_if(resource_changed::<BoardMetrics>));
+        schedule.run(&mut world);
+        world.resource_mut::<BoardMetrics>().block_size = 12.0;
+        schedule.run(&mut world);
+
+        let metrics = *world.resource::<BoardMetrics>();
+        let mut query = world.query::<(&GridPosition, &Transform, &Sprite)>();
+        assert_eq!(query.iter(&world).count(), cells.len() + 1);
+        for (position, transform, sprite) in query.iter(&world) {
+            assert_eq!(
+                transform.translation.truncate(),
+                metrics.grid_to_world(*position)
+            );
+            assert_eq!(sprite.custom_size, Some(Vec2::splat(12.0)));
+        }
+    }
+
+    /// Starts the entry delay, then runs frames of `step` seconds through the `Spawning` state
+    /// until the next piece may spawn, and returns how many frames that took.
+    fn frames_until_spawn(world: &mut World, step: f32) -> u32 {
+        world.init_resource::<EntryDelayTimer>();
+        world.run_system_once(start_entry_delay).unwrap();
+        let mut time = Time::<()>::default();
+        for frame in 1..=100 {
+            time.advance_by(Duration::from_secs_f32(step));
+            world.insert_resource(time);
+            world.run_system_once(tick_entry_delay).unwrap();
+            if world.run_system_once(entry_delay_elapsed).unwrap() {
+                return frame;
+            }
+        }
+        panic!("The entry delay never ran out");
+    }
+
+    #[test]
+    fn the_next_piece_waits_out_the_entry_delay() {
+        let mut world = World::new();
+        world.insert_resource(EntryDelaySeconds(0.5));
+        assert_eq!(frames_until_spawn(&mut world, 0.125), 4);
+
+        // Cleared rows fading away add to the wait
+        world.spawn(ClearingRow(Timer::from_seconds(
+            LINE_CLEAR_SECONDS,
+            TimerMode::Once,
+        )));
+        assert_eq!(frames_until_spawn(&mut world, 0.125), 7);
+    }
+
+    #[test]
+    fn no_entry_delay_spawns_on_the_next_frame() {
+        let mut world = World::new();
+        world.insert_resource(EntryDelaySeconds(0.0));
+        assert_eq!(frames_until_spawn(&mut world, 1.0 / 60.0), 1);
+    }
+
+    #[test]
+    fn each_rule_profile_sets_its_own_fall_speed_and_single_score() {
+        for (profile, fall_seconds, single_points) in [
+            (RuleProfile::Nintendo, 48.0 / 60.0, 80),
+            (RuleProfile::Guideline, 1.0, 100),
+            (RuleProfile::Sega, 1.0, 100),
+            (RuleProfile::Custom, 1.0, 80),
+        ] {
+            let mut world = World::new();
+            insert_scoring_resources(&mut world);
+            world.insert_resource(profile);
+            world.insert_resource(FallTimer(Timer::from_seconds(0.1, TimerMode::Repeating)));
+            world.run_system_once(update_fall_speed).unwrap();
+            assert_eq!(
+                world.resource::<FallTimer>().duration(),
+                Duration::from_secs_f32(fall_seconds),
+                "{profile:?}"
+            );
+
+            // A single on level 1, with a block left over so it is no perfect clear
+            let cells: Vec<(i32, i32)> = (0..GRID_SIZE_X).map(|x| (x, 0)).chain([(0, 1)]).collect();
+            lock_blocks(&mut world, &cells);
+            world.run_system_once(clear_lines).unwrap();
+            assert_eq!(world.resource::<Score>().0, single_points, "{profile:?}");
+        }
+    }
+
+    #[test]
+    fn the_paused_board_follows_a_resize() {
+        let mut app = App::new();
+        app.add_plugins(bevy::state::app::StatesPlugin)
+            .init_state::<GameState>()
+            .init_resource::<BoardMetrics>()
+            .init_resource::<ColorScheme>()
+            .init_resource::<BlockAtlas>()
+            // As wired up in main
+            .add_systems(OnEnter(GameState::Paused), update_transforms)
+            .add_systems(
+                Update,
+                recenter_board.run_if(resource_changed::<BoardMetrics>),
+            );
+        app.update();
+
+        // Stale transforms, as if the blocks moved since they were last drawn
+        for (x, y) in [(0, 0), (1, 0)] {
+            app.world_mut().spawn((
+                GridPosition { x, y },
+                Transform::default(),
+                Sprite::default(),
+                Visibility::default(),
+            ));
+        }
+        for (x, y) in [(4, 10), (5, 10), (4, 11), (5, 11)] {
+            app.world_mut().spawn((
+                GridPosition { x, y },
+                Tetromino,
+                Transform::default(),
+                Sprite::default(),
+                Visibility::default(),
+            ));
+        }
+
+        let blocks_in_place = |app: &mut App| {
+            let metrics = *app.world().resource::<BoardMetrics>();
+            let world = app.world_mut();
+            let mut query = world.query::<(&GridPosition, &Transform)>();
+            query.iter(world).all(|(position, transform)| {
+                transform.translation.truncate() == metrics.grid_to_world(*position)
+            })
+        };
+
+        app.world_mut()
+            .resource_mut::<NextState<GameState>>()
+            .set(GameState::Paused);
+        app.update();
+        assert_eq!(
+            *app.world().resource::<State<GameState>>().get(),
+            GameState::Paused
+        );
+        assert!(blocks_in_place(&mut app));
+
+        app.world_mut().resource_mut::<BoardMetrics>().block_size = 12.0;
+        app.update();
+        assert!(blocks_in_place(&mut app));
+    }
+
+    #[test]
+    fn garbage_lifts_the_piece_only_as_far_as_the_ceiling() {
+        // A column right under an O piece, which the garbage pushes up into it
+        for (piece_bottom, game_over) in [(15, false), (GRID_SIZE_Y, true)] {
+            let column: Vec<(i32, i32)> = (0..piece_bottom).map(|y| (4, y)).collect();
+            let mut world = survival_world(&column);
+            for (x, y) in [(4, 0), (5, 0), (4, 1), (5, 1)] {
+                world.spawn((
+                    GridPosition {
+                        x,
+                        y: piece_bottom + y,
+                    },
+                    Tetromino,
+                ));
+            }
+            raise_garbage(&mut world);
+
+            let piece_bottom_after = world
+                .query_filtered::<&GridPosition, With<Tetromino>>()
+                .iter(&world)
+                .map(|position| position.y)
+                .min();
+            if game_over {
+                assert!(matches!(
+                    world.resource::<NextState<GameState>>(),
+                    NextState::Pending(GameState::GameOver)
+                ));
+                assert_eq!(*world.resource::<GameOverReason>(), GameOverReason::TopOut);
+                // It is left where it was rather than pushed over the ceiling
+                assert_eq!(piece_bottom_after, Some(piece_bottom));
+            } else {
+                assert!(!transition_pending(
+                    world.resource::<NextState<GameState>>()
+                ));
+                assert_eq!(piece_bottom_after, Some(piece_bottom + 1));
+            }
+        }
+    }
+}
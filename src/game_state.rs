@@ -1,13 +1,17 @@
 use bevy::prelude::*;
 
 /// Represents the different states the game can be in.
-/// This controls the game flow between title screen, playing, paused, and game over states.
+/// This controls the game flow between title screen, countdown, playing, paused, game over and
+/// victory states.
 #[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, States)]
 pub enum GameState {
     #[default]
     Title,
+    /// The "3, 2, 1, GO" shown over the empty board before the first piece of a game.
+    Countdown,
     Playing,
     Paused,
     Spawning,
     GameOver,
+    Victory,
 }
\ No newline at end of file
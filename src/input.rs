@@ -1,216 +1,1373 @@
-use bevy::prelude::*;
+use bevy::{ecs::system::SystemParam, prelude::*};
 
 use crate::{
-    components::{GridPosition, RotationCenter, Tetromino},
-    game_logic::check_collision,
+    bindings::{ActionInput, GameAction},
+    board::CollisionGrid,
+    components::{GridPosition, HoldSwap, JustLocked, RotationCenter, Shape, Tetromino},
+    constants::{
+        GRID_BUFFER_ROWS, GRID_SIZE_X, GRID_SIZE_Y, HARD_DROP_POINTS_PER_CELL,
+        SOFT_DROP_POINTS_PER_CELL, SURVIVAL_GARBAGE_INTERVAL,
+    },
+    game_logic::{check_collision, drop_distance, is_lock_out, transition_pending},
     game_state::GameState,
-    resources::{Level, LinesCleared, Score},
+    puzzle::{PuzzleProgress, Puzzles},
+    randomizer::{GameRng, GameSeed, PieceGenerator, RandomizerKind, RepeatLimit},
+    resources::{
+        AllClearStreak, BackToBack, ColorScheme, Combo, Efficiency, ForcedSequence, GameMode,
+        GameOverReason, GarbageTimer, HoldPiece, HudSide, IdleDrop, InputBuffer, InputPriority,
+        Level, LifetimeStats, LinesCleared, LockDelay, NewBests, NextPiece, NextQueue,
+        NoOverhangStart, PlayCounters, PlayTime, RuleProfile, Score, ScoreMultiplier,
+        ScreenTransition, ShiftDas, ShiftRepeat, SoftDropDas, SoftDropRepeat, StartCountdown,
+        StartingLevel, StatsResetPending, Volume,
+    },
+    rewind::{RewindHistory, RewindsLeft},
+    setup::save_volume,
+    sound::{SoundEffect, play_sound},
+    srs::kick_offsets,
+    tetromino::{first_shape, rotate_about, rotation_pivot},
 };
 
+/// A system to handle the title screen keys: starting the game and changing the settings.
+#[allow(clippy::too_many_arguments)]
+pub fn handle_title_input(
+    input: Res<ButtonInput<KeyCode>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut game_mode: ResMut<GameMode>,
+    mut rule_profile: ResMut<RuleProfile>,
+    mut randomizer_kind: ResMut<RandomizerKind>,
+    mut game_seed: ResMut<GameSeed>,
+    mut hud_side: ResMut<HudSide>,
+    mut color_scheme: ResMut<ColorScheme>,
+    mut puzzles: ResMut<Puzzles>,
+    mut lifetime_stats: ResMut<LifetimeStats>,
+    mut stats_reset: ResMut<StatsResetPending>,
+    mut starting_level: ResMut<StartingLevel>,
+    mut level: ResMut<Level>,
+    gamepads: Query<&Gamepad>,
+) {
+    // Wipe the bests of the selected mode, once a second press of X confirms it
+    if input.just_pressed(KeyCode::KeyX) {
+        if stats_reset.0 {
+            lifetime_stats.reset(*game_mode);
+            info!("{} personal bests reset", game_mode.label());
+        } else {
+            info!("Press X again to reset the personal bests");
+        }
+        stats_reset.0 = !stats_reset.0;
+        return;
+    }
+
+    let start_pressed = input.just_pressed(KeyCode::Space)
+        || gamepads
+            .iter()
+            .any(|gamepad| gamepad.just_pressed(GamepadButton::Start));
+    // Any other key keeps the bests after all
+    if stats_reset.0 && (start_pressed || input.get_just_pressed().next().is_some()) {
+        stats_reset.0 = false;
+    }
+
+    // Start the game from the title screen, at the chosen level
+    if start_pressed {
+        level.0 = starting_level.for_mode(*game_mode);
+        next_state.set(GameState::Countdown);
+        info!("Game started!");
+        return;
+    }
+
+    // Cycle through the game modes on the title screen
+    if input.just_pressed(KeyCode::KeyM) {
+        *game_mode = game_mode.next();
+        info!("Game mode: {}", game_mode.label());
+        return;
+    }
+
+    // Cycle through the scoring and leveling rule profiles
+    if input.just_pressed(KeyCode::KeyL) {
+        *rule_profile = rule_profile.next();
+        info!("Rules: {}", rule_profile.label());
+        return;
+    }
+
+    // Cycle through the ways pieces are dealt
+    if input.just_pressed(KeyCode::KeyG) {
+        *randomizer_kind = randomizer_kind.next();
+        info!("Randomizer: {}", randomizer_kind.label());
+        return;
+    }
+
+    // Pick the level to start at; puzzles and training always start at level 1
+    if !game_mode.uses_puzzle_board() {
+        let up = input.just_pressed(KeyCode::ArrowUp);
+        let down = input.just_pressed(KeyCode::ArrowDown);
+        let steps = up as i32 - down as i32;
+        if steps != 0 {
+            *starting_level = starting_level.stepped(steps);
+            info!("Starting level: {}", starting_level.0);
+            return;
+        }
+    }
+
+    // Roll a new seed for the pieces of the upcoming game
+    if input.just_pressed(KeyCode::KeyD) {
+        *game_seed = GameSeed::default();
+        info!("Seed: {}", game_seed.label());
+        return;
+    }
+
+    // Flip the HUD panels to the other side of the playfield
+    if input.just_pressed(KeyCode::KeyH) {
+        *hud_side = hud_side.flipped();
+        info!("HUD side: {}", hud_side.label());
+        return;
+    }
+
+    // Switch between the standard and high contrast colors
+    if input.just_pressed(KeyCode::KeyC) {
+        *color_scheme = color_scheme.toggled();
+        info!("Color scheme: {}", color_scheme.label());
+        return;
+    }
+
+    // Pick which puzzle or training position to play
+    if game_mode.uses_puzzle_board() && input.just_pressed(KeyCode::KeyN) {
+        puzzles.select_next();
+        info!("Puzzle: {}", puzzles.current().name);
+    }
+}
+
+/// A system that turns the master volume up with + and down with -, on the title screen and
+/// in the pause menu, and saves the new volume straight away.
+pub fn adjust_volume(input: Res<ButtonInput<KeyCode>>, mut volume: ResMut<Volume>) {
+    let up = input.any_just_pressed([KeyCode::Equal, KeyCode::NumpadAdd]);
+    let down = input.any_just_pressed([KeyCode::Minus, KeyCode::NumpadSubtract]);
+    let steps = up as i32 - down as i32;
+    if steps == 0 {
+        return;
+    }
+    let stepped = volume.stepped(steps);
+    if volume.set_if_neq(stepped) {
+        info!("Volume: {:.0}%", volume.0 * 100.0);
+        save_volume(*volume);
+    }
+}
+
 /// A system to handle user input for moving and rotating pieces.
-/// Bevy provides a `Res<ButtonInput<KeyCode>>` to check for key presses.
+/// Keys and gamepad buttons are read through their bindings as `GameAction`s.
+#[allow(clippy::too_many_arguments)]
 pub fn handle_input(
     mut commands: Commands,
-    input: Res<ButtonInput<KeyCode>>,
+    mut action_input: ActionInput,
     current_state: Res<State<GameState>>,
     mut next_state: ResMut<NextState<GameState>>,
-    mut tetromino_query: Query<(Entity, &mut GridPosition, Option<&RotationCenter>), With<Tetromino>>,
-    grid_query: Query<&GridPosition, Without<Tetromino>>,
-    grid_entities: Query<Entity, With<GridPosition>>,
+    mut tetromino_query: Query<
+        (Entity, &mut GridPosition, Option<&mut RotationCenter>),
+        With<Tetromino>,
+    >,
+    collision_grid: Res<CollisionGrid>,
+    mut held_repeat: HeldRepeat,
+    mut drop_score: DropScore,
+    input_priority: Res<InputPriority>,
+    mut game_over_reason: ResMut<GameOverReason>,
+    screen_transition: Res<ScreenTransition>,
+    mut play_counters: ResMut<PlayCounters>,
+    mut input_buffer: ResMut<InputBuffer>,
+    mut hold_piece: ResMut<HoldPiece>,
+    shape_query: Query<&Shape, With<Tetromino>>,
+    mut lock_delay: ResMut<LockDelay>,
 ) {
-    // Start the game from the title screen
-    if *current_state.get() == GameState::Title && input.just_pressed(KeyCode::Space) {
-        next_state.set(GameState::Spawning);
-        println!("Game started!");
+    // Read every discrete press of this frame up front, whatever the state, so presses
+    // from a previous state never leak into gameplay unless they are buffered below
+    let mut presses = action_input.read_presses();
+
+    // Nothing can be done while the screen is fading; the presses above are dropped
+    if screen_transition.is_running() {
         return;
     }
 
-    // Toggle between Playing and Paused states
-    if input.just_pressed(KeyCode::KeyP) {
-        if *current_state.get() == GameState::Playing {
-            next_state.set(GameState::Paused);
-            println!("Game Paused");
-        } else if *current_state.get() == GameState::Paused {
-            next_state.set(GameState::Playing);
-            println!("Game Resumed");
+    // Toggle between Playing and Paused states. The countdown can be paused too; no piece has
+    // spawned before it is over, so an empty board goes back to it. A pause pressed while the
+    // next piece was on its way takes effect as soon as it has spawned.
+    let buffered_pause =
+        *current_state.get() == GameState::Playing && std::mem::take(&mut input_buffer.pause);
+    if buffered_pause || presses.contains(&GameAction::Pause) {
+        match current_state.get() {
+            GameState::Playing | GameState::Countdown => {
+                next_state.set(GameState::Paused);
+                info!("Game Paused");
+            }
+            GameState::Paused if shape_query.is_empty() => {
+                next_state.set(GameState::Countdown);
+                info!("Game Resumed");
+            }
+            GameState::Paused => {
+                next_state.set(GameState::Playing);
+                info!("Game Resumed");
+            }
+            // There is no piece to freeze yet, so wait for it
+            GameState::Spawning => input_buffer.pause = true,
+            _ => {}
         }
         return;
     }
 
-    // Reset the game when 'R' is pressed
-    if input.just_pressed(KeyCode::KeyR)
+    // Reset the game when R (or the bound gamepad button) is pressed
+    if presses.contains(&GameAction::Reset)
         && (*current_state.get() == GameState::Playing
+            || *current_state.get() == GameState::Countdown
             || *current_state.get() == GameState::Paused
-            || *current_state.get() == GameState::GameOver)
+            || *current_state.get() == GameState::GameOver
+            || *current_state.get() == GameState::Victory)
     {
-        println!("Resetting Game");
-        for entity in grid_entities.iter() {
-            commands.entity(entity).despawn();
-        }
-        commands.insert_resource(Score(0));
-        commands.insert_resource(LinesCleared(0));
-        commands.insert_resource(Level(1));
+        // The board itself is torn down by `despawn_game_board` when the title screen is entered
+        info!("Resetting Game");
+        reset_game_resources(&mut commands);
         next_state.set(GameState::Title);
         return;
     }
 
-    // Only process movement input if the game is playing
-    if *current_state.get() == GameState::Playing {
-        // Collect the positions of all static blocks once for collision checks
-        let static_blocks: Vec<GridPosition> = grid_query.iter().cloned().collect();
-        
-        // Handle rotation first, as it can block movement
-        if input.just_pressed(KeyCode::ArrowUp) {
-            handle_rotation(&mut tetromino_query, &static_blocks);
+    // Presses made while the next piece is on its way are kept for it, and played first once it
+    // spawns. Outside of a game they are dropped, so none carry over into the next one.
+    match current_state.get() {
+        GameState::Spawning => {
+            for action in presses {
+                input_buffer.push(action);
+            }
+            return;
         }
-        
-        if input.just_pressed(KeyCode::ArrowLeft) {
-            handle_horizontal_movement(&mut tetromino_query, &static_blocks, -1);
+        GameState::Playing => {
+            presses.splice(0..0, input_buffer.actions.drain(..));
         }
-        
-        if input.just_pressed(KeyCode::ArrowRight) {
-            handle_horizontal_movement(&mut tetromino_query, &static_blocks, 1);
+        _ => input_buffer.clear(),
+    }
+
+    // Only process movement input if the game is playing
+    if *current_state.get() == GameState::Playing {
+        // Apply every press of the frame, so several taps landing in the same frame each move
+        // the piece instead of being collapsed into one. Rotations and moves are resolved in
+        // the configured priority, and presses of the same kind in the order they happened.
+        presses.sort_by_key(|action| input_priority.rank(*action));
+        for action in &presses {
+            play_counters.actions += 1;
+            match action {
+                GameAction::Rotate
+                | GameAction::RotateCounterclockwise
+                | GameAction::Rotate180 => {
+                    let quarter_turns = action.quarter_turns();
+                    if handle_rotation(&mut tetromino_query, &collision_grid, quarter_turns) {
+                        lock_delay.piece_moved();
+                    }
+                }
+                GameAction::MoveLeft => {
+                    held_repeat.restart_shift(-1);
+                    if handle_horizontal_movement(&mut tetromino_query, &collision_grid, -1) {
+                        lock_delay.piece_moved();
+                    }
+                }
+                GameAction::MoveRight => {
+                    held_repeat.restart_shift(1);
+                    if handle_horizontal_movement(&mut tetromino_query, &collision_grid, 1) {
+                        lock_delay.piece_moved();
+                    }
+                }
+                GameAction::SoftDrop => {
+                    held_repeat.restart_soft_drop();
+                    if handle_vertical_movement(&mut tetromino_query, &collision_grid, -1) {
+                        drop_score.soft_drop(1);
+                    }
+                }
+                // Hard drop logic
+                GameAction::HardDrop => {
+                    let distance = handle_hard_drop(
+                        &mut commands,
+                        &mut tetromino_query,
+                        &collision_grid,
+                        &mut next_state,
+                        &mut game_over_reason,
+                    );
+                    drop_score.hard_drop(distance);
+                    // The piece has locked, so nothing else this frame applies to it
+                    return;
+                }
+                GameAction::Hold => {
+                    if handle_hold(
+                        &mut commands,
+                        &tetromino_query,
+                        &shape_query,
+                        &mut hold_piece,
+                        &mut next_state,
+                    ) {
+                        // The piece is gone, so nothing else this frame applies to it
+                        return;
+                    }
+                }
+                GameAction::Pause | GameAction::Reset => {}
+            }
         }
-        
-        if input.just_pressed(KeyCode::ArrowDown) {
-            handle_vertical_movement(&mut tetromino_query, &static_blocks, -1);
+
+        // A held direction auto-shifts once the DAS delay has passed since its press
+        let (shift_direction, shift_steps) = held_repeat.shift_steps(
+            action_input.pressed(GameAction::MoveLeft),
+            action_input.pressed(GameAction::MoveRight),
+        );
+        for _ in 0..shift_steps {
+            if handle_horizontal_movement(&mut tetromino_query, &collision_grid, shift_direction) {
+                lock_delay.piece_moved();
+            }
         }
-        
-        // Hard drop logic for the Space key
-        if input.just_pressed(KeyCode::Space) {
-            handle_hard_drop(&mut commands, &mut tetromino_query, &static_blocks, &mut next_state);
+
+        // Held soft drop is applied after the taps, so a sideways tap during a fast soft drop
+        // still moves the piece one column at the height it was tapped
+        let soft_drop_steps =
+            held_repeat.soft_drop_steps(action_input.pressed(GameAction::SoftDrop));
+        for _ in 0..soft_drop_steps {
+            if handle_vertical_movement(&mut tetromino_query, &collision_grid, -1) {
+                drop_score.soft_drop(1);
+            }
         }
     }
 }
 
-/// Handles tetromino rotation
-fn handle_rotation(
-    tetromino_query: &mut Query<(Entity, &mut GridPosition, Option<&RotationCenter>), With<Tetromino>>,
-    static_blocks: &[GridPosition],
+/// Puts every per-game resource back to how a new game starts. Settings chosen on the title
+/// screen are left alone.
+fn reset_game_resources(commands: &mut Commands) {
+    commands.insert_resource(Score(0));
+    commands.insert_resource(LinesCleared(0));
+    commands.insert_resource(Level(1));
+    commands.insert_resource(AllClearStreak(0));
+    commands.insert_resource(Combo::default());
+    commands.insert_resource(BackToBack::default());
+    commands.insert_resource(ForcedSequence::default());
+    commands.insert_resource(NextQueue::default());
+    commands.insert_resource(HoldPiece::default());
+    commands.insert_resource(PuzzleProgress::default());
+    commands.insert_resource(GameOverReason::default());
+    commands.insert_resource(RewindHistory::default());
+    commands.insert_resource(RewindsLeft::default());
+    commands.insert_resource(PlayTime::default());
+    commands.insert_resource(PlayCounters::default());
+    commands.insert_resource(Efficiency::default());
+    commands.insert_resource(NewBests::default());
+    commands.insert_resource(StartCountdown::default());
+    commands.insert_resource(GarbageTimer(Timer::from_seconds(
+        SURVIVAL_GARBAGE_INTERVAL,
+        TimerMode::Repeating,
+    )));
+}
+
+/// A system that soft resets the game from the pause menu: the board and falling piece are
+/// cleared and a new game starts after the countdown, in the same mode with the same settings.
+/// The piece sequence and garbage start over from the game seed, so the new game deals the same
+/// pieces.
+/// Any imported or puzzle board is laid out again by the systems chained after this one.
+#[allow(clippy::too_many_arguments)]
+pub fn soft_reset(
+    mut commands: Commands,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut next_piece: ResMut<NextPiece>,
+    no_overhang_start: Res<NoOverhangStart>,
+    randomizer_kind: Res<RandomizerKind>,
+    game_seed: Res<GameSeed>,
+    repeat_limit: Res<RepeatLimit>,
+    mut piece_generator: ResMut<PieceGenerator>,
+    mut game_rng: ResMut<GameRng>,
+    starting_level: Res<StartingLevel>,
+    game_mode: Res<GameMode>,
+    block_query: Query<Entity, With<GridPosition>>,
 ) {
-    let mut can_rotate = true;
-    let mut new_positions = Vec::new();
+    info!("Soft resetting game");
+    for entity in block_query.iter() {
+        commands.entity(entity).try_despawn();
+    }
+    reset_game_resources(&mut commands);
+    commands.insert_resource(Level(starting_level.for_mode(*game_mode)));
+    *game_rng = GameRng::new(*game_seed);
+    next_piece.0 = first_shape(
+        &mut piece_generator,
+        *randomizer_kind,
+        *game_seed,
+        *repeat_limit,
+        *no_overhang_start,
+    );
+    next_state.set(GameState::Countdown);
+}
 
-    // Find the rotation center's current grid position
-    let rotation_center_pos = tetromino_query
-        .iter()
-        .find_map(|(_, pos, center)| {
-            if center.is_some() {
-                Some(*pos)
-            } else {
-                None
-            }
-        })
-        .unwrap_or(GridPosition { x: 0, y: 0 });
+/// System parameter with what held keys need each frame: the frame time and the auto-repeat
+/// settings and progress of soft drop and of sideways shifting.
+#[derive(SystemParam)]
+pub struct HeldRepeat<'w> {
+    time: Res<'w, Time>,
+    soft_drop_das: Res<'w, SoftDropDas>,
+    soft_drop_repeat: ResMut<'w, SoftDropRepeat>,
+    shift_das: Res<'w, ShiftDas>,
+    shift_repeat: ResMut<'w, ShiftRepeat>,
+}
 
-    for (entity, position, _) in tetromino_query.iter() {
-        // Calculate position relative to the rotation center
-        let relative_x = position.x - rotation_center_pos.x;
-        let relative_y = position.y - rotation_center_pos.y;
+impl HeldRepeat<'_> {
+    /// Restarts the auto-repeat after a fresh press of the soft drop key.
+    fn restart_soft_drop(&mut self) {
+        restart_soft_drop_repeat(&mut self.soft_drop_repeat, &self.soft_drop_das);
+    }
 
-        // Rotate 90 degrees clockwise: (x, y) -> (y, -x)
-        let rotated_x = relative_y;
-        let rotated_y = -relative_x;
+    /// Returns how many extra cells the piece should soft drop this frame.
+    fn soft_drop_steps(&mut self, pressed: bool) -> u32 {
+        soft_drop_repeat_steps(
+            &mut self.soft_drop_repeat,
+            &self.soft_drop_das,
+            self.time.delta_secs(),
+            pressed,
+        )
+    }
 
-        let new_pos = GridPosition {
-            x: rotated_x + rotation_center_pos.x,
-            y: rotated_y + rotation_center_pos.y,
+    /// Starts auto-shifting in a direction after a fresh press of left or right, dropping any
+    /// repeat in the other direction.
+    fn restart_shift(&mut self, direction: i32) {
+        *self.shift_repeat = ShiftRepeat {
+            direction,
+            held_for: 0.0,
+            next_step_at: self.shift_das.delay,
         };
+    }
+
+    /// Returns which way and how many extra columns the piece should shift this frame. The
+    /// repeat stops when its key is let go; only a fresh press starts it again.
+    fn shift_steps(&mut self, left_held: bool, right_held: bool) -> (i32, u32) {
+        let repeat = &mut *self.shift_repeat;
+        let held = match repeat.direction {
+            -1 => left_held,
+            1 => right_held,
+            _ => false,
+        };
+        if !held {
+            repeat.direction = 0;
+        }
+        if !held || !self.shift_das.enabled {
+            return (0, 0);
+        }
+
+        repeat.held_for += self.time.delta_secs();
+        let steps = repeat_steps(
+            &mut repeat.held_for,
+            &mut repeat.next_step_at,
+            self.shift_das.rate,
+            GRID_SIZE_X as u32,
+        );
+        (repeat.direction, steps)
+    }
+}
+
+/// System parameter awarding the points for dropping the piece by hand, scaled by the score
+/// multiplier like every other point.
+#[derive(SystemParam)]
+pub struct DropScore<'w> {
+    score: ResMut<'w, Score>,
+    score_multiplier: Res<'w, ScoreMultiplier>,
+}
+
+impl DropScore<'_> {
+    /// Awards the points for soft dropping the piece the given number of cells.
+    pub fn soft_drop(&mut self, cells: i32) {
+        self.award(cells, SOFT_DROP_POINTS_PER_CELL);
+    }
 
-        if check_collision(new_pos, static_blocks) {
-            can_rotate = false;
+    /// Awards the points for hard dropping the piece the given number of cells.
+    pub fn hard_drop(&mut self, cells: i32) {
+        self.award(cells, HARD_DROP_POINTS_PER_CELL);
+    }
+
+    fn award(&mut self, cells: i32, points_per_cell: u32) {
+        if cells > 0 {
+            self.score.0 += self.score_multiplier.apply(cells as u32 * points_per_cell);
+        }
+    }
+}
+
+/// Restarts the soft drop auto-repeat after a fresh press of the soft drop key.
+fn restart_soft_drop_repeat(repeat: &mut SoftDropRepeat, das: &SoftDropDas) {
+    repeat.held_for = 0.0;
+    repeat.next_step_at = das.delay;
+}
+
+/// Returns how many extra cells the piece should soft drop this frame while the key is held.
+/// The drop repeats every `rate` seconds once the key has been held for `delay` seconds.
+fn soft_drop_repeat_steps(
+    repeat: &mut SoftDropRepeat,
+    das: &SoftDropDas,
+    delta: f32,
+    pressed: bool,
+) -> u32 {
+    if !pressed || !das.enabled {
+        return 0;
+    }
+
+    repeat.held_for += delta;
+    // Never drop further than the board is tall in a single frame
+    repeat_steps(
+        &mut repeat.held_for,
+        &mut repeat.next_step_at,
+        das.rate,
+        (GRID_SIZE_Y + GRID_BUFFER_ROWS) as u32,
+    )
+}
+
+/// Counts the repeated steps due by the time a key has been held for, moving the time of the
+/// next step along by `rate` for each. At most `max_steps` are returned, with the rest skipped.
+fn repeat_steps(held_for: &mut f32, next_step_at: &mut f32, rate: f32, max_steps: u32) -> u32 {
+    let rate = rate.max(f32::EPSILON);
+    let mut steps = 0;
+    while *held_for >= *next_step_at {
+        steps += 1;
+        *next_step_at += rate;
+        if steps >= max_steps {
+            *next_step_at = *held_for + rate;
             break;
         }
-        new_positions.push((entity, new_pos));
     }
+    steps
+}
+
+/// Handles tetromino rotation around the piece's rotation center, by a number of quarter turns:
+/// 1 turns it clockwise, -1 counterclockwise and 2 halfway round. When the turned piece would
+/// overlap a wall or block, it is kicked to the first free spot of its SRS table.
+/// Returns whether the piece turned.
+pub fn handle_rotation(
+    tetromino_query: &mut Query<
+        (Entity, &mut GridPosition, Option<&mut RotationCenter>),
+        With<Tetromino>,
+    >,
+    collision_grid: &CollisionGrid,
+    quarter_turns: i32,
+) -> bool {
+    // Find the rotation center's current grid position and which way the piece faces. The O
+    // piece has no rotation center, as turning it would change nothing
+    let Some((rotation_center_pos, rotation_center)) = tetromino_query
+        .iter()
+        .find_map(|(_, pos, center)| center.map(|center| (*pos, *center)))
+    else {
+        return false;
+    };
+
+    // Turn every block around the pivot, which for the I piece sits between cells. A
+    // counterclockwise turn is three clockwise ones: (x, y) -> (-y, x)
+    let pivot = rotation_pivot(
+        rotation_center.shape,
+        rotation_center_pos,
+        rotation_center.state,
+    );
+    let new_positions: Vec<(Entity, GridPosition)> = tetromino_query
+        .iter()
+        .map(|(entity, position, _)| (entity, rotate_about(*position, pivot, quarter_turns)))
+        .collect();
 
-    if can_rotate {
-        for (entity, new_pos) in new_positions {
-            let mut position = tetromino_query.get_mut(entity).unwrap().1;
-            *position = new_pos;
+    // Turn in place if there is room, otherwise try each SRS wall kick in turn
+    let kicks = kick_offsets(
+        rotation_center.shape,
+        rotation_center.state,
+        rotation_center.state.turned(quarter_turns),
+    );
+    let Some((kick_x, kick_y)) = kicks.iter().copied().find(|(kick_x, kick_y)| {
+        new_positions.iter().all(|(_, new_pos)| {
+            let kicked = GridPosition {
+                x: new_pos.x + kick_x,
+                y: new_pos.y + kick_y,
+            };
+            !check_collision(kicked, collision_grid)
+        })
+    }) else {
+        return false;
+    };
+
+    for (entity, new_pos) in new_positions {
+        let (_, mut position, center) = tetromino_query.get_mut(entity).unwrap();
+        *position = GridPosition {
+            x: new_pos.x + kick_x,
+            y: new_pos.y + kick_y,
+        };
+        if let Some(mut center) = center {
+            center.state = center.state.turned(quarter_turns);
+            center.rotated_last = true;
         }
     }
+    true
 }
 
-/// Handles horizontal movement (left/right)
-fn handle_horizontal_movement(
-    tetromino_query: &mut Query<(Entity, &mut GridPosition, Option<&RotationCenter>), With<Tetromino>>,
-    static_blocks: &[GridPosition],
+/// Handles horizontal movement (left/right). Returns whether the piece moved.
+pub fn handle_horizontal_movement(
+    tetromino_query: &mut Query<
+        (Entity, &mut GridPosition, Option<&mut RotationCenter>),
+        With<Tetromino>,
+    >,
+    collision_grid: &CollisionGrid,
     direction: i32,
-) {
-    let mut can_move = true;
+) -> bool {
+    let mut can_move = !tetromino_query.is_empty();
     for (_entity, position, _) in tetromino_query.iter() {
         let new_pos = GridPosition {
             x: position.x + direction,
             y: position.y,
         };
-        if check_collision(new_pos, static_blocks) {
+        if check_collision(new_pos, collision_grid) {
             can_move = false;
             break;
         }
     }
     if can_move {
-        for (_entity, mut position, _) in tetromino_query.iter_mut() {
+        for (_entity, mut position, center) in tetromino_query.iter_mut() {
             position.x += direction;
+            if let Some(mut center) = center {
+                center.rotated_last = false;
+            }
         }
     }
+    can_move
 }
 
-/// Handles vertical movement (down)
+/// Handles vertical movement (down). Returns whether the piece moved.
 fn handle_vertical_movement(
-    tetromino_query: &mut Query<(Entity, &mut GridPosition, Option<&RotationCenter>), With<Tetromino>>,
-    static_blocks: &[GridPosition],
+    tetromino_query: &mut Query<
+        (Entity, &mut GridPosition, Option<&mut RotationCenter>),
+        With<Tetromino>,
+    >,
+    collision_grid: &CollisionGrid,
     direction: i32,
-) {
-    let mut can_move = true;
+) -> bool {
+    let mut can_move = !tetromino_query.is_empty();
     for (_entity, position, _) in tetromino_query.iter() {
         let new_pos = GridPosition {
             x: position.x,
             y: position.y + direction,
         };
-        if check_collision(new_pos, static_blocks) {
+        if check_collision(new_pos, collision_grid) {
             can_move = false;
             break;
         }
     }
     if can_move {
-        for (_entity, mut position, _) in tetromino_query.iter_mut() {
+        for (_entity, mut position, center) in tetromino_query.iter_mut() {
             position.y += direction;
+            if let Some(mut center) = center {
+                center.rotated_last = false;
+            }
         }
     }
+    can_move
 }
 
-/// Handles hard drop (space key)
-fn handle_hard_drop(
+/// A system that hard drops the piece once the player has gone `IdleDrop` seconds without any
+/// input, from the keys, a gamepad or the pointer. Grounded or not, the piece locks where it
+/// lands. The idle time starts over with every input and every new piece.
+#[allow(clippy::too_many_arguments)]
+pub fn idle_drop_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    idle_drop: Res<IdleDrop>,
+    play_counters: Res<PlayCounters>,
+    mut tetromino_query: Query<
+        (Entity, &mut GridPosition, Option<&mut RotationCenter>),
+        With<Tetromino>,
+    >,
+    collision_grid: Res<CollisionGrid>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut game_over_reason: ResMut<GameOverReason>,
+    // The idle time, and the action and piece counts it was last measured against
+    mut idle: Local<(f32, u32, u32)>,
+) {
+    let Some(limit) = idle_drop.0 else {
+        return;
+    };
+
+    let (idle_for, last_actions, last_pieces) = &mut *idle;
+    if *last_actions != play_counters.actions || *last_pieces != play_counters.pieces {
+        *last_actions = play_counters.actions;
+        *last_pieces = play_counters.pieces;
+        *idle_for = 0.0;
+    }
+
+    *idle_for += time.delta_secs();
+    if *idle_for >= limit {
+        *idle_for = 0.0;
+        info!("No input for {limit} seconds, dropping the piece");
+        handle_hard_drop(
+            &mut commands,
+            &mut tetromino_query,
+            &collision_grid,
+            &mut next_state,
+            &mut game_over_reason,
+        );
+    }
+}
+
+/// Puts the falling piece in the hold box and sends for the next one: the piece held before if
+/// there is one, otherwise the next piece. Returns false when the piece cannot be held, as it
+/// came out of the hold box or was already held.
+pub fn handle_hold(
     commands: &mut Commands,
-    tetromino_query: &mut Query<(Entity, &mut GridPosition, Option<&RotationCenter>), With<Tetromino>>,
-    static_blocks: &[GridPosition],
+    tetromino_query: &Query<
+        (Entity, &mut GridPosition, Option<&mut RotationCenter>),
+        With<Tetromino>,
+    >,
+    shape_query: &Query<&Shape, With<Tetromino>>,
+    hold_piece: &mut HoldPiece,
     next_state: &mut ResMut<NextState<GameState>>,
-) {
-    let mut can_move = true;
-    while can_move {
-        let mut temp_positions: Vec<GridPosition> = Vec::new();
-        for (_entity, position, _) in tetromino_query.iter() {
-            let new_pos = GridPosition {
-                x: position.x,
-                y: position.y - 1,
+) -> bool {
+    // Another input this frame may already have locked the piece
+    if !hold_piece.can_hold || transition_pending(next_state) {
+        return false;
+    }
+    let Some(shape) = shape_query.iter().next() else {
+        return false;
+    };
+
+    // The piece leaves the game at once; its blocks only stay on to shrink into the hold box
+    for (entity, _, _) in tetromino_query.iter() {
+        commands
+            .entity(entity)
+            .remove::<(Tetromino, GridPosition, Shape, RotationCenter)>()
+            .insert(HoldSwap::new(true));
+    }
+    hold_piece.pending = Some(*shape);
+    hold_piece.can_hold = false;
+    next_state.set(GameState::Spawning);
+    true
+}
+
+/// Handles hard drop (space key). Returns how many cells the piece fell.
+pub fn handle_hard_drop(
+    commands: &mut Commands,
+    tetromino_query: &mut Query<
+        (Entity, &mut GridPosition, Option<&mut RotationCenter>),
+        With<Tetromino>,
+    >,
+    collision_grid: &CollisionGrid,
+    next_state: &mut ResMut<NextState<GameState>>,
+    game_over_reason: &mut GameOverReason,
+) -> i32 {
+    // Another input this frame may already have locked the piece
+    if transition_pending(next_state) {
+        return 0;
+    }
+    let blocks: Vec<GridPosition> = tetromino_query
+        .iter()
+        .map(|(_, position, _)| *position)
+        .collect();
+    if blocks.is_empty() {
+        return 0;
+    }
+
+    let distance = drop_distance(&blocks, collision_grid);
+    for (_entity, mut position, center) in tetromino_query.iter_mut() {
+        position.y -= distance;
+        if let Some(mut center) = center
+            && distance > 0
+        {
+            center.rotated_last = false;
+        }
+    }
+    for (entity, _, _) in tetromino_query.iter() {
+        commands
+            .entity(entity)
+            .remove::<Tetromino>()
+            .insert(JustLocked);
+    }
+    play_sound(commands, SoundEffect::HardDrop);
+    if is_lock_out(tetromino_query.iter().map(|(_, position, _)| position)) {
+        info!("Lock out!");
+        *game_over_reason = GameOverReason::LockOut;
+        next_state.set(GameState::GameOver);
+    } else {
+        next_state.set(GameState::Spawning);
+    }
+    distance
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+
+    use bevy::input::{
+        ButtonState,
+        keyboard::{Key, KeyboardInput, NativeKey},
+    };
+    use rand::Rng;
+    use std::time::Duration;
+
+    use crate::{
+        bindings::{GamepadBindings, KeyBindings},
+        components::RotationState,
+        game_logic::gravity_system,
+        resources::{FallTimer, GravityEnabled},
+        tetromino::{get_rotation_center_index, spawn_blocks},
+    };
+
+    /// The falling piece, as the input handlers take it.
+    type PieceQuery<'w, 's> = Query<
+        'w,
+        's,
+        (
+            Entity,
+            &'static mut GridPosition,
+            Option<&'static mut RotationCenter>,
+        ),
+        With<Tetromino>,
+    >;
+
+    /// Builds a world with an empty board and a piece on it, moved from where it spawns by
+    /// `offset`.
+    fn world_with_piece(shape: Shape, offset: IVec2) -> World {
+        let mut world = World::new();
+        world.init_resource::<CollisionGrid>();
+        for (i, block) in spawn_blocks(shape).into_iter().enumerate() {
+            let position = GridPosition {
+                x: block.x + offset.x,
+                y: block.y + offset.y,
             };
-            if check_collision(new_pos, static_blocks) {
-                can_move = false;
-                break;
+            let mut entity = world.spawn((position, shape, Tetromino));
+            if get_rotation_center_index(shape) == Some(i) {
+                entity.insert(RotationCenter {
+                    shape,
+                    state: RotationState::Spawn,
+                    rotated_last: false,
+                });
             }
-            temp_positions.push(new_pos);
         }
+        world
+    }
+
+    /// Returns the cells of the falling piece, sorted so two pieces can be compared.
+    fn piece_cells(world: &mut World) -> Vec<(i32, i32)> {
+        let mut cells: Vec<(i32, i32)> = world
+            .query_filtered::<&GridPosition, With<Tetromino>>()
+            .iter(world)
+            .map(|position| (position.x, position.y))
+            .collect();
+        cells.sort_unstable();
+        cells
+    }
+
+    fn rotate(world: &mut World, quarter_turns: i32) -> bool {
+        world
+            .run_system_once(
+                move |mut tetromino_query: PieceQuery, collision_grid: Res<CollisionGrid>| {
+                    handle_rotation(&mut tetromino_query, &collision_grid, quarter_turns)
+                },
+            )
+            .unwrap()
+    }
+
+    fn shift(world: &mut World, direction: i32) -> bool {
+        world
+            .run_system_once(
+                move |mut tetromino_query: PieceQuery, collision_grid: Res<CollisionGrid>| {
+                    handle_horizontal_movement(&mut tetromino_query, &collision_grid, direction)
+                },
+            )
+            .unwrap()
+    }
+
+    /// Adds everything `handle_input` reads, in the given state, with the default bindings and
+    /// settings and nothing pressed.
+    fn insert_input_resources(world: &mut World, state: GameState) {
+        world.insert_resource(State::new(state));
+        world.init_resource::<NextState<GameState>>();
+        world.init_resource::<ButtonInput<KeyCode>>();
+        world.init_resource::<Events<KeyboardInput>>();
+        world.init_resource::<KeyBindings>();
+        world.init_resource::<GamepadBindings>();
+        world.init_resource::<Time>();
+        world.init_resource::<SoftDropDas>();
+        world.init_resource::<SoftDropRepeat>();
+        world.init_resource::<ShiftDas>();
+        world.init_resource::<ShiftRepeat>();
+        world.insert_resource(Score(0));
+        world.init_resource::<ScoreMultiplier>();
+        world.init_resource::<InputPriority>();
+        world.init_resource::<GameOverReason>();
+        world.init_resource::<ScreenTransition>();
+        world.init_resource::<PlayCounters>();
+        world.init_resource::<InputBuffer>();
+        world.init_resource::<HoldPiece>();
+        world.init_resource::<LockDelay>();
+    }
+
+    /// Runs `handle_input` for one frame of `delta` seconds, in which the given keys go down in
+    /// order. Keys pressed in earlier frames stay held until released.
+    fn press_keys(world: &mut World, keys: &[KeyCode], delta: f32) {
+        world.resource_mut::<ButtonInput<KeyCode>>().clear();
+        world.resource_mut::<Events<KeyboardInput>>().clear();
+        for &key_code in keys {
+            world.resource_mut::<ButtonInput<KeyCode>>().press(key_code);
+            world.send_event(KeyboardInput {
+                key_code,
+                logical_key: Key::Unidentified(NativeKey::Unidentified),
+                state: ButtonState::Pressed,
+                text: None,
+                repeat: false,
+                window: Entity::PLACEHOLDER,
+            });
+        }
+        let mut time = *world.resource::<Time>();
+        time.advance_by(Duration::from_secs_f32(delta));
+        world.insert_resource(time);
+        world.run_system_once(handle_input).unwrap();
+    }
+
+    #[test]
+    fn j_piece_against_the_left_wall_kicks_right() {
+        // Stand the J up and slide it flush against the left wall
+        let mut world = world_with_piece(Shape::J, IVec2::new(0, -10));
+        assert!(rotate(&mut world, 1));
+        while shift(&mut world, -1) {}
+        assert_eq!(piece_cells(&mut world), [(0, 9), (0, 10), (0, 11), (1, 11)]);
 
-        if can_move {
-            for (_entity, mut position, _) in tetromino_query.iter_mut() {
-                position.y -= 1;
+        // Laid flat in place it would poke into the wall, so it kicks one column right
+        assert!(rotate(&mut world, -1));
+        assert_eq!(
+            piece_cells(&mut world),
+            [(0, 10), (0, 11), (1, 10), (2, 10)]
+        );
+    }
+
+    #[test]
+    fn turning_a_t_piece_clockwise_then_back_restores_it() {
+        let mut world = world_with_piece(Shape::T, IVec2::new(0, -10));
+        let start = piece_cells(&mut world);
+
+        assert!(rotate(&mut world, 1));
+        assert_ne!(piece_cells(&mut world), start);
+        assert!(rotate(&mut world, -1));
+        assert_eq!(piece_cells(&mut world), start);
+    }
+
+    #[test]
+    fn four_turns_bring_every_shape_back_to_its_cells() {
+        for shape in Shape::ALL {
+            for quarter_turns in [1, -1] {
+                let mut world = world_with_piece(shape, IVec2::new(0, -10));
+                let start = piece_cells(&mut world);
+                for _ in 0..4 {
+                    rotate(&mut world, quarter_turns);
+                }
+                assert_eq!(
+                    piece_cells(&mut world),
+                    start,
+                    "{shape:?} turned {quarter_turns}"
+                );
             }
-        } else {
-            for (entity, _, _) in tetromino_query.iter() {
-                commands.entity(entity).remove::<Tetromino>();
+        }
+    }
+
+    #[test]
+    fn hard_drop_from_spawn_scores_two_points_a_cell() {
+        let mut world = world_with_piece(Shape::T, IVec2::ZERO);
+        world.insert_resource(Score(0));
+        world.insert_resource(ScoreMultiplier(1.0));
+        world.init_resource::<NextState<GameState>>();
+        world.init_resource::<GameOverReason>();
+
+        let distance = world
+            .run_system_once(
+                |mut commands: Commands,
+                 mut tetromino_query: PieceQuery,
+                 collision_grid: Res<CollisionGrid>,
+                 mut next_state: ResMut<NextState<GameState>>,
+                 mut game_over_reason: ResMut<GameOverReason>,
+                 mut drop_score: DropScore| {
+                    let distance = handle_hard_drop(
+                        &mut commands,
+                        &mut tetromino_query,
+                        &collision_grid,
+                        &mut next_state,
+                        &mut game_over_reason,
+                    );
+                    drop_score.hard_drop(distance);
+                    distance
+                },
+            )
+            .unwrap();
+
+        // The flat side of a T spawns in the lowest buffer row and falls to the floor
+        assert_eq!(distance, GRID_SIZE_Y);
+        assert_eq!(world.resource::<Score>().0, 2 * GRID_SIZE_Y as u32);
+    }
+
+    #[test]
+    fn soft_drop_repeats_after_the_delay_at_the_rate() {
+        let das = SoftDropDas {
+            enabled: true,
+            delay: 0.25,
+            rate: 0.125,
+        };
+        let mut repeat = SoftDropRepeat::default();
+        restart_soft_drop_repeat(&mut repeat, &das);
+
+        // Nothing until the key has been held for the delay, then a step every `rate` seconds
+        let steps: Vec<u32> = (0..5)
+            .map(|_| soft_drop_repeat_steps(&mut repeat, &das, 0.125, true))
+            .collect();
+        assert_eq!(steps, [0, 1, 1, 1, 1]);
+        // A long frame catches up on every step it covered
+        assert_eq!(soft_drop_repeat_steps(&mut repeat, &das, 0.5, true), 4);
+
+        // Letting go stops the repeat, and pressing again waits out the delay once more
+        assert_eq!(soft_drop_repeat_steps(&mut repeat, &das, 0.125, false), 0);
+        restart_soft_drop_repeat(&mut repeat, &das);
+        assert_eq!(soft_drop_repeat_steps(&mut repeat, &das, 0.125, true), 0);
+        assert_eq!(soft_drop_repeat_steps(&mut repeat, &das, 0.125, true), 1);
+
+        let disabled = SoftDropDas {
+            enabled: false,
+            ..das
+        };
+        assert_eq!(soft_drop_repeat_steps(&mut repeat, &disabled, 1.0, true), 0);
+    }
+
+    #[test]
+    fn every_press_in_a_frame_is_applied() {
+        let mut world = world_with_piece(Shape::O, IVec2::ZERO);
+        insert_input_resources(&mut world, GameState::Playing);
+        let start = piece_cells(&mut world);
+
+        // Three taps landing in one frame move the piece three columns
+        let left = KeyCode::ArrowLeft;
+        press_keys(&mut world, &[left, left, left], 0.0);
+        let moved: Vec<(i32, i32)> = start.iter().map(|&(x, y)| (x - 3, y)).collect();
+        assert_eq!(piece_cells(&mut world), moved);
+        assert_eq!(world.resource::<PlayCounters>().actions, 3);
+    }
+
+    #[test]
+    fn a_sideways_tap_during_a_fast_soft_drop_moves_one_column() {
+        let mut world = world_with_piece(Shape::O, IVec2::ZERO);
+        insert_input_resources(&mut world, GameState::Playing);
+        world.insert_resource(SoftDropDas {
+            enabled: true,
+            delay: 0.05,
+            rate: 0.01,
+        });
+
+        // Hold soft drop until it repeats every frame
+        press_keys(&mut world, &[KeyCode::ArrowDown], 0.0);
+        press_keys(&mut world, &[], 0.1);
+        let before = piece_cells(&mut world);
+        assert!(before[0].1 < GRID_SIZE_Y - 1);
+
+        press_keys(&mut world, &[KeyCode::ArrowLeft], 0.05);
+        let after = piece_cells(&mut world);
+        for (&(x_before, y_before), &(x_after, y_after)) in before.iter().zip(&after) {
+            assert_eq!(x_after, x_before - 1);
+            assert!(y_after < y_before - 1);
+        }
+    }
+
+    #[test]
+    fn rotate_and_move_in_one_frame_follow_the_input_priority() {
+        // A T piece flat against the left wall, with Up and Left pressed in the same frame
+        let offset = IVec2::new(-(GRID_SIZE_X / 2 - 2), -10);
+        let mut outcomes = Vec::new();
+        for input_priority in [InputPriority::MoveFirst, InputPriority::RotateFirst] {
+            let mut world = world_with_piece(Shape::T, offset);
+            insert_input_resources(&mut world, GameState::Playing);
+            world.insert_resource(input_priority);
+            press_keys(&mut world, &[KeyCode::ArrowUp, KeyCode::ArrowLeft], 0.0);
+
+            // The second press is checked against where the first one left the piece
+            let mut expected = world_with_piece(Shape::T, offset);
+            if input_priority == InputPriority::MoveFirst {
+                assert!(!shift(&mut expected, -1));
+                assert!(rotate(&mut expected, 1));
+            } else {
+                assert!(rotate(&mut expected, 1));
+                assert!(shift(&mut expected, -1));
             }
-            next_state.set(GameState::Spawning);
+            let cells = piece_cells(&mut world);
+            assert_eq!(cells, piece_cells(&mut expected));
+            outcomes.push(cells);
         }
+
+        // Moving first wastes the move on the wall; turning first frees the piece to step left
+        assert_eq!(outcomes[0].iter().map(|cell| cell.0).min(), Some(1));
+        assert_eq!(outcomes[1].iter().map(|cell| cell.0).min(), Some(0));
+    }
+
+    #[test]
+    fn pausing_while_the_next_piece_spawns_pauses_once_it_is_in() {
+        let mut world = world_with_piece(Shape::O, IVec2::ZERO);
+        insert_input_resources(&mut world, GameState::Spawning);
+        // Even with moves not buffered at all
+        world.insert_resource(InputBuffer::with_capacity(0));
+
+        press_keys(&mut world, &[KeyCode::KeyP], 0.0);
+        assert!(!transition_pending(
+            world.resource::<NextState<GameState>>()
+        ));
+        assert!(world.resource::<InputBuffer>().pause);
+
+        world.insert_resource(State::new(GameState::Playing));
+        press_keys(&mut world, &[], 0.0);
+        assert!(matches!(
+            world.resource::<NextState<GameState>>(),
+            NextState::Pending(GameState::Paused)
+        ));
+        assert!(!world.resource::<InputBuffer>().pause);
+    }
+
+    #[test]
+    fn a_hard_drop_and_a_gravity_landing_in_one_frame_lock_once() {
+        // An O resting on the floor, due both to fall and to lock by the end of the frame
+        let mut world = world_with_piece(Shape::O, IVec2::ZERO);
+        let spawn_row = piece_cells(&mut world)[0].1;
+        let mut world = world_with_piece(Shape::O, IVec2::new(0, -spawn_row));
+        let resting = piece_cells(&mut world);
+        insert_input_resources(&mut world, GameState::Playing);
+        world.insert_resource(FallTimer(Timer::from_seconds(0.5, TimerMode::Repeating)));
+        world.insert_resource(GravityEnabled(true));
+
+        // The hard drop goes first, as in the game, and gravity follows in the same frame before
+        // the lock has been applied
+        world
+            .resource_mut::<ButtonInput<KeyCode>>()
+            .press(KeyCode::Space);
+        world.send_event(KeyboardInput {
+            key_code: KeyCode::Space,
+            logical_key: Key::Unidentified(NativeKey::Unidentified),
+            state: ButtonState::Pressed,
+            text: None,
+            repeat: false,
+            window: Entity::PLACEHOLDER,
+        });
+        let mut time = *world.resource::<Time>();
+        time.advance_by(Duration::from_secs(1));
+        world.insert_resource(time);
+        let mut frame = Schedule::default();
+        frame.add_systems((handle_input, gravity_system).chain_ignore_deferred());
+        frame.run(&mut world);
+
+        assert!(matches!(
+            world.resource::<NextState<GameState>>(),
+            NextState::Pending(GameState::Spawning)
+        ));
+        let mut locked = world
+            .query_filtered::<&GridPosition, With<JustLocked>>()
+            .iter(&world)
+            .map(|position| (position.x, position.y))
+            .collect::<Vec<_>>();
+        locked.sort_unstable();
+        assert_eq!(locked, resting);
+        assert!(piece_cells(&mut world).is_empty());
+        // Gravity stood aside: neither the fall nor the lock delay ran on
+        assert_eq!(world.resource::<FallTimer>().0.elapsed(), Duration::ZERO);
+        assert_eq!(
+            world.resource::<LockDelay>().timer.elapsed(),
+            Duration::ZERO
+        );
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn only_the_latest_presses_during_spawning_are_applied() {
+        let mut world = world_with_piece(Shape::T, IVec2::new(0, -10));
+        insert_input_resources(&mut world, GameState::Spawning);
+        world.insert_resource(InputBuffer::with_capacity(2));
+
+        // Three quick presses while the piece is on its way
+        for key in [KeyCode::ArrowRight, KeyCode::ArrowLeft, KeyCode::ArrowUp] {
+            press_keys(&mut world, &[key], 0.0);
+        }
+        assert_eq!(
+            world.resource::<InputBuffer>().actions,
+            [GameAction::MoveLeft, GameAction::Rotate]
+        );
+
+        // Once it is in, the two most recent are played in order
+        world.insert_resource(State::new(GameState::Playing));
+        press_keys(&mut world, &[], 0.0);
+        let mut expected = world_with_piece(Shape::T, IVec2::new(0, -10));
+        assert!(shift(&mut expected, -1));
+        assert!(rotate(&mut expected, 1));
+        assert_eq!(piece_cells(&mut world), piece_cells(&mut expected));
+        assert!(world.resource::<InputBuffer>().actions.is_empty());
+    }
+
+    #[test]
+    fn soft_reset_starts_a_clean_game_in_the_same_mode() {
+        let game_seed = GameSeed(0x691);
+        let randomizer_kind = RandomizerKind::FourteenBag;
+        let mut world = world_with_piece(Shape::T, IVec2::ZERO);
+        world.init_resource::<NextState<GameState>>();
+        world.insert_resource(NoOverhangStart(true));
+        world.insert_resource(randomizer_kind);
+        world.insert_resource(game_seed);
+        world.init_resource::<RepeatLimit>();
+        world.insert_resource(StartingLevel(7));
+        world.insert_resource(GameMode::Survival);
+
+        // Partway through a game: a stack, points, and some of the sequence and garbage dealt
+        for x in 0..5 {
+            world.spawn(GridPosition { x, y: 0 });
+        }
+        world.insert_resource(Score(1200));
+        world.insert_resource(Level(9));
+        let mut piece_generator =
+            PieceGenerator::new(randomizer_kind, game_seed, RepeatLimit::default());
+        for _ in 0..10 {
+            piece_generator.next_shape();
+        }
+        world.insert_resource(piece_generator);
+        world.insert_resource(NextPiece(Shape::I));
+        let mut game_rng = GameRng::new(game_seed);
+        game_rng.0.random::<u64>();
+        world.insert_resource(game_rng);
+
+        world.run_system_once(soft_reset).unwrap();
+
+        assert_eq!(world.query::<&GridPosition>().iter(&world).count(), 0);
+        assert_eq!(world.resource::<Score>().0, 0);
+        assert_eq!(world.resource::<Level>().0, 7);
+        assert_eq!(*world.resource::<GameMode>(), GameMode::Survival);
+        assert!(matches!(
+            world.resource::<NextState<GameState>>(),
+            NextState::Pending(GameState::Countdown)
+        ));
+
+        // The pieces and garbage start over from the seed, in a fresh bag
+        let mut fresh = PieceGenerator::default();
+        let first = first_shape(
+            &mut fresh,
+            randomizer_kind,
+            game_seed,
+            RepeatLimit::default(),
+            NoOverhangStart(true),
+        );
+        assert_eq!(world.resource::<NextPiece>().0, first);
+        let mut piece_generator = world.resource_mut::<PieceGenerator>();
+        for _ in 0..28 {
+            assert_eq!(piece_generator.next_shape(), fresh.next_shape());
+        }
+        assert_eq!(
+            world.resource_mut::<GameRng>().0.random::<u64>(),
+            GameRng::new(game_seed).0.random::<u64>()
+        );
+    }
+
+    #[test]
+    fn locking_entirely_above_the_field_is_a_lock_out() {
+        // A stack with a well down the first column, up to the top of the field or one row short
+        for (stack_height, lock_out) in [(GRID_SIZE_Y, true), (GRID_SIZE_Y - 1, false)] {
+            let mut world = world_with_piece(Shape::O, IVec2::ZERO);
+            insert_input_resources(&mut world, GameState::Playing);
+            let stack = (1..GRID_SIZE_X)
+                .flat_map(|x| (0..stack_height).map(move |y| GridPosition { x, y }));
+            world.insert_resource(CollisionGrid::from_blocks(
+                stack.map(|position| (Entity::PLACEHOLDER, position)),
+            ));
+
+            press_keys(&mut world, &[KeyCode::Space], 0.0);
+            let next_state = world.resource::<NextState<GameState>>();
+            if lock_out {
+                assert!(matches!(
+                    next_state,
+                    NextState::Pending(GameState::GameOver)
+                ));
+                assert_eq!(*world.resource::<GameOverReason>(), GameOverReason::LockOut);
+            } else {
+                // Partly inside the field it locks as usual, and the top out check takes over
+                assert!(matches!(
+                    next_state,
+                    NextState::Pending(GameState::Spawning)
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn going_idle_drops_the_piece_after_the_timeout() {
+        let mut world = world_with_piece(Shape::O, IVec2::ZERO);
+        world.init_resource::<Time>();
+        world.insert_resource(IdleDrop(Some(2.0)));
+        world.init_resource::<PlayCounters>();
+        world.init_resource::<NextState<GameState>>();
+        world.init_resource::<GameOverReason>();
+        let idle_drop = world.register_system(idle_drop_system);
+        let spawned = piece_cells(&mut world);
+        let idle_for = |world: &mut World, seconds: f32| {
+            for _ in 0..(seconds / 0.5) as u32 {
+                let mut time = *world.resource::<Time>();
+                time.advance_by(Duration::from_millis(500));
+                world.insert_resource(time);
+                world.run_system(idle_drop).unwrap();
+            }
+        };
+
+        // An input just short of the timeout starts the idle time over
+        idle_for(&mut world, 1.5);
+        world.resource_mut::<PlayCounters>().actions += 1;
+        idle_for(&mut world, 1.5);
+        assert_eq!(piece_cells(&mut world), spawned);
+        assert!(!transition_pending(
+            world.resource::<NextState<GameState>>()
+        ));
+
+        // Left alone for the full timeout, the piece drops to the floor and locks
+        idle_for(&mut world, 0.5);
+        assert!(piece_cells(&mut world).is_empty());
+        let locked = world
+            .query_filtered::<&GridPosition, With<JustLocked>>()
+            .iter(&world)
+            .map(|position| position.y)
+            .collect::<Vec<_>>();
+        assert_eq!(locked.len(), 4);
+        assert_eq!(locked.iter().min(), Some(&0));
+        assert!(matches!(
+            world.resource::<NextState<GameState>>(),
+            NextState::Pending(GameState::Spawning)
+        ));
+    }
+
+    #[test]
+    fn pressing_x_twice_on_the_title_wipes_the_personal_bests() {
+        let mut world = World::new();
+        world.init_resource::<ButtonInput<KeyCode>>();
+        world.init_resource::<NextState<GameState>>();
+        world.insert_resource(GameMode::Marathon);
+        world.init_resource::<RuleProfile>();
+        world.init_resource::<RandomizerKind>();
+        world.insert_resource(GameSeed(0x732));
+        world.init_resource::<HudSide>();
+        world.init_resource::<ColorScheme>();
+        world.init_resource::<Puzzles>();
+        world.init_resource::<StatsResetPending>();
+        world.insert_resource(StartingLevel(1));
+        world.insert_resource(Level(1));
+        let mut lifetime_stats = LifetimeStats::default();
+        lifetime_stats.record(GameMode::Marathon, 4000, 25, 3);
+        world.insert_resource(lifetime_stats);
+        let press = |world: &mut World, key: KeyCode| {
+            let mut input = world.resource_mut::<ButtonInput<KeyCode>>();
+            input.reset_all();
+            input.press(key);
+            world.run_system_once(handle_title_input).unwrap();
+        };
+
+        // Any other key after the first X keeps the bests
+        press(&mut world, KeyCode::KeyX);
+        assert!(world.resource::<StatsResetPending>().0);
+        press(&mut world, KeyCode::KeyQ);
+        assert!(!world.resource::<StatsResetPending>().0);
+        press(&mut world, KeyCode::KeyX);
+        let bests = world.resource::<LifetimeStats>().bests(GameMode::Marathon);
+        assert_eq!(bests.best_score, 4000);
+
+        // The second X confirms
+        press(&mut world, KeyCode::KeyX);
+        assert!(!world.resource::<StatsResetPending>().0);
+        let bests = world.resource::<LifetimeStats>().bests(GameMode::Marathon);
+        assert_eq!(
+            (
+                bests.games_played,
+                bests.best_score,
+                bests.best_lines,
+                bests.best_level
+            ),
+            (0, 0, 0, 0)
+        );
+    }
+}
@@ -1,41 +1,292 @@
-use bevy::prelude::*;
+use bevy::{
+    asset::RenderAssetUsages,
+    image::{CompressedImageFormats, ImageSampler, ImageType},
+    input::common_conditions::input_just_pressed,
+    log::LogPlugin,
+    prelude::*,
+};
 use bevy_embedded_assets::EmbeddedAssetPlugin;
-use rand::seq::IndexedRandom;
-
 // Module declarations
+mod atlas;
+mod bindings;
+mod board;
 mod components;
+mod confetti;
 mod constants;
+mod debug;
 mod game_logic;
 mod game_state;
 mod input;
+mod placement;
+mod pointer;
+mod puzzle;
+mod randomizer;
 mod resources;
+mod rewind;
 mod setup;
+mod sound;
+mod spectator;
+mod srs;
 mod tetromino;
 mod ui;
 
 // Re-export commonly used items
-use components::Shape;
+use constants::SURVIVAL_GARBAGE_INTERVAL;
 use game_state::GameState;
-use resources::{FallTimer, Level, LinesCleared, NextPiece, Score};
+use resources::{
+    AllClearStreak, BackToBack, BackdropImage, BoardMetrics, ClassicScore, CollapseDirection,
+    ColorScheme, Combo, Efficiency, EntryDelaySeconds, EntryDelayTimer, FallTimer, ForcedSequence,
+    GameMode, GameOverReason, GarbageTimer, GhostPieceEnabled, GravityEnabled, GridPulseEnabled,
+    HoldAnimation, HoldPiece, HudSide, IdleDrop, ImportedBoard, InputBuffer, InputPriority,
+    KeepMusic, LandingHighlight, LastLock, Letterbox, Level, LifetimeStats, LinesCleared,
+    LockDelay, LockTicks, NewBests, NextPiece, NextQueue, NoOverhangStart, PlayCounters, PlayTime,
+    PointerInput, PreviewLayout, PreviewScale, RuleProfile, Score, ScoreMultiplier,
+    ScreenTransition, ShiftDas, ShiftRepeat, ShowEfficiency, SoftDropDas, SoftDropRepeat,
+    StartCountdown, StartingLevel, StatsResetPending, TimedLevels, Volume,
+};
+
+/// Returns the value following `flag` on the command line, if any.
+fn arg_value(flag: &str) -> Option<String> {
+    let mut args = std::env::args();
+    args.find(|arg| arg == flag)?;
+    args.next()
+}
+
+/// Returns whether `flag` was passed on the command line.
+fn has_flag(flag: &str) -> bool {
+    std::env::args().any(|arg| arg == flag)
+}
+
+/// Returns the log settings. Passing `--quiet` turns off all log output, game messages and
+/// warnings about bad settings included.
+fn log_plugin() -> LogPlugin {
+    if has_flag("--quiet") {
+        LogPlugin {
+            filter: "off".to_string(),
+            level: bevy::log::Level::ERROR,
+            ..default()
+        }
+    } else {
+        LogPlugin::default()
+    }
+}
+
+/// Reads the score multiplier given with `--score-multiplier <x>`, falling back to 1.0.
+fn load_score_multiplier() -> ScoreMultiplier {
+    let Some(value) = arg_value("--score-multiplier") else {
+        return ScoreMultiplier::default();
+    };
+    match value.parse::<f32>() {
+        Ok(multiplier) if multiplier > 0.0 && multiplier.is_finite() => ScoreMultiplier(multiplier),
+        _ => {
+            warn!("Ignoring invalid score multiplier {value}, it must be a positive number");
+            ScoreMultiplier::default()
+        }
+    }
+}
+
+/// Reads the seed given with `--seed <hex>`, as shown on the title screen, so a shared game can
+/// be played again. Without it a random seed is rolled.
+fn load_game_seed() -> randomizer::GameSeed {
+    let Some(value) = arg_value("--seed") else {
+        return randomizer::GameSeed::default();
+    };
+    randomizer::GameSeed::from_hex(&value).unwrap_or_else(|| {
+        warn!("Ignoring invalid seed {value}, it must be up to eight hex digits");
+        randomizer::GameSeed::default()
+    })
+}
+
+/// Reads how many times in a row the uniform randomizer may deal a shape from
+/// `--max-repeats <n>`. Without it repeats are unlimited.
+fn load_repeat_limit() -> randomizer::RepeatLimit {
+    let Some(value) = arg_value("--max-repeats") else {
+        return randomizer::RepeatLimit::default();
+    };
+    match value.parse::<u32>() {
+        Ok(limit) if limit > 0 => randomizer::RepeatLimit(Some(limit)),
+        _ => {
+            warn!("Ignoring invalid repeat limit {value}, it must be a whole number above 0");
+            randomizer::RepeatLimit::default()
+        }
+    }
+}
+
+/// Reads how many presses are kept for the next piece from `--input-buffer <n>`, falling back
+/// to the default size.
+fn load_input_buffer() -> InputBuffer {
+    let Some(value) = arg_value("--input-buffer") else {
+        return InputBuffer::default();
+    };
+    match value.parse::<usize>() {
+        Ok(capacity) => InputBuffer::with_capacity(capacity),
+        _ => {
+            warn!("Ignoring invalid input buffer size {value}, it must be a whole number");
+            InputBuffer::default()
+        }
+    }
+}
+
+/// Reads the seconds of play per level given with `--level-seconds <n>`. Left off when the flag
+/// is missing.
+fn load_timed_levels() -> TimedLevels {
+    let Some(value) = arg_value("--level-seconds") else {
+        return TimedLevels::default();
+    };
+    match value.parse::<f32>() {
+        Ok(seconds) if seconds > 0.0 && seconds.is_finite() => TimedLevels(Some(seconds)),
+        _ => {
+            warn!("Ignoring invalid level time {value}, it must be a positive number");
+            TimedLevels::default()
+        }
+    }
+}
+
+/// Reads the idle time given with `--idle-drop <seconds>`, after which the piece is dropped
+/// for a player who has stopped pressing anything. Left off when the flag is missing.
+fn load_idle_drop() -> IdleDrop {
+    let Some(value) = arg_value("--idle-drop") else {
+        return IdleDrop::default();
+    };
+    match value.parse::<f32>() {
+        Ok(seconds) if seconds > 0.0 && seconds.is_finite() => IdleDrop(Some(seconds)),
+        _ => {
+            warn!("Ignoring invalid idle drop time {value}, it must be a positive number");
+            IdleDrop::default()
+        }
+    }
+}
+
+/// Reads the wait between a piece locking and the next one appearing given with
+/// `--entry-delay <seconds>`. Without it the next piece comes in straight away.
+fn load_entry_delay() -> EntryDelaySeconds {
+    let Some(value) = arg_value("--entry-delay") else {
+        return EntryDelaySeconds::default();
+    };
+    match value.parse::<f32>() {
+        Ok(seconds) if seconds >= 0.0 && seconds.is_finite() => EntryDelaySeconds(seconds),
+        _ => {
+            warn!("Ignoring invalid entry delay {value}, it must be 0 or a positive number");
+            EntryDelaySeconds::default()
+        }
+    }
+}
+
+/// Reads the preview block scale given with `--preview-scale <x>`, falling back to 1.0.
+/// Scales above 1.0 are refused, as the I piece would no longer fit the preview box.
+fn load_preview_scale() -> PreviewScale {
+    let Some(value) = arg_value("--preview-scale") else {
+        return PreviewScale::default();
+    };
+    match value.parse::<f32>() {
+        Ok(scale) if scale > 0.0 && scale <= 1.0 => PreviewScale(scale),
+        _ => {
+            warn!("Ignoring invalid preview scale {value}, it must be above 0 and at most 1");
+            PreviewScale::default()
+        }
+    }
+}
+
+/// Loads the board shown in the spectator view from `--spectate`, which takes either the compact
+/// hex form printed by F2 or a file containing it.
+fn load_spectator_board() -> Option<board::CompactBoard> {
+    let value = arg_value("--spectate")?;
+    let text = std::fs::read_to_string(&value).unwrap_or_else(|_| value.clone());
+    let compact = board::CompactBoard::from_hex(text.trim());
+    if compact.is_none() {
+        error!("Could not load spectator board from {value}: expected compact board hex");
+    }
+    compact
+}
+
+/// Reads the solid background color given with `--background <hex>`, such as `--background 1a1a2e`.
+fn load_background_color() -> Option<ClearColor> {
+    let value = arg_value("--background")?;
+    match Srgba::hex(&value) {
+        Ok(color) => Some(ClearColor(color.into())),
+        Err(_) => {
+            warn!("Ignoring invalid background color {value}, expected a hex color");
+            None
+        }
+    }
+}
+
+/// Loads the PNG image given with `--backdrop <file>` to draw behind the playfield.
+fn load_backdrop_image() -> Option<Image> {
+    let path = arg_value("--backdrop")?;
+    let bytes = match std::fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(error) => {
+            error!("Could not read backdrop image {path}: {error}");
+            return None;
+        }
+    };
+    match Image::from_buffer(
+        &bytes,
+        ImageType::Extension("png"),
+        CompressedImageFormats::NONE,
+        true,
+        ImageSampler::Default,
+        RenderAssetUsages::RENDER_WORLD,
+    ) {
+        Ok(image) => Some(image),
+        Err(error) => {
+            error!("Could not load backdrop image {path}: {error}");
+            None
+        }
+    }
+}
+
+/// Loads the board given with `--board <file>`, reporting any problem and carrying on without it.
+/// The file holds either a board string or the compact hex form printed by F2.
+fn load_imported_board() -> Option<board::Board> {
+    let path = arg_value("--board")?;
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(error) => {
+            error!("Could not read board file {path}: {error}");
+            return None;
+        }
+    };
+    if let Some(compact) = board::CompactBoard::from_hex(text.trim()) {
+        return match compact.decode() {
+            Ok((board, _, _)) => Some(board),
+            Err(error) => {
+                error!("Could not load board from {path}: {error}");
+                None
+            }
+        };
+    }
+    match board::board_from_string(&text) {
+        Ok(board) => Some(board),
+        Err(error) => {
+            error!("Could not load board from {path}: {error}");
+            None
+        }
+    }
+}
 
 fn main() {
-    // Determine the very first piece to put into the NextPiece resource
-    let first_next_shape = [
-        Shape::I,
-        Shape::O,
-        Shape::T,
-        Shape::L,
-        Shape::J,
-        Shape::S,
-        Shape::Z,
-    ]
-    .choose(&mut rand::rng()) // Use thread_rng for initial randomness
-    .unwrap()
-    .clone();
-
-    App::new()
-        // Add the default Bevy plugins for rendering, window management, input, etc.
-        .add_plugins((DefaultPlugins, EmbeddedAssetPlugin::default()))
+    let mut app = App::new();
+    // Add the default Bevy plugins for rendering, window management, input, etc. They go first,
+    // so logging is set up before the settings below are read and can warn about bad values.
+    app.add_plugins((
+        DefaultPlugins.set(log_plugin()),
+        EmbeddedAssetPlugin::default(),
+    ));
+
+    // Determine the very first piece to put into the NextPiece resource. A new randomizer of the
+    // selected kind takes over once a game is started from the title screen.
+    let game_seed = load_game_seed();
+    let repeat_limit = load_repeat_limit();
+    let mut piece_generator = randomizer::PieceGenerator::new(
+        randomizer::RandomizerKind::default(),
+        game_seed,
+        repeat_limit,
+    );
+    let first_next_shape = piece_generator.next_shape();
+
+    app
         // This is where we'll add our game state logic.
         // We're initializing it to the "Playing" state.
         .init_state::<GameState>()
@@ -44,49 +295,481 @@ fn main() {
         .insert_resource(Score(0))
         .insert_resource(LinesCleared(0))
         .insert_resource(Level(1))
+        .insert_resource(AllClearStreak(0))
+        .init_resource::<Combo>()
+        .init_resource::<BackToBack>()
+        .init_resource::<LastLock>()
+        .insert_resource(load_score_multiplier())
+        .insert_resource(load_idle_drop())
+        .insert_resource(BackdropImage(load_backdrop_image()))
+        .insert_resource(load_background_color().unwrap_or_default())
+        .insert_resource(load_timed_levels())
+        .insert_resource(load_input_buffer())
+        .insert_resource(setup::load_volume())
+        .init_resource::<CollapseDirection>()
+        .insert_resource(NoOverhangStart(has_flag("--no-overhang-start")))
         .insert_resource(NextPiece(first_next_shape)) // Initialize the NextPiece resource
+        .init_resource::<HoldPiece>()
+        .init_resource::<LockDelay>()
+        .insert_resource(piece_generator)
+        .insert_resource(randomizer::GameRng::new(game_seed))
+        .insert_resource(game_seed)
+        .insert_resource(repeat_limit)
+        .init_resource::<randomizer::RandomizerKind>()
+        .init_resource::<BoardMetrics>()
+        .init_resource::<atlas::BlockAtlas>()
+        .init_resource::<sound::SoundEffects>()
+        .init_resource::<board::CollisionGrid>()
+        .insert_resource(ImportedBoard(load_imported_board()))
+        .insert_resource(spectator::SpectatorBoard(load_spectator_board()))
+        .insert_resource(load_preview_scale())
+        .init_resource::<PreviewLayout>()
+        .init_resource::<GameMode>()
+        .init_resource::<RuleProfile>()
+        .init_resource::<GameOverReason>()
+        .insert_resource(debug::DebugMode(has_flag("--debug")))
+        .init_resource::<debug::GameLog>()
+        .init_resource::<GravityEnabled>()
+        .init_resource::<PlayTime>()
+        .init_resource::<PlayCounters>()
+        .init_resource::<Efficiency>()
+        .init_resource::<LifetimeStats>()
+        .init_resource::<StatsResetPending>()
+        .init_resource::<NewBests>()
+        .insert_resource(ShowEfficiency(has_flag("--stats")))
+        .insert_resource(ClassicScore(has_flag("--classic-score")))
+        .insert_resource(GridPulseEnabled(has_flag("--grid-pulse")))
+        .insert_resource(GhostPieceEnabled(!has_flag("--no-ghost")))
+        .insert_resource(LandingHighlight(has_flag("--landing-rows")))
+        .insert_resource(HoldAnimation(!has_flag("--no-hold-animation")))
+        .insert_resource(LockTicks(has_flag("--lock-ticks")))
+        .insert_resource(KeepMusic(has_flag("--keep-music")))
+        .insert_resource(PointerInput(has_flag("--pointer")))
+        .insert_resource(Letterbox(has_flag("--letterbox")))
+        .init_resource::<pointer::PointerGesture>()
+        .insert_resource(ScreenTransition {
+            enabled: !has_flag("--no-transitions"),
+            ..default()
+        })
+        .init_resource::<ForcedSequence>()
+        .init_resource::<NextQueue>()
+        .init_resource::<puzzle::Puzzles>()
+        .init_resource::<puzzle::PuzzleProgress>()
+        .init_resource::<rewind::RewindHistory>()
+        .init_resource::<rewind::RewindsLeft>()
+        .init_resource::<HudSide>()
+        .init_resource::<ColorScheme>()
+        .insert_resource(if has_flag("--rotate-first") {
+            InputPriority::RotateFirst
+        } else {
+            InputPriority::MoveFirst
+        })
+        .insert_resource(bindings::load_key_bindings())
+        .init_resource::<bindings::GamepadBindings>()
+        .init_resource::<SoftDropDas>()
+        .init_resource::<ShiftDas>()
+        .init_resource::<ShiftRepeat>()
+        .init_resource::<SoftDropRepeat>()
+        .insert_resource(load_entry_delay())
+        .init_resource::<EntryDelayTimer>()
+        .init_resource::<StartCountdown>()
+        .init_resource::<StartingLevel>()
+        .insert_resource(GarbageTimer(Timer::from_seconds(
+            SURVIVAL_GARBAGE_INTERVAL,
+            TimerMode::Repeating,
+        )))
 
+        // Catch broken shape tables before anything is spawned from them
+        .add_systems(
+            Startup,
+            tetromino::validate_shape_definitions.run_if(|| cfg!(debug_assertions)),
+        )
         // Add a startup system to set up the game environment once.
         .add_systems(Startup, setup::setup_camera)
-        .add_systems(Startup, setup::setup_audio)
+        .add_systems(Startup, setup::setup_backdrop)
+        .add_systems(Update, setup::fit_backdrop)
+        .add_systems(Startup, atlas::setup_block_atlas)
+        .add_systems(Startup, sound::setup_sound_effects)
+        // Collision checks read the settled blocks from the grid, brought up to date before
+        // anything moves in a frame and again once cleared rows have been taken out
+        .add_systems(First, board::sync_collision_grid)
+        // Keep bloom in line with the color scheme on every camera, including ones spawned later
+        .add_systems(Update, setup::sync_camera_bloom)
+        .add_systems(
+            Update,
+            setup::apply_letterbox.run_if(|letterbox: Res<Letterbox>| letterbox.0),
+        )
+        // The debug panel lives for the whole session and follows game flow in every state
+        .add_systems(Startup, debug::setup_debug_panel)
+        .add_systems(
+            Update,
+            (
+                debug::toggle_debug_mode,
+                debug::toggle_gravity,
+                debug::record_game_log,
+                debug::update_debug_panel,
+            )
+                .chain(),
+        )
         
         // Add systems for the Title state
         .add_systems(
             OnEnter(GameState::Title),
-            (ui::setup_title_screen, ui::despawn_game_board).chain(),
+            (
+                (ui::setup_title_screen, ui::despawn_game_board).chain(),
+                setup::setup_audio,
+            ),
+        )
+        .add_systems(
+            OnExit(GameState::Title),
+            (
+                ui::despawn_title_screen,
+                // The board and HUD are built once per game. Building them on entering Playing
+                // would duplicate them after every piece and every unpause.
+                (
+                    setup::setup_grid,
+                    ui::setup_scoreboard,
+                    ui::setup_next_piece_preview,
+                    ui::setup_hold_preview,
+                )
+                    .chain(),
+                ui::setup_controls_legend,
+                setup::spawn_imported_board,
+                // Puzzles bring their own first piece, so they are set up after the draw
+                (tetromino::draw_first_piece, puzzle::setup_puzzle).chain(),
+            ),
+        )
+        .add_systems(
+            Update,
+            ui::update_title_settings.run_if(in_state(GameState::Title)),
         )
-        .add_systems(OnExit(GameState::Title), ui::despawn_title_screen)
         
+        // The countdown waits for the fade into the game, and lets the first piece in at "GO"
+        .add_systems(OnEnter(GameState::Countdown), ui::setup_countdown)
+        .add_systems(OnExit(GameState::Countdown), ui::despawn_countdown)
+        .add_systems(
+            Update,
+            (game_logic::tick_countdown, ui::update_countdown)
+                .chain()
+                .run_if(in_state(GameState::Countdown).and(ui::screen_transition_finished)),
+        )
         // Add systems for the Paused state
-        .add_systems(OnEnter(GameState::Paused), ui::setup_pause_menu)
-        .add_systems(OnExit(GameState::Paused), ui::despawn_pause_menu)
+        // Block transforms are only kept in sync while playing, so bring the frozen board up to
+        // date once on pausing. `recenter_board` handles any resize while paused.
+        .add_systems(
+            OnEnter(GameState::Paused),
+            (
+                ui::setup_pause_menu,
+                game_logic::update_transforms,
+                setup::pause_music,
+            ),
+        )
+        .add_systems(
+            OnExit(GameState::Paused),
+            (ui::despawn_pause_menu, setup::resume_music),
+        )
+        // A soft reset clears the board, then lays out the mode's starting board again. In
+        // training, T does the same straight from play or game over to retry the position.
+        .add_systems(
+            Update,
+            (
+                input::soft_reset,
+                setup::spawn_imported_board,
+                puzzle::setup_puzzle,
+            )
+                .chain()
+                .before(ui::skip_screen_transition)
+                .run_if(
+                    (in_state(GameState::Paused).and(input_just_pressed(KeyCode::KeyS)))
+                        .or(in_state(GameState::Playing)
+                            .or(in_state(GameState::Paused))
+                            .or(in_state(GameState::GameOver))
+                            .and(|game_mode: Res<GameMode>| *game_mode == GameMode::Training)
+                            .and(input_just_pressed(KeyCode::KeyT)))
+                        .and(ui::screen_transition_finished),
+                ),
+        )
 
         // Add systems for the GameOver state
-        .add_systems(OnEnter(GameState::GameOver), ui::setup_game_over_screen)
-        .add_systems(OnExit(GameState::GameOver), ui::despawn_game_over_screen)
+        .add_systems(
+            OnEnter(GameState::GameOver),
+            (
+                ui::setup_game_over_screen,
+                game_logic::commit_personal_bests,
+                setup::pause_music.run_if(|keep_music: Res<KeepMusic>| !keep_music.0),
+            ),
+        )
+        // The music carries on after a rewind or retry, and starts over on the title screen
+        // unless it is kept playing
+        .add_systems(
+            OnExit(GameState::GameOver),
+            (ui::despawn_game_over_screen, setup::resume_music),
+        )
+        // Add systems for the Victory state. ENTER goes straight on to the next puzzle.
+        .add_systems(
+            OnEnter(GameState::Victory),
+            (
+                ui::setup_victory_screen,
+                confetti::spawn_confetti,
+                game_logic::commit_personal_bests,
+            ),
+        )
+        .add_systems(OnExit(GameState::Victory), ui::despawn_victory_screen)
+        .add_systems(
+            Update,
+            (
+                puzzle::select_next_puzzle,
+                input::soft_reset,
+                puzzle::setup_puzzle,
+            )
+                .chain()
+                .before(ui::skip_screen_transition)
+                .run_if(
+                    in_state(GameState::Victory)
+                        .and(input_just_pressed(KeyCode::Enter))
+                        .and(ui::screen_transition_finished),
+                ),
+        )
+        .add_systems(Update, confetti::update_confetti)
+        // A casual game can be rewound from the game over screen while it has rewinds left
+        .add_systems(
+            Update,
+            rewind::rewind_game
+                .before(ui::skip_screen_transition)
+                .run_if(
+                    in_state(GameState::GameOver)
+                        .and(input_just_pressed(KeyCode::KeyB))
+                        .and(ui::screen_transition_finished),
+                ),
+        )
 
         // Systems for handling user input. This will now run in all states.
-        .add_systems(Update, input::handle_input)
+        // Input runs before gravity so a tap always moves the piece before gravity can lock it
+        .add_systems(Update, input::handle_input.before(game_logic::gravity_system))
+        // A gamepad unplugged mid-game pauses it, unless this frame's input already moved on
+        .add_systems(
+            Update,
+            bindings::handle_gamepad_connections.after(input::handle_input),
+        )
+        // The anti-stall drop, when enabled, watches for input once the keys and pointer are read
+        .add_systems(
+            Update,
+            input::idle_drop_system
+                .after(input::handle_input)
+                .after(pointer::handle_pointer_input)
+                .before(game_logic::gravity_system)
+                .run_if(
+                    in_state(GameState::Playing)
+                        .and(|idle_drop: Res<IdleDrop>| idle_drop.0.is_some())
+                        .and(ui::screen_transition_finished),
+                ),
+        )
+        // Mouse and touch play, when enabled, is handled like the keys just after them
+        .add_systems(
+            Update,
+            pointer::handle_pointer_input
+                .after(input::handle_input)
+                .before(game_logic::gravity_system)
+                .run_if(
+                    in_state(GameState::Playing)
+                        .and(|pointer_input: Res<PointerInput>| pointer_input.0)
+                        .and(ui::screen_transition_finished),
+                ),
+        )
+        // The volume can be changed on the title screen and in the pause menu
+        .add_systems(
+            Update,
+            (
+                input::adjust_volume,
+                setup::apply_volume.run_if(resource_changed::<Volume>),
+            )
+                .chain()
+                .run_if(in_state(GameState::Title).or(in_state(GameState::Paused))),
+        )
+        .add_systems(
+            Update,
+            input::handle_title_input
+                .before(ui::skip_screen_transition)
+                .run_if(in_state(GameState::Title).and(ui::screen_transition_finished)),
+        )
+        // Screens fade in from black. A key press skips the fade, after input has been gated for
+        // this frame so the same press does not also act in the game.
+        .add_systems(Startup, ui::setup_transition_overlay)
+        .add_systems(
+            Update,
+            (
+                ui::start_screen_transition.before(input::handle_input),
+                ui::skip_screen_transition.after(input::handle_input),
+                ui::animate_screen_transition,
+            )
+                .chain(),
+        )
         
-        // When we enter the Spawning state, we'll clear lines, spawn a new piece, and immediately
-        // transition back to Playing.
+        // When we enter the Spawning state, we'll clear lines, check whether the game is over and
+        // start the entry delay. Once the delay has run out we spawn a new piece and transition
+        // back to Playing. A held piece didn't lock, so a hold skips straight to the respawn.
         .add_systems(
             OnEnter(GameState::Spawning),
-            (game_logic::clear_lines, tetromino::spawn_tetromino).chain(),
+            (
+                game_logic::record_last_lock.run_if(game_logic::piece_locked),
+                game_logic::clear_lines,
+                board::sync_collision_grid,
+                puzzle::check_puzzle_progress.run_if(game_logic::piece_locked),
+                game_logic::check_top_out,
+                rewind::record_snapshot.run_if(game_logic::piece_locked),
+                game_logic::start_entry_delay.run_if(game_logic::piece_locked),
+            )
+                .chain(),
         )
         .add_systems(
-            OnEnter(GameState::Playing),
-            (setup::setup_grid, ui::setup_scoreboard, ui::setup_next_piece_preview).chain(),
+            Update,
+            (
+                game_logic::tick_entry_delay,
+                (
+                    puzzle::count_puzzle_piece.run_if(puzzle::draws_new_piece),
+                    tetromino::spawn_tetromino,
+                )
+                    .chain()
+                    .run_if(
+                        game_logic::entry_delay_elapsed
+                            .and(puzzle::puzzle_in_progress)
+                            .and(game_logic::no_transition_pending),
+                    ),
+            )
+                .chain()
+                .run_if(in_state(GameState::Spawning)),
         )
         // Add a system for the main game logic that runs during the `Playing` state.
-        // `update_transforms` will sync grid positions with their visual transforms.
+        // `update_transforms` will sync grid positions with their visual transforms. Gravity, then
+        // rising garbage, move the piece strictly after the input systems have, and the transforms
+        // follow once every move of the frame is done.
+        .add_systems(
+            Update,
+            (
+                (
+                    game_logic::gravity_system,
+                    game_logic::survival_garbage_system,
+                    game_logic::update_transforms,
+                )
+                    .chain(),
+                ui::update_scoreboard,
+                ui::update_next_piece_preview,
+                ui::update_hold_preview,
+            )
+                .run_if(in_state(GameState::Playing)),
+        )
+        // The ghost and the landing rows follow the piece, and are cleared again once it has locked
+        .add_systems(
+            Update,
+            game_logic::update_ghost_piece
+                .after(input::handle_input)
+                .after(game_logic::gravity_system)
+                .run_if(|ghost_piece: Res<GhostPieceEnabled>| ghost_piece.0),
+        )
         .add_systems(
             Update,
-            (game_logic::gravity_system, game_logic::update_transforms, ui::update_scoreboard, ui::update_next_piece_preview)
+            game_logic::update_landing_highlight
+                .after(input::handle_input)
+                .after(game_logic::gravity_system)
+                .run_if(|landing_highlight: Res<LandingHighlight>| landing_highlight.0),
+        )
+        // APM and PPS only count time spent playing, so pausing does not drag them down
+        .add_systems(
+            Update,
+            (game_logic::tick_play_time, game_logic::update_efficiency)
+                .chain()
+                .before(ui::update_scoreboard)
                 .run_if(in_state(GameState::Playing)),
         )
+        // Timed levels go by the same time played, on top of the lines cleared
+        .add_systems(
+            Update,
+            game_logic::level_up_by_time
+                .after(game_logic::tick_play_time)
+                .before(game_logic::update_fall_speed)
+                .before(ui::update_scoreboard)
+                .run_if(
+                    in_state(GameState::Playing)
+                        .and(|timed_levels: Res<TimedLevels>| timed_levels.0.is_some()),
+                ),
+        )
+        .add_systems(
+            OnTransition {
+                exited: GameState::Playing,
+                entered: GameState::Spawning,
+            },
+            game_logic::count_locked_piece.run_if(game_logic::piece_locked),
+        )
+        // Personal bests are checked during play against finished games only
+        .add_systems(
+            Update,
+            ui::check_personal_bests
+                .after(ui::update_scoreboard)
+                .run_if(in_state(GameState::Playing)),
+        )
+        .add_systems(Update, ui::update_best_banner)
+        // Cleared rows fade away in any state, and the rows above drop into place once they
+        // have gone
+        .add_systems(
+            Update,
+            (
+                game_logic::animate_clearing_rows,
+                game_logic::update_transforms
+                    .run_if(in_state(GameState::Spawning).and(game_logic::no_rows_clearing)),
+            )
+                .chain(),
+        )
+        .add_systems(
+            Update,
+            ui::pulse_grid_on_clear.run_if(|grid_pulse: Res<GridPulseEnabled>| grid_pulse.0),
+        )
+        .add_systems(
+            Update,
+            sound::tick_lock_sound
+                .after(game_logic::gravity_system)
+                .run_if(
+                    in_state(GameState::Playing).and(|lock_ticks: Res<LockTicks>| lock_ticks.0),
+                ),
+        )
+        .add_systems(
+            Update,
+            ui::animate_hold_swap
+                .after(input::handle_input)
+                .after(tetromino::spawn_tetromino)
+                .run_if(in_state(GameState::Playing).or(in_state(GameState::Spawning))),
+        )
+        // The spectator board is drawn beside the playfield whenever the board is on screen
+        .add_systems(
+            Update,
+            spectator::render_spectator_board.run_if(
+                in_state(GameState::Playing)
+                    .or(in_state(GameState::Paused))
+                    .or(in_state(GameState::Countdown)),
+            ),
+        )
+        // The controls legend can be toggled and the board exported while playing or paused
+        .add_systems(
+            Update,
+            (ui::toggle_controls_legend, board::export_board)
+                .run_if(in_state(GameState::Playing).or(in_state(GameState::Paused))),
+        )
+        .add_systems(
+            Update,
+            ui::update_controls_legend.run_if(resource_changed::<bindings::KeyBindings>),
+        )
         // System to update the fall speed when the level changes
         .add_systems(Update, game_logic::update_fall_speed)
+        // Keep the board sized to the window and re-center it in any state when the layout changes
+        .add_systems(
+            Update,
+            (
+                setup::fit_board_to_window.run_if(|letterbox: Res<Letterbox>| !letterbox.0),
+                game_logic::recenter_board.run_if(resource_changed::<BoardMetrics>),
+                ui::update_preview_layout
+                    .run_if(resource_changed::<BoardMetrics>.or(resource_changed::<HudSide>)),
+            )
+                .chain(),
+        )
         // Run the game!
         .run();
 }
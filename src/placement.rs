@@ -0,0 +1,116 @@
+use bevy::math::IVec2;
+use std::collections::{HashSet, VecDeque};
+
+use crate::{
+    board::CollisionGrid,
+    components::{GridPosition, RotationState, Shape},
+    constants::GRID_SIZE_Y,
+    game_logic::check_collision,
+    srs::kick_offsets,
+    tetromino::{get_rotation_center_index, rotate_about, rotation_pivot, spawn_blocks},
+};
+
+/// Moves every block of a piece by the given offset.
+fn shifted(blocks: &[GridPosition], dx: i32, dy: i32) -> Vec<GridPosition> {
+    blocks
+        .iter()
+        .map(|block| GridPosition {
+            x: block.x + dx,
+            y: block.y + dy,
+        })
+        .collect()
+}
+
+/// Rotates a piece by a number of quarter turns clockwise around a pivot in doubled grid
+/// coordinates, the same way player input does; negative turns go counterclockwise.
+pub fn rotated(blocks: &[GridPosition], pivot: IVec2, quarter_turns: i32) -> Vec<GridPosition> {
+    blocks
+        .iter()
+        .map(|block| rotate_about(*block, pivot, quarter_turns))
+        .collect()
+}
+
+fn collides(blocks: &[GridPosition], collision_grid: &CollisionGrid) -> bool {
+    blocks
+        .iter()
+        .any(|block| check_collision(*block, collision_grid))
+}
+
+/// Rotates a piece by a number of quarter turns the way player input does, wall kicks
+/// included. Returns the turned piece and its new state, or `None` when every kick is blocked or
+/// the shape never turns.
+fn kicked_rotation(
+    shape: Shape,
+    blocks: &[GridPosition],
+    state: RotationState,
+    collision_grid: &CollisionGrid,
+    quarter_turns: i32,
+) -> Option<(Vec<GridPosition>, RotationState)> {
+    let center = blocks[get_rotation_center_index(shape)?];
+    let turned = rotated(blocks, rotation_pivot(shape, center, state), quarter_turns);
+    let new_state = state.turned(quarter_turns);
+    kick_offsets(shape, state, new_state)
+        .iter()
+        .map(|(dx, dy)| shifted(&turned, *dx, *dy))
+        .find(|kicked| !collides(kicked, collision_grid))
+        .map(|kicked| (kicked, new_state))
+}
+
+/// Enumerates every resting spot a freshly spawned piece can reach by moving left, right,
+/// down and rotating, in the same steps the player can take.
+/// Each placement is returned as the positions of the piece's blocks.
+pub fn reachable_placements(
+    shape: Shape,
+    collision_grid: &CollisionGrid,
+) -> Vec<Vec<GridPosition>> {
+    let start = spawn_blocks(shape);
+    if collides(&start, collision_grid) {
+        return Vec::new();
+    }
+
+    // The state is part of the key, as it decides which kicks the next turn can use
+    let key = |blocks: &[GridPosition], state: RotationState| {
+        let mut key: Vec<(i32, i32)> = blocks.iter().map(|block| (block.x, block.y)).collect();
+        key.sort_unstable();
+        (key, state)
+    };
+    let start_state = RotationState::default();
+    let mut visited = HashSet::from([key(&start, start_state)]);
+    let mut queue = VecDeque::from([(start, start_state)]);
+    let mut placements = Vec::new();
+
+    while let Some((blocks, state)) = queue.pop_front() {
+        let down = shifted(&blocks, 0, -1);
+        if collides(&down, collision_grid) {
+            placements.push(blocks.clone());
+        }
+        let mut moves = vec![
+            (down, state),
+            (shifted(&blocks, -1, 0), state),
+            (shifted(&blocks, 1, 0), state),
+        ];
+        for quarter_turns in [1, -1, 2] {
+            moves.extend(kicked_rotation(
+                shape,
+                &blocks,
+                state,
+                collision_grid,
+                quarter_turns,
+            ));
+        }
+        for (next, next_state) in moves {
+            if !collides(&next, collision_grid) && visited.insert(key(&next, next_state)) {
+                queue.push_back((next, next_state));
+            }
+        }
+    }
+    placements
+}
+
+/// Returns true when the piece can come to rest somewhere entirely inside the playfield.
+/// When it cannot, the game is in a stalemate even though the piece spawned clear.
+pub fn has_legal_placement(shape: Shape, collision_grid: &CollisionGrid) -> bool {
+    reachable_placements(shape, collision_grid)
+        .iter()
+        .any(|blocks| blocks.iter().all(|block| block.y < GRID_SIZE_Y))
+}
@@ -0,0 +1,150 @@
+use bevy::{prelude::*, window::PrimaryWindow};
+
+use crate::{
+    board::CollisionGrid,
+    components::{GridPosition, RotationCenter, Tetromino},
+    constants::{
+        POINTER_SWIPE_DISTANCE, POINTER_SWIPE_SECONDS, POINTER_TAP_DISTANCE, POINTER_TAP_SECONDS,
+    },
+    game_state::GameState,
+    input::{DropScore, handle_hard_drop, handle_horizontal_movement, handle_rotation},
+    resources::{BoardMetrics, GameOverReason, LockDelay, PlayCounters},
+    setup::GameCameraFilter,
+};
+
+/// Resource tracking the mouse or touch gesture in progress.
+#[derive(Resource, Default)]
+pub struct PointerGesture {
+    /// Where the press started, in screen pixels, and when, in seconds since startup.
+    start: Option<(Vec2, f32)>,
+    /// The latest pointer position in screen pixels.
+    position: Vec2,
+    /// The board column under the pointer when the piece last followed it.
+    column: i32,
+    /// Whether the piece has been dragged since the press, which rules out a tap.
+    dragged: bool,
+}
+
+/// Returns where the pointer is pressed, preferring a touch over the mouse.
+fn pressed_position(
+    touches: &Touches,
+    mouse_buttons: &ButtonInput<MouseButton>,
+    window: &Window,
+) -> Option<Vec2> {
+    touches.first_pressed_position().or_else(|| {
+        mouse_buttons
+            .pressed(MouseButton::Left)
+            .then(|| window.cursor_position())
+            .flatten()
+    })
+}
+
+/// A system that plays with a mouse or touchscreen: dragging sideways moves the piece column by
+/// column, a tap rotates it and a quick swipe down hard drops it. The keyboard keeps working
+/// alongside it.
+#[allow(clippy::too_many_arguments)]
+pub fn handle_pointer_input(
+    mut commands: Commands,
+    time: Res<Time>,
+    touches: Res<Touches>,
+    mouse_buttons: Res<ButtonInput<MouseButton>>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    camera_query: Query<(&Camera, &GlobalTransform), GameCameraFilter>,
+    metrics: Res<BoardMetrics>,
+    mut gesture: ResMut<PointerGesture>,
+    mut tetromino_query: Query<
+        (Entity, &mut GridPosition, Option<&mut RotationCenter>),
+        With<Tetromino>,
+    >,
+    collision_grid: Res<CollisionGrid>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut game_over_reason: ResMut<GameOverReason>,
+    mut play_counters: ResMut<PlayCounters>,
+    mut drop_score: DropScore,
+    mut lock_delay: ResMut<LockDelay>,
+) {
+    let Ok(window) = window_query.single() else {
+        return;
+    };
+    let Some((camera, camera_transform)) = camera_query.iter().find(|(camera, _)| camera.is_active)
+    else {
+        return;
+    };
+    // Pointer positions are window coordinates, while the camera works in its own viewport
+    let viewport_origin = camera
+        .logical_viewport_rect()
+        .map(|rect| rect.min)
+        .unwrap_or_default();
+    let column_at = |position: Vec2| {
+        camera
+            .viewport_to_world_2d(camera_transform, position - viewport_origin)
+            .ok()
+            .map(|world| metrics.world_to_column(world.x))
+    };
+    let now = time.elapsed_secs();
+
+    let Some((start, started_at)) = gesture.start else {
+        // A new press starts a gesture from the column under the pointer
+        if let Some(position) = pressed_position(&touches, &mouse_buttons, window)
+            && let Some(column) = column_at(position)
+        {
+            *gesture = PointerGesture {
+                start: Some((position, now)),
+                position,
+                column,
+                dragged: false,
+            };
+        }
+        return;
+    };
+
+    if let Some(position) = pressed_position(&touches, &mouse_buttons, window) {
+        // While held, the piece follows the pointer one column at a time
+        gesture.position = position;
+        let Some(column) = column_at(position) else {
+            return;
+        };
+        while gesture.column != column {
+            let direction = (column - gesture.column).signum();
+            if handle_horizontal_movement(&mut tetromino_query, &collision_grid, direction) {
+                lock_delay.piece_moved();
+            }
+            play_counters.actions += 1;
+            gesture.column += direction;
+            gesture.dragged = true;
+        }
+        return;
+    }
+
+    // The press has ended. A release that happened outside of play is dropped with the gesture.
+    let released = mouse_buttons.just_released(MouseButton::Left) || touches.any_just_released();
+    let travel = gesture.position - start;
+    let duration = now - started_at;
+    let dragged = gesture.dragged;
+    *gesture = PointerGesture::default();
+    if !released {
+        return;
+    }
+
+    // Screen coordinates grow downwards, so a swipe down has a positive y travel
+    if travel.y >= POINTER_SWIPE_DISTANCE
+        && travel.y > travel.x.abs()
+        && duration <= POINTER_SWIPE_SECONDS
+    {
+        play_counters.actions += 1;
+        let distance = handle_hard_drop(
+            &mut commands,
+            &mut tetromino_query,
+            &collision_grid,
+            &mut next_state,
+            &mut game_over_reason,
+        );
+        drop_score.hard_drop(distance);
+    } else if !dragged && travel.length() <= POINTER_TAP_DISTANCE && duration <= POINTER_TAP_SECONDS
+    {
+        play_counters.actions += 1;
+        if handle_rotation(&mut tetromino_query, &collision_grid, 1) {
+            lock_delay.piece_moved();
+        }
+    }
+}
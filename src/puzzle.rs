@@ -0,0 +1,446 @@
+use bevy::prelude::*;
+use std::{collections::VecDeque, fmt};
+
+use crate::{
+    atlas::BlockAtlas,
+    board::{Board, BoardParseError, Cell, board_from_string, spawn_board_blocks, stack_is_empty},
+    components::{GridPosition, Shape, Tetromino},
+    game_state::GameState,
+    resources::{
+        BoardMetrics, CollapseDirection, ColorScheme, ForcedSequence, GameMode, HoldPiece,
+        LinesCleared, NextPiece,
+    },
+};
+
+/// The puzzles bundled with the game, in the order they are offered on the title screen.
+const BUNDLED_PUZZLES: [&str; 3] = [
+    include_str!("../assets/puzzles/tetris_well.txt"),
+    include_str!("../assets/puzzles/twin_squares.txt"),
+    include_str!("../assets/puzzles/corner_fill.txt"),
+];
+
+/// What the player has to achieve to solve a puzzle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PuzzleGoal {
+    /// Leave the board completely empty.
+    ClearAll,
+    /// Clear at least this many lines.
+    ClearLines(u32),
+}
+
+/// A preset board together with the pieces the player gets and the goal to reach with them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PuzzleSpec {
+    pub name: String,
+    pub board: Board,
+    pub piece_sequence: Vec<Shape>,
+    pub goal: PuzzleGoal,
+    pub collapse: CollapseDirection,
+}
+
+/// How a puzzle attempt ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PuzzleOutcome {
+    Solved,
+    Failed,
+}
+
+/// The reasons a puzzle file can fail to parse.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PuzzleParseError {
+    /// A required `key:` line is missing.
+    MissingField(&'static str),
+    /// A piece in the `pieces:` line is not a shape letter.
+    UnknownPiece(String),
+    /// The `goal:` line is neither `clear all` nor `<n> lines`.
+    UnknownGoal(String),
+    /// The optional `collapse:` line is neither `down` nor `up`.
+    UnknownCollapse(String),
+    /// The board below the `board:` line is malformed.
+    Board(BoardParseError),
+}
+
+impl fmt::Display for PuzzleParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PuzzleParseError::MissingField(field) => write!(f, "missing '{field}:' line"),
+            PuzzleParseError::UnknownPiece(piece) => write!(f, "unknown piece '{piece}'"),
+            PuzzleParseError::UnknownGoal(goal) => write!(f, "unknown goal '{goal}'"),
+            PuzzleParseError::UnknownCollapse(collapse) => {
+                write!(f, "unknown collapse direction '{collapse}'")
+            }
+            PuzzleParseError::Board(error) => write!(f, "invalid board: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for PuzzleParseError {}
+
+/// Parses a puzzle file. The file has `name:`, `pieces:` and `goal:` lines and an optional
+/// `collapse:` line followed by a `board:` line, after which the rest of the file is the board
+/// in board string notation.
+pub fn parse_puzzle(text: &str) -> Result<PuzzleSpec, PuzzleParseError> {
+    let (header, board) = text
+        .split_once("board:")
+        .ok_or(PuzzleParseError::MissingField("board"))?;
+    let field = |key: &'static str| {
+        header
+            .lines()
+            .find_map(|line| line.trim().strip_prefix(key)?.strip_prefix(':'))
+            .map(str::trim)
+            .ok_or(PuzzleParseError::MissingField(key))
+    };
+
+    let piece_sequence = field("pieces")?
+        .split_whitespace()
+        .map(|piece| match piece {
+            "I" => Ok(Shape::I),
+            "O" => Ok(Shape::O),
+            "T" => Ok(Shape::T),
+            "L" => Ok(Shape::L),
+            "J" => Ok(Shape::J),
+            "S" => Ok(Shape::S),
+            "Z" => Ok(Shape::Z),
+            _ => Err(PuzzleParseError::UnknownPiece(piece.to_string())),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if piece_sequence.is_empty() {
+        return Err(PuzzleParseError::MissingField("pieces"));
+    }
+
+    let goal_text = field("goal")?;
+    let goal = match goal_text.split_whitespace().collect::<Vec<_>>()[..] {
+        ["clear", "all"] => PuzzleGoal::ClearAll,
+        [lines, "lines" | "line"] => PuzzleGoal::ClearLines(
+            lines
+                .parse()
+                .map_err(|_| PuzzleParseError::UnknownGoal(goal_text.to_string()))?,
+        ),
+        _ => return Err(PuzzleParseError::UnknownGoal(goal_text.to_string())),
+    };
+
+    let collapse = match field("collapse") {
+        Err(_) | Ok("down") => CollapseDirection::Down,
+        Ok("up") => CollapseDirection::Up,
+        Ok(collapse) => return Err(PuzzleParseError::UnknownCollapse(collapse.to_string())),
+    };
+
+    Ok(PuzzleSpec {
+        name: field("name")?.to_string(),
+        board: board_from_string(board).map_err(PuzzleParseError::Board)?,
+        piece_sequence,
+        goal,
+        collapse,
+    })
+}
+
+/// Resource holding every available puzzle and the one selected on the title screen.
+#[derive(Resource)]
+pub struct Puzzles {
+    pub specs: Vec<PuzzleSpec>,
+    pub selected: usize,
+}
+
+impl Default for Puzzles {
+    fn default() -> Self {
+        Self {
+            specs: BUNDLED_PUZZLES
+                .iter()
+                .map(|text| parse_puzzle(text).expect("bundled puzzles are valid"))
+                .collect(),
+            selected: 0,
+        }
+    }
+}
+
+impl Puzzles {
+    /// Returns the puzzle selected on the title screen.
+    pub fn current(&self) -> &PuzzleSpec {
+        &self.specs[self.selected]
+    }
+
+    /// Selects the next puzzle, wrapping around after the last one.
+    pub fn select_next(&mut self) {
+        self.selected = (self.selected + 1) % self.specs.len();
+    }
+}
+
+/// Resource tracking the attempt at the current puzzle.
+#[derive(Resource, Default)]
+pub struct PuzzleProgress {
+    pub pieces_spawned: usize,
+    pub outcome: Option<PuzzleOutcome>,
+}
+
+/// A system that lays out the selected puzzle when a game starts in puzzle or training mode:
+/// the preset board is placed and its pieces are queued up in order. Training plays the same
+/// positions without a goal, and random pieces follow once the sequence runs out.
+#[allow(clippy::too_many_arguments)]
+pub fn setup_puzzle(
+    mut commands: Commands,
+    game_mode: Res<GameMode>,
+    puzzles: Res<Puzzles>,
+    metrics: Res<BoardMetrics>,
+    color_scheme: Res<ColorScheme>,
+    block_atlas: Res<BlockAtlas>,
+    mut next_piece: ResMut<NextPiece>,
+    mut forced_sequence: ResMut<ForcedSequence>,
+    mut progress: ResMut<PuzzleProgress>,
+    mut collapse_direction: ResMut<CollapseDirection>,
+) {
+    if !game_mode.uses_puzzle_board() {
+        *collapse_direction = CollapseDirection::default();
+        return;
+    }
+
+    let puzzle = puzzles.current();
+    *collapse_direction = puzzle.collapse;
+    spawn_board_blocks(
+        &mut commands,
+        &metrics,
+        &block_atlas,
+        *color_scheme,
+        &puzzle.board,
+    );
+    next_piece.0 = puzzle.piece_sequence[0];
+    forced_sequence.0 = puzzle.piece_sequence[1..]
+        .iter()
+        .copied()
+        .collect::<VecDeque<_>>();
+    *progress = PuzzleProgress::default();
+    info!("{} started: {}", game_mode.label(), puzzle.name);
+}
+
+/// A system that checks the puzzle goal after every lock, once full rows have been cleared.
+/// The puzzle fails when the last piece has been played without reaching the goal.
+pub fn check_puzzle_progress(
+    game_mode: Res<GameMode>,
+    puzzles: Res<Puzzles>,
+    lines_cleared: Res<LinesCleared>,
+    mut progress: ResMut<PuzzleProgress>,
+    block_query: Query<(&GridPosition, Option<&Shape>), Without<Tetromino>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if *game_mode != GameMode::Puzzle || progress.pieces_spawned == 0 {
+        return;
+    }
+
+    let puzzle = puzzles.current();
+    let solved = match puzzle.goal {
+        PuzzleGoal::ClearAll => stack_is_empty(
+            block_query
+                .iter()
+                .map(|(position, shape)| (*position, Cell::from_shape(shape))),
+        ),
+        PuzzleGoal::ClearLines(lines) => lines_cleared.0 >= lines,
+    };
+    if solved {
+        progress.outcome = Some(PuzzleOutcome::Solved);
+        info!("Puzzle solved!");
+        next_state.set(GameState::Victory);
+    } else if progress.pieces_spawned >= puzzle.piece_sequence.len() {
+        progress.outcome = Some(PuzzleOutcome::Failed);
+        info!("Puzzle failed!");
+        next_state.set(GameState::GameOver);
+    }
+}
+
+/// A system that moves on to the next puzzle, so the victory screen can start it straight away.
+pub fn select_next_puzzle(mut puzzles: ResMut<Puzzles>) {
+    puzzles.select_next();
+}
+
+/// A system that counts the pieces handed out during a puzzle.
+pub fn count_puzzle_piece(game_mode: Res<GameMode>, mut progress: ResMut<PuzzleProgress>) {
+    if *game_mode == GameMode::Puzzle {
+        progress.pieces_spawned += 1;
+    }
+}
+
+/// Run condition that is false when the piece about to spawn comes back out of the hold box, as
+/// it was already counted when it was first handed out.
+pub fn draws_new_piece(hold_piece: Res<HoldPiece>) -> bool {
+    hold_piece.pending.is_none() || hold_piece.shape.is_none()
+}
+
+/// Run condition that is false once the current puzzle has been solved or failed,
+/// so no further pieces are spawned.
+pub fn puzzle_in_progress(progress: Res<PuzzleProgress>) -> bool {
+    progress.outcome.is_none()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+
+    use crate::{
+        game_logic::{clear_lines, tests::insert_scoring_resources},
+        input::soft_reset,
+        randomizer::{GameRng, GameSeed, PieceGenerator, RandomizerKind, RepeatLimit},
+        resources::{NoOverhangStart, StartingLevel},
+    };
+
+    /// A piece and the four cells it locks in.
+    type Placement = (Shape, [(i32, i32); 4]);
+
+    /// Starts the bundled puzzle at `index` in puzzle mode, with its board laid out as settled
+    /// blocks.
+    fn world_with_puzzle(index: usize) -> World {
+        let mut world = World::new();
+        insert_scoring_resources(&mut world);
+        world.insert_resource(GameMode::Puzzle);
+        world.init_resource::<PuzzleProgress>();
+        world.init_resource::<NextState<GameState>>();
+        let puzzles = Puzzles {
+            selected: index,
+            ..default()
+        };
+        for (position, _) in puzzles.current().board.occupied() {
+            world.spawn(position);
+        }
+        world.insert_resource(puzzles);
+        world
+    }
+
+    /// Locks each piece in the given cells in turn, clearing lines and checking the goal after
+    /// each, and returns the outcome.
+    fn play(world: &mut World, pieces: &[Placement]) -> Option<PuzzleOutcome> {
+        for (shape, cells) in pieces {
+            world.resource_mut::<PuzzleProgress>().pieces_spawned += 1;
+            for &(x, y) in cells {
+                world.spawn((GridPosition { x, y }, *shape));
+            }
+            world.run_system_once(clear_lines).unwrap();
+            world.run_system_once(check_puzzle_progress).unwrap();
+        }
+        world.resource::<PuzzleProgress>().outcome
+    }
+
+    /// Returns the settled board as it stands in the world.
+    fn settled_board(world: &mut World) -> Board {
+        let blocks = world
+            .query_filtered::<(&GridPosition, Option<&Shape>), Without<Tetromino>>()
+            .iter(world)
+            .map(|(position, shape)| (*position, Cell::from_shape(shape)))
+            .collect::<Vec<_>>();
+        Board::from_blocks(blocks)
+    }
+
+    #[test]
+    fn retrying_a_training_position_lays_it_out_again() {
+        let mut world = World::new();
+        world.insert_resource(GameMode::Training);
+        world.insert_resource(Puzzles {
+            selected: 2,
+            ..default()
+        });
+        world.init_resource::<BoardMetrics>();
+        world.init_resource::<ColorScheme>();
+        world.init_resource::<BlockAtlas>();
+        world.insert_resource(NextPiece(Shape::I));
+        world.init_resource::<ForcedSequence>();
+        world.init_resource::<PuzzleProgress>();
+        world.init_resource::<CollapseDirection>();
+        world.init_resource::<NextState<GameState>>();
+        world.init_resource::<NoOverhangStart>();
+        world.init_resource::<RandomizerKind>();
+        world.insert_resource(GameSeed(0x707));
+        world.init_resource::<RepeatLimit>();
+        world.init_resource::<PieceGenerator>();
+        world.insert_resource(GameRng::new(GameSeed(0x707)));
+        world.insert_resource(StartingLevel(1));
+
+        let puzzle = world.resource::<Puzzles>().current().clone();
+        let check_position = |world: &mut World| {
+            assert_eq!(settled_board(world), puzzle.board);
+            assert_eq!(world.resource::<NextPiece>().0, puzzle.piece_sequence[0]);
+            assert!(
+                world
+                    .resource::<ForcedSequence>()
+                    .0
+                    .iter()
+                    .eq(&puzzle.piece_sequence[1..])
+            );
+        };
+
+        world.run_system_once(setup_puzzle).unwrap();
+        check_position(&mut world);
+
+        // Play on a little: a piece locks and the queue moves along
+        world.spawn((GridPosition { x: 0, y: 10 }, Shape::Z));
+        world.resource_mut::<ForcedSequence>().0.pop_front();
+        world.insert_resource(NextPiece(Shape::S));
+
+        // T restarts the position through the soft reset chain
+        let mut retry = Schedule::default();
+        retry.add_systems((soft_reset, setup_puzzle).chain());
+        retry.run(&mut world);
+        check_position(&mut world);
+    }
+
+    #[test]
+    fn bundled_puzzles_parse() {
+        for text in BUNDLED_PUZZLES {
+            assert!(parse_puzzle(text).is_ok());
+        }
+    }
+
+    #[test]
+    fn known_solutions_solve_every_bundled_puzzle() {
+        let solutions: [&[Placement]; 3] = [
+            // Tetris Well: the I stands in the well
+            &[(Shape::I, [(9, 0), (9, 1), (9, 2), (9, 3)])],
+            // Twin Squares: the two O pieces fill the gap side by side
+            &[
+                (Shape::O, [(0, 0), (1, 0), (0, 1), (1, 1)]),
+                (Shape::O, [(2, 0), (3, 0), (2, 1), (3, 1)]),
+            ],
+            // Corner Fill: the O fills the corner and the L hooks over the stack beside it
+            &[
+                (Shape::L, [(6, 2), (7, 2), (7, 1), (7, 0)]),
+                (Shape::O, [(8, 0), (9, 0), (8, 1), (9, 1)]),
+            ],
+        ];
+        for (index, solution) in solutions.into_iter().enumerate() {
+            let mut world = world_with_puzzle(index);
+            assert_eq!(play(&mut world, solution), Some(PuzzleOutcome::Solved));
+            assert!(matches!(
+                world.resource::<NextState<GameState>>(),
+                NextState::Pending(GameState::Victory)
+            ));
+        }
+    }
+
+    #[test]
+    fn missing_the_goal_with_the_last_piece_fails() {
+        // Laying the I flat on top of the stack clears nothing and uses up the only piece
+        let mut world = world_with_puzzle(0);
+        let outcome = play(&mut world, &[(Shape::I, [(0, 4), (1, 4), (2, 4), (3, 4)])]);
+        assert_eq!(outcome, Some(PuzzleOutcome::Failed));
+        assert!(matches!(
+            world.resource::<NextState<GameState>>(),
+            NextState::Pending(GameState::GameOver)
+        ));
+    }
+
+    #[test]
+    fn malformed_puzzles_are_rejected() {
+        assert_eq!(
+            parse_puzzle("name: Bad\npieces: I X\ngoal: clear all\nboard:\nGGGG......"),
+            Err(PuzzleParseError::UnknownPiece("X".to_string()))
+        );
+        assert_eq!(
+            parse_puzzle("name: Bad\npieces: I\ngoal: win\nboard:\nGGGG......"),
+            Err(PuzzleParseError::UnknownGoal("win".to_string()))
+        );
+        assert_eq!(
+            parse_puzzle("pieces: I\ngoal: clear all\nboard:\nGGGG......"),
+            Err(PuzzleParseError::MissingField("name"))
+        );
+        assert_eq!(
+            parse_puzzle("name: Bad\npieces: I\ngoal: clear all\nGGGG......"),
+            Err(PuzzleParseError::MissingField("board"))
+        );
+    }
+}
@@ -0,0 +1,319 @@
+use bevy::prelude::*;
+use rand::{
+    RngCore, SeedableRng,
+    rngs::StdRng,
+    seq::{IndexedRandom, SliceRandom},
+};
+use std::collections::VecDeque;
+
+use crate::components::Shape;
+
+/// How many times the TGM randomizer rolls for a piece that is not in its history.
+const TGM_ROLLS: usize = 6;
+
+/// A way of generating the sequence of pieces handed out during a game.
+pub trait Randomizer {
+    /// Returns the next piece in the sequence.
+    fn next(&mut self, rng: &mut dyn RngCore) -> Shape;
+}
+
+/// Picks every piece at random on its own, so long droughts and repeats can happen.
+/// With a repeat limit, a shape that has already come up that many times in a row is rerolled
+/// among the other six, so the run is never longer than the limit.
+#[derive(Default)]
+pub struct Uniform {
+    repeat_limit: Option<u32>,
+    last: Option<Shape>,
+    run: u32,
+}
+
+impl Uniform {
+    pub fn new(repeat_limit: RepeatLimit) -> Self {
+        Self {
+            repeat_limit: repeat_limit.0,
+            ..default()
+        }
+    }
+}
+
+impl Randomizer for Uniform {
+    fn next(&mut self, rng: &mut dyn RngCore) -> Shape {
+        let run_is_full = self.repeat_limit.is_some_and(|limit| self.run >= limit);
+        let shape = if run_is_full {
+            // Drawing from the others directly is the same as rerolling until the shape
+            // changes, without any chance of looping
+            *Shape::ALL
+                .iter()
+                .copied()
+                .filter(|shape| Some(*shape) != self.last)
+                .collect::<Vec<_>>()
+                .choose(rng)
+                .unwrap()
+        } else {
+            *Shape::ALL.choose(rng).unwrap()
+        };
+        if Some(shape) == self.last {
+            self.run += 1;
+        } else {
+            self.last = Some(shape);
+            self.run = 1;
+        }
+        shape
+    }
+}
+
+/// Deals pieces from a shuffled bag holding `copies` of every shape, refilled once it is empty.
+/// Every shape comes up exactly `copies` times in each run of `copies * 7` pieces.
+pub struct Bag {
+    copies: usize,
+    bag: Vec<Shape>,
+}
+
+impl Bag {
+    pub fn new(copies: usize) -> Self {
+        Self {
+            copies,
+            bag: Vec::new(),
+        }
+    }
+}
+
+impl Randomizer for Bag {
+    fn next(&mut self, rng: &mut dyn RngCore) -> Shape {
+        if self.bag.is_empty() {
+            for _ in 0..self.copies {
+                self.bag.extend(Shape::ALL);
+            }
+            self.bag.shuffle(rng);
+        }
+        self.bag.pop().unwrap()
+    }
+}
+
+/// The history-based randomizer of the TGM games. It remembers the last four pieces and rolls
+/// up to `TGM_ROLLS` times for one that is not among them, keeping the last roll otherwise.
+/// The history starts out full of S and Z pieces, so games rarely open with them.
+pub struct TgmHistory {
+    history: VecDeque<Shape>,
+}
+
+impl Default for TgmHistory {
+    fn default() -> Self {
+        Self {
+            history: VecDeque::from([Shape::Z, Shape::S, Shape::Z, Shape::S]),
+        }
+    }
+}
+
+impl Randomizer for TgmHistory {
+    fn next(&mut self, rng: &mut dyn RngCore) -> Shape {
+        let mut shape = *Shape::ALL.choose(rng).unwrap();
+        for _ in 1..TGM_ROLLS {
+            if !self.history.contains(&shape) {
+                break;
+            }
+            shape = *Shape::ALL.choose(rng).unwrap();
+        }
+        self.history.pop_front();
+        self.history.push_back(shape);
+        shape
+    }
+}
+
+/// Which randomizer a game deals its pieces with, chosen on the title screen.
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RandomizerKind {
+    /// Every piece picked independently, as the game used to deal them.
+    Uniform,
+    /// One of each shape per shuffled bag of seven, the guideline's random generator. No shape
+    /// goes more than twelve pieces without coming up.
+    #[default]
+    SevenBag,
+    /// Two of each shape per shuffled bag of fourteen.
+    FourteenBag,
+    /// Rerolls pieces seen among the last four.
+    Tgm,
+}
+
+impl RandomizerKind {
+    /// Returns the randomizer that follows this one when cycling on the title screen.
+    pub fn next(self) -> Self {
+        match self {
+            RandomizerKind::Uniform => RandomizerKind::SevenBag,
+            RandomizerKind::SevenBag => RandomizerKind::FourteenBag,
+            RandomizerKind::FourteenBag => RandomizerKind::Tgm,
+            RandomizerKind::Tgm => RandomizerKind::Uniform,
+        }
+    }
+
+    /// Returns the display name of the randomizer.
+    pub fn label(self) -> &'static str {
+        match self {
+            RandomizerKind::Uniform => "Uniform",
+            RandomizerKind::SevenBag => "7-bag",
+            RandomizerKind::FourteenBag => "14-bag",
+            RandomizerKind::Tgm => "TGM",
+        }
+    }
+}
+
+/// Resource holding the seed the piece sequence of the next game is drawn from. The same seed
+/// and randomizer always deal the same pieces, so a seed can be shared to replay a game.
+/// It can be rerolled on the title screen or given with `--seed <hex>`.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GameSeed(pub u32);
+
+impl Default for GameSeed {
+    fn default() -> Self {
+        Self(rand::random())
+    }
+}
+
+impl GameSeed {
+    /// Returns the seed as it is shown and typed in, as eight hex digits.
+    pub fn label(self) -> String {
+        format!("{:08X}", self.0)
+    }
+
+    /// Reads a seed written as hex digits, as shown by `label`.
+    pub fn from_hex(text: &str) -> Option<Self> {
+        u32::from_str_radix(text.trim(), 16).ok().map(Self)
+    }
+}
+
+/// Resource holding the seeded random number generator for everything random in a game other
+/// than the pieces, such as where the gaps in survival garbage fall. It is reseeded from the
+/// `GameSeed` whenever a game starts, so a shared seed replays the garbage as well.
+#[derive(Resource)]
+pub struct GameRng(pub StdRng);
+
+impl Default for GameRng {
+    fn default() -> Self {
+        Self::new(GameSeed::default())
+    }
+}
+
+impl GameRng {
+    /// Starts the generator for the given seed. It is kept apart from the piece sequence, so
+    /// drawing garbage never changes which pieces are dealt.
+    pub fn new(seed: GameSeed) -> Self {
+        Self(StdRng::seed_from_u64(u64::from(seed.0) << 32))
+    }
+}
+
+/// Resource holding how many times in a row the uniform randomizer may deal the same shape,
+/// given with `--max-repeats <n>`. `None`, the default, leaves repeats unlimited.
+/// The bags and TGM randomizers already keep repeats short and ignore it.
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RepeatLimit(pub Option<u32>);
+
+/// Resource holding the randomizer of the current game, with whatever state it has built up,
+/// and the seeded random number generator it draws from.
+#[derive(Resource)]
+pub struct PieceGenerator {
+    randomizer: Box<dyn Randomizer + Send + Sync>,
+    rng: StdRng,
+}
+
+impl Default for PieceGenerator {
+    fn default() -> Self {
+        Self::new(
+            RandomizerKind::default(),
+            GameSeed::default(),
+            RepeatLimit::default(),
+        )
+    }
+}
+
+impl PieceGenerator {
+    /// Creates a fresh randomizer of the given kind, starting the sequence of the given seed.
+    pub fn new(kind: RandomizerKind, seed: GameSeed, repeat_limit: RepeatLimit) -> Self {
+        let randomizer: Box<dyn Randomizer + Send + Sync> = match kind {
+            RandomizerKind::Uniform => Box::new(Uniform::new(repeat_limit)),
+            RandomizerKind::SevenBag => Box::new(Bag::new(1)),
+            RandomizerKind::FourteenBag => Box::new(Bag::new(2)),
+            RandomizerKind::Tgm => Box::new(TgmHistory::default()),
+        };
+        Self {
+            randomizer,
+            rng: StdRng::seed_from_u64(seed.0.into()),
+        }
+    }
+
+    /// Returns the next piece from the randomizer.
+    pub fn next_shape(&mut self) -> Shape {
+        self.randomizer.next(&mut self.rng)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deals `count` pieces from a fresh generator of the given kind, always from the same seed.
+    fn deal(kind: RandomizerKind, repeat_limit: RepeatLimit, count: usize) -> Vec<Shape> {
+        let mut generator = PieceGenerator::new(kind, GameSeed(0x1234_5678), repeat_limit);
+        (0..count).map(|_| generator.next_shape()).collect()
+    }
+
+    #[test]
+    fn bags_deal_every_shape_equally_in_each_bag() {
+        for (kind, copies) in [
+            (RandomizerKind::SevenBag, 1),
+            (RandomizerKind::FourteenBag, 2),
+        ] {
+            for bag in deal(kind, RepeatLimit::default(), 70 * copies).chunks(7 * copies) {
+                for shape in Shape::ALL {
+                    let dealt = bag.iter().filter(|dealt| **dealt == shape).count();
+                    assert_eq!(dealt, copies, "{kind:?} dealt {shape:?} {dealt} times");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tgm_history_rarely_repeats_a_piece() {
+        let pieces = deal(RandomizerKind::Tgm, RepeatLimit::default(), 7000);
+        let repeats = pieces.windows(2).filter(|pair| pair[0] == pair[1]).count();
+        // Picking each piece on its own repeats about once every seven pieces
+        assert!(repeats < pieces.len() / 50, "{repeats} repeats");
+    }
+
+    #[test]
+    fn same_seed_deals_the_same_pieces() {
+        for kind in [
+            RandomizerKind::Uniform,
+            RandomizerKind::SevenBag,
+            RandomizerKind::FourteenBag,
+            RandomizerKind::Tgm,
+        ] {
+            let first = deal(kind, RepeatLimit::default(), 100);
+            assert_eq!(first, deal(kind, RepeatLimit::default(), 100), "{kind:?}");
+        }
+    }
+
+    #[test]
+    fn uniform_never_deals_more_repeats_than_the_limit() {
+        for limit in 1..=3 {
+            let pieces = deal(RandomizerKind::Uniform, RepeatLimit(Some(limit)), 10_000);
+            let longest_run = pieces.chunk_by(|a, b| a == b).map(<[Shape]>::len).max();
+            assert!(longest_run <= Some(limit as usize), "limit {limit}");
+        }
+    }
+
+    #[test]
+    fn seven_bag_never_goes_more_than_twelve_pieces_without_a_shape() {
+        let pieces = deal(RandomizerKind::SevenBag, RepeatLimit::default(), 7000);
+        for shape in Shape::ALL {
+            let dealt_at: Vec<usize> = pieces
+                .iter()
+                .enumerate()
+                .filter(|(_, dealt)| **dealt == shape)
+                .map(|(i, _)| i)
+                .collect();
+            assert!(dealt_at[0] < 7, "{shape:?} missing from the first bag");
+            let longest_drought = dealt_at.windows(2).map(|pair| pair[1] - pair[0] - 1).max();
+            assert!(longest_drought <= Some(12), "{shape:?}");
+        }
+    }
+}
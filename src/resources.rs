@@ -1,10 +1,101 @@
 use bevy::prelude::*;
-use crate::components::Shape;
+use std::{
+    collections::{HashMap, VecDeque},
+    time::Duration,
+};
+
+use crate::{
+    bindings::GameAction,
+    board::Board,
+    components::{GridPosition, Shape},
+    constants::{
+        BLOCK_SIZE, COUNTDOWN_STEPS, COUNTDOWN_STEP_SECONDS, GRAVITY_FRAMES, GRID_SIZE_X,
+        GRID_SIZE_Y, INPUT_BUFFER_SIZE, LOCK_DELAY_MAX_RESETS, LOCK_DELAY_SECONDS,
+        MAX_GRAVITY_LEVEL, MAX_STARTING_LEVEL, MIN_FALL_SECONDS, NEXT_QUEUE_SIZE,
+        QUEUE_PREVIEW_SCALE, QUEUE_PREVIEW_SPACING, SCREEN_TRANSITION_SECONDS, VOLUME_STEP,
+    },
+};
 
 /// A resource to control the speed at which tetrominoes fall.
 #[derive(Resource, Deref, DerefMut)]
 pub struct FallTimer(pub Timer);
 
+/// How long to wait, in seconds, between a piece locking and the next one appearing (ARE).
+/// Zero gives the modern instant feel.
+#[derive(Resource, Default)]
+pub struct EntryDelaySeconds(pub f32);
+
+/// A resource counting down the entry delay while in the `Spawning` state.
+#[derive(Resource, Deref, DerefMut, Default)]
+pub struct EntryDelayTimer(pub Timer);
+
+/// A resource timing the countdown before a game. It carries on where it was after a pause, and
+/// a new game starts it over.
+#[derive(Resource, Deref, DerefMut)]
+pub struct StartCountdown(pub Timer);
+
+impl Default for StartCountdown {
+    fn default() -> Self {
+        Self(Timer::from_seconds(
+            COUNTDOWN_STEP_SECONDS * COUNTDOWN_STEPS.len() as f32,
+            TimerMode::Once,
+        ))
+    }
+}
+
+impl StartCountdown {
+    /// Returns the word the countdown is showing.
+    pub fn label(&self) -> &'static str {
+        let step = (self.elapsed_secs() / COUNTDOWN_STEP_SECONDS) as usize;
+        COUNTDOWN_STEPS[step.min(COUNTDOWN_STEPS.len() - 1)]
+    }
+}
+
+/// A resource giving a landed piece a moment before it locks, so it can still be slid or
+/// turned into place. Each move or turn on the ground restarts the wait, up to
+/// `LOCK_DELAY_MAX_RESETS` times; reaching a new lowest row gives the resets back, so a piece
+/// can never be kept from locking forever.
+#[derive(Resource, Debug, Clone)]
+pub struct LockDelay {
+    pub timer: Timer,
+    /// Whether the piece was resting on something when gravity last looked.
+    pub grounded: bool,
+    pub resets: u32,
+    /// The lowest row the piece's bottom block has reached.
+    pub lowest_row: i32,
+}
+
+impl Default for LockDelay {
+    fn default() -> Self {
+        Self {
+            timer: Timer::from_seconds(LOCK_DELAY_SECONDS, TimerMode::Once),
+            grounded: false,
+            resets: 0,
+            lowest_row: i32::MAX,
+        }
+    }
+}
+
+impl LockDelay {
+    /// Restarts the wait after a move or turn of a grounded piece, unless it has been restarted
+    /// too often already.
+    pub fn piece_moved(&mut self) {
+        if self.grounded && self.resets < LOCK_DELAY_MAX_RESETS {
+            self.timer.reset();
+            self.resets += 1;
+        }
+    }
+
+    /// Notes the lowest row the piece now reaches, starting the count afresh on a new low.
+    pub fn follow(&mut self, bottom_row: i32) {
+        if bottom_row < self.lowest_row {
+            self.lowest_row = bottom_row;
+            self.resets = 0;
+            self.timer.reset();
+        }
+    }
+}
+
 /// A resource to track the player's score.
 #[derive(Resource)]
 pub struct Score(pub u32);
@@ -17,6 +108,965 @@ pub struct LinesCleared(pub u32);
 #[derive(Resource)]
 pub struct Level(pub u32);
 
+/// The level games start at, picked with the up and down arrows on the title screen. It is kept
+/// when a game is reset back to the title, and is not used by puzzles or training.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StartingLevel(pub u32);
+
+impl Default for StartingLevel {
+    fn default() -> Self {
+        Self(1)
+    }
+}
+
+impl StartingLevel {
+    /// Returns the starting level moved by the given number of levels, kept within 1 to
+    /// `MAX_STARTING_LEVEL`.
+    pub fn stepped(self, steps: i32) -> Self {
+        Self(self.0.saturating_add_signed(steps).clamp(1, MAX_STARTING_LEVEL))
+    }
+
+    /// Returns the level a game of the given mode starts at.
+    pub fn for_mode(self, game_mode: GameMode) -> u32 {
+        if game_mode.uses_puzzle_board() {
+            1
+        } else {
+            self.0
+        }
+    }
+}
+
+/// A board loaded from a board string, placed on the playfield whenever a game starts.
+#[derive(Resource, Default)]
+pub struct ImportedBoard(pub Option<Board>);
+
+/// A resource to track how many perfect clears in a row the player has made.
+/// A line clear that leaves blocks on the board resets it.
+#[derive(Resource)]
+pub struct AllClearStreak(pub u32);
+
+/// A resource counting the locks in a row that have cleared lines, less one: the first clear
+/// of a run makes it 0 and each clear after it adds one. A lock that clears nothing puts it
+/// back to -1, while holding a piece leaves it alone.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Combo(pub i32);
+
+impl Default for Combo {
+    fn default() -> Self {
+        Self(-1)
+    }
+}
+
+/// A resource tracking whether the last line clear was a difficult one, a tetris or a T-spin, so
+/// the next difficult clear earns the back-to-back bonus. Locks that clear nothing keep it.
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BackToBack(pub bool);
+
+/// The master volume of the game's audio, from 0.0 (silent) to 1.0 (full). It is changed with
+/// + and - on the title screen and in the pause menu, and kept in the settings file.
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct Volume(pub f32);
+
+impl Default for Volume {
+    fn default() -> Self {
+        Self(1.0)
+    }
+}
+
+impl Volume {
+    /// Returns the volume moved by the given number of `VOLUME_STEP`s, kept within 0 to 1.
+    pub fn stepped(self, steps: i32) -> Self {
+        let level = (self.0 / VOLUME_STEP).round() + steps as f32;
+        Self((level * VOLUME_STEP).clamp(0.0, 1.0))
+    }
+}
+
+/// Resource describing the piece that locked just before the current `Spawning` state, for the
+/// scoring that depends on how it got there. It is left empty when a hold started the state.
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LastLock {
+    pub shape: Option<Shape>,
+    /// Whether the last thing that moved the piece before it locked was a turn.
+    pub was_rotation: bool,
+    /// Whether it was a T piece turned into a spot with at least three of the four cells
+    /// diagonal to its center filled, or out of the playfield.
+    pub t_spin: bool,
+}
+
+/// Tuning for auto-repeating soft drop while the key is held, in seconds.
+/// Kept separate from any horizontal auto-shift so each can be tuned on its own.
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct SoftDropDas {
+    pub enabled: bool,
+    /// How long the key must be held before the repeat kicks in.
+    pub delay: f32,
+    /// Time between repeated steps once the repeat is active.
+    pub rate: f32,
+}
+
+impl Default for SoftDropDas {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            delay: 0.15,
+            rate: 0.05,
+        }
+    }
+}
+
+/// Tuning for Delayed Auto Shift: holding left or right moves the piece once, then again every
+/// `rate` seconds once the key has been held for `delay` seconds.
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct ShiftDas {
+    pub enabled: bool,
+    /// How long the key must be held before the repeat kicks in (DAS).
+    pub delay: f32,
+    /// Time between repeated shifts once the repeat is active (ARR).
+    pub rate: f32,
+}
+
+impl Default for ShiftDas {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            delay: 0.17,
+            rate: 0.05,
+        }
+    }
+}
+
+/// Tracks which way the piece is being auto-shifted, and for how long the key has been held.
+#[derive(Resource, Default)]
+pub struct ShiftRepeat {
+    /// -1 for left, 1 for right, or 0 when no direction is held.
+    pub direction: i32,
+    pub held_for: f32,
+    pub next_step_at: f32,
+}
+
+/// How many seconds the player may go without any input before the piece is hard dropped for
+/// them, so a game cannot be stalled by waiting. Off unless set with `--idle-drop <seconds>`.
+#[derive(Resource, Debug, Clone, Copy, Default)]
+pub struct IdleDrop(pub Option<f32>);
+
+/// Tracks how long soft drop has been held to drive its auto-repeat.
+#[derive(Resource, Default)]
+pub struct SoftDropRepeat {
+    pub held_for: f32,
+    pub next_step_at: f32,
+}
+
 /// Resource to hold the shape of the next piece to spawn
 #[derive(Resource, Clone, Copy)]
-pub struct NextPiece(pub Shape);
\ No newline at end of file
+pub struct NextPiece(pub Shape);
+
+/// Resource holding the piece put aside with the hold key. Each piece can only be held once, so
+/// holding cannot be used to skip through the sequence.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HoldPiece {
+    /// The shape sitting in the hold box.
+    pub shape: Option<Shape>,
+    /// The shape just held, which takes its place in the hold box once the next piece spawns.
+    pub pending: Option<Shape>,
+    /// Whether the falling piece can still be held.
+    pub can_hold: bool,
+}
+
+impl Default for HoldPiece {
+    fn default() -> Self {
+        Self {
+            shape: None,
+            pending: None,
+            can_hold: true,
+        }
+    }
+}
+
+impl HoldPiece {
+    /// Returns the shape to show in the hold box, counting a hold that is still waiting for the
+    /// next piece to spawn.
+    pub fn shown(&self) -> Option<Shape> {
+        self.pending.or(self.shape)
+    }
+}
+
+/// Resource holding the pieces queued up after the `NextPiece`, soonest first. Spawning tops it
+/// back up to `NEXT_QUEUE_SIZE - 1` pieces, and it is emptied whenever a new game starts.
+#[derive(Resource, Debug, Clone, Default, PartialEq, Eq)]
+pub struct NextQueue(pub VecDeque<Shape>);
+
+/// Resource holding pieces that must be handed out in order before random pieces resume.
+#[derive(Resource, Default)]
+pub struct ForcedSequence(pub VecDeque<Shape>);
+
+/// The rule set the current game is played with, chosen on the title screen.
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum GameMode {
+    /// The classic endless game.
+    #[default]
+    Marathon,
+    /// Garbage rises from the bottom on a timer regardless of line clears.
+    Survival,
+    /// A preset board has to be solved with a fixed sequence of pieces.
+    Puzzle,
+    /// Marathon where a game that would end can be rewound a few pieces, a few times a game.
+    Casual,
+    /// A saved position is played with its pieces, without a goal, and can be restarted at will.
+    Training,
+}
+
+impl GameMode {
+    /// Returns the mode that follows this one when cycling on the title screen.
+    pub fn next(self) -> Self {
+        match self {
+            GameMode::Marathon => GameMode::Survival,
+            GameMode::Survival => GameMode::Puzzle,
+            GameMode::Puzzle => GameMode::Casual,
+            GameMode::Casual => GameMode::Training,
+            GameMode::Training => GameMode::Marathon,
+        }
+    }
+
+    /// Returns the display name of the mode.
+    pub fn label(self) -> &'static str {
+        match self {
+            GameMode::Marathon => "Marathon",
+            GameMode::Survival => "Survival",
+            GameMode::Puzzle => "Puzzle",
+            GameMode::Casual => "Casual",
+            GameMode::Training => "Training",
+        }
+    }
+
+    /// Returns true for the modes that start from a bundled board and piece sequence.
+    pub fn uses_puzzle_board(self) -> bool {
+        matches!(self, GameMode::Puzzle | GameMode::Training)
+    }
+}
+
+/// Named presets for scoring and leveling, chosen on the title screen. Each one sets how many
+/// lines a level takes, the points for a line clear and how fast pieces fall at every level.
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RuleProfile {
+    /// The NES scoring table and its frame-based gravity, with a level every 10 lines.
+    Nintendo,
+    /// Modern guideline scoring and gravity, with a level every 10 lines.
+    Guideline,
+    /// Sega's steeper scoring table, with a level every 4 lines.
+    Sega,
+    /// This game's own tuning: NES scoring, a level every 5 lines and gently rising speed.
+    #[default]
+    Custom,
+}
+
+impl RuleProfile {
+    /// Returns the profile that follows this one when cycling on the title screen.
+    pub fn next(self) -> Self {
+        match self {
+            RuleProfile::Custom => RuleProfile::Nintendo,
+            RuleProfile::Nintendo => RuleProfile::Guideline,
+            RuleProfile::Guideline => RuleProfile::Sega,
+            RuleProfile::Sega => RuleProfile::Custom,
+        }
+    }
+
+    /// Returns the display name of the profile.
+    pub fn label(self) -> &'static str {
+        match self {
+            RuleProfile::Nintendo => "Nintendo",
+            RuleProfile::Guideline => "Guideline",
+            RuleProfile::Sega => "Sega",
+            RuleProfile::Custom => "Custom",
+        }
+    }
+
+    /// Returns how many cleared lines it takes to go up a level.
+    pub fn lines_per_level(self) -> u32 {
+        match self {
+            RuleProfile::Nintendo | RuleProfile::Guideline => 10,
+            RuleProfile::Sega => 4,
+            RuleProfile::Custom => 5,
+        }
+    }
+
+    /// Returns the points for clearing `rows` lines at once at the given level.
+    pub fn line_clear_points(self, rows: i32, level: u32) -> u32 {
+        let table = match self {
+            RuleProfile::Nintendo | RuleProfile::Custom => [40, 100, 300, 1200],
+            RuleProfile::Guideline => [100, 300, 500, 800],
+            RuleProfile::Sega => [100, 400, 900, 2000],
+        };
+        let base = match rows {
+            1..=4 => table[rows as usize - 1],
+            _ => 0,
+        };
+        // NES scoring multiplies by the level plus one, the others by the level itself
+        match self {
+            RuleProfile::Nintendo | RuleProfile::Custom => base * (level + 1),
+            RuleProfile::Guideline | RuleProfile::Sega => base * level,
+        }
+    }
+
+    /// Returns how many seconds a piece takes to fall one row at the given level.
+    /// It never drops below `MIN_FALL_SECONDS`, however high the level climbs.
+    pub fn fall_seconds(self, level: u32) -> f32 {
+        let level = level.max(1);
+        let seconds = match self {
+            RuleProfile::Nintendo => {
+                // Frames per row at 60 frames per second, from the NES level table
+                const FRAMES: [u32; 19] = [
+                    48, 43, 38, 33, 28, 23, 18, 13, 8, 6, 5, 5, 5, 4, 4, 4, 3, 3, 3,
+                ];
+                let frames = match FRAMES.get(level as usize - 1) {
+                    Some(frames) => *frames,
+                    None if level <= 29 => 2,
+                    None => 1,
+                };
+                frames as f32 / 60.0
+            }
+            RuleProfile::Guideline | RuleProfile::Custom => {
+                gravity_for_level(level).as_secs_f32()
+            }
+            RuleProfile::Sega => 0.8_f32.powf((level - 1) as f32).max(0.05),
+        };
+        seconds.max(MIN_FALL_SECONDS)
+    }
+}
+
+/// Returns how long a piece takes to fall one row at the given level on the guideline gravity
+/// curve, in whole frames at 60 frames per second. Levels past `MAX_GRAVITY_LEVEL` fall at its
+/// speed, so the time never reaches zero.
+pub fn gravity_for_level(level: u32) -> Duration {
+    let frames = GRAVITY_FRAMES[level.clamp(1, MAX_GRAVITY_LEVEL) as usize - 1];
+    Duration::from_secs_f64(f64::from(frames) / 60.0)
+}
+
+/// Which side of the playfield the HUD panels (next piece, hold) sit on.
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HudSide {
+    Left,
+    #[default]
+    Right,
+}
+
+impl HudSide {
+    /// Returns the opposite side.
+    pub fn flipped(self) -> Self {
+        match self {
+            HudSide::Left => HudSide::Right,
+            HudSide::Right => HudSide::Left,
+        }
+    }
+
+    /// Returns the display name of the side.
+    pub fn label(self) -> &'static str {
+        match self {
+            HudSide::Left => "Left",
+            HudSide::Right => "Right",
+        }
+    }
+
+    /// Returns -1.0 for the left side and 1.0 for the right, for mirroring x offsets.
+    pub fn sign(self) -> f32 {
+        match self {
+            HudSide::Left => -1.0,
+            HudSide::Right => 1.0,
+        }
+    }
+}
+
+/// How rotate and move presses landing in the same frame are resolved.
+/// Either way every press is applied in turn against the piece's current position, so a
+/// rotation is checked after the move that came before it. Drops always come last.
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InputPriority {
+    /// Moves are applied before rotations, so a piece can step off a wall and then rotate.
+    #[default]
+    MoveFirst,
+    /// Rotations are applied before moves. Set with `--rotate-first`.
+    RotateFirst,
+}
+
+impl InputPriority {
+    /// Returns where an action goes when ordering the presses of one frame; lower goes first.
+    /// Actions with the same rank keep the order they were pressed in.
+    pub fn rank(self, action: GameAction) -> u8 {
+        let rotates = action.quarter_turns() != 0;
+        match (self, action) {
+            (InputPriority::MoveFirst, GameAction::MoveLeft | GameAction::MoveRight) => 0,
+            (InputPriority::MoveFirst, _) if rotates => 1,
+            (InputPriority::RotateFirst, _) if rotates => 0,
+            (InputPriority::RotateFirst, GameAction::MoveLeft | GameAction::MoveRight) => 1,
+            (_, GameAction::SoftDrop) => 2,
+            _ => 3,
+        }
+    }
+}
+
+/// Resource holding the actions pressed while the next piece is on its way, to apply as soon
+/// as it spawns. Only the most recent `capacity` presses are kept, so a long entry delay cannot
+/// pile up moves; a capacity of 0 drops them all. Set with `--input-buffer <n>`.
+#[derive(Resource, Debug, Clone)]
+pub struct InputBuffer {
+    pub actions: VecDeque<GameAction>,
+    pub capacity: usize,
+    /// Whether pause was pressed meanwhile. It is kept whatever the capacity, and pauses the
+    /// game as soon as the piece is in.
+    pub pause: bool,
+}
+
+impl Default for InputBuffer {
+    fn default() -> Self {
+        Self::with_capacity(INPUT_BUFFER_SIZE)
+    }
+}
+
+impl InputBuffer {
+    /// Creates an empty buffer keeping up to `capacity` actions.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            actions: VecDeque::with_capacity(capacity),
+            capacity,
+            pause: false,
+        }
+    }
+
+    /// Queues an action, dropping the oldest one when the buffer is full.
+    pub fn push(&mut self, action: GameAction) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.actions.len() == self.capacity {
+            self.actions.pop_front();
+        }
+        self.actions.push_back(action);
+    }
+
+    /// Drops every buffered action and any buffered pause.
+    pub fn clear(&mut self) {
+        self.actions.clear();
+        self.pause = false;
+    }
+}
+
+/// Resource driving the short fade from black shown whenever the game changes screens.
+/// Turned off with `--no-transitions`.
+#[derive(Resource)]
+pub struct ScreenTransition {
+    pub enabled: bool,
+    pub timer: Timer,
+}
+
+impl Default for ScreenTransition {
+    fn default() -> Self {
+        let mut timer = Timer::from_seconds(SCREEN_TRANSITION_SECONDS, TimerMode::Once);
+        timer.tick(timer.duration());
+        Self {
+            enabled: true,
+            timer,
+        }
+    }
+}
+
+impl ScreenTransition {
+    /// Returns true while a fade is playing.
+    pub fn is_running(&self) -> bool {
+        !self.timer.finished()
+    }
+
+    /// Starts a fade, unless transitions are turned off.
+    pub fn start(&mut self) {
+        if self.enabled {
+            self.timer.reset();
+        }
+    }
+
+    /// Ends the current fade straight away.
+    pub fn skip(&mut self) {
+        let remaining = self.timer.remaining();
+        self.timer.tick(remaining);
+    }
+}
+
+/// Resource that lets gravity be frozen from debug mode, so the piece stays put while it is
+/// moved and rotated by hand.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GravityEnabled(pub bool);
+
+impl Default for GravityEnabled {
+    fn default() -> Self {
+        Self(true)
+    }
+}
+
+/// Resource for the optional rule that a game never opens with an S or Z, whose overhang makes
+/// for an awkward start. Off by default; turned on with `--no-overhang-start`.
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NoOverhangStart(pub bool);
+
+/// Which way the remaining rows move when lines are cleared.
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CollapseDirection {
+    /// Rows above a cleared line fall down into the gap, as in normal Tetris.
+    #[default]
+    Down,
+    /// Rows below a cleared line are pushed up into the gap, for inverted-gravity variants.
+    Up,
+}
+
+/// Resource scaling every point awarded, so players of different skill can share a leaderboard.
+/// Set with `--score-multiplier <x>`; 1.0 leaves scoring unchanged.
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct ScoreMultiplier(pub f32);
+
+impl Default for ScoreMultiplier {
+    fn default() -> Self {
+        Self(1.0)
+    }
+}
+
+impl ScoreMultiplier {
+    /// Scales a number of points, rounding to the nearest whole point.
+    pub fn apply(self, points: u32) -> u32 {
+        (points as f32 * self.0).round() as u32
+    }
+}
+
+/// The colors the game is drawn with, chosen on the title screen.
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorScheme {
+    /// Glowing, slightly translucent blocks with bloom.
+    #[default]
+    Standard,
+    /// Flat, fully opaque colors without bloom, which hold up better on stream captures.
+    HighContrast,
+}
+
+impl ColorScheme {
+    /// Returns the other color scheme.
+    pub fn toggled(self) -> Self {
+        match self {
+            ColorScheme::Standard => ColorScheme::HighContrast,
+            ColorScheme::HighContrast => ColorScheme::Standard,
+        }
+    }
+
+    /// Returns the display name of the color scheme.
+    pub fn label(self) -> &'static str {
+        match self {
+            ColorScheme::Standard => "Standard",
+            ColorScheme::HighContrast => "High contrast",
+        }
+    }
+
+    /// Returns whether the cameras should render bloom.
+    pub fn bloom(self) -> bool {
+        self == ColorScheme::Standard
+    }
+}
+
+/// A resource to control how often garbage rises in survival mode.
+#[derive(Resource, Deref, DerefMut)]
+pub struct GarbageTimer(pub Timer);
+
+/// Resource describing how the board is laid out in world space.
+/// Every system that converts grid coordinates to world coordinates reads from this,
+/// so changing it at runtime re-centers the whole board.
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct BoardMetrics {
+    pub block_size: f32,
+}
+
+impl Default for BoardMetrics {
+    fn default() -> Self {
+        Self {
+            block_size: BLOCK_SIZE,
+        }
+    }
+}
+
+impl BoardMetrics {
+    /// Converts a grid position to the world position of the center of that cell.
+    pub fn grid_to_world(&self, position: GridPosition) -> Vec2 {
+        Vec2::new(
+            (position.x as f32 - (GRID_SIZE_X as f32 / 2.0) + 0.5) * self.block_size,
+            (position.y as f32 - (GRID_SIZE_Y as f32 / 2.0) + 0.5) * self.block_size,
+        )
+    }
+
+    /// Returns the world rectangle covering the full width of the board from row `bottom` up to
+    /// and including row `top`.
+    pub fn rows_rect(&self, bottom: i32, top: i32) -> Rect {
+        let half_block = Vec2::splat(self.block_size / 2.0);
+        Rect::from_corners(
+            self.grid_to_world(GridPosition { x: 0, y: bottom }) - half_block,
+            self.grid_to_world(GridPosition {
+                x: GRID_SIZE_X - 1,
+                y: top,
+            }) + half_block,
+        )
+    }
+
+    /// Returns the board column a world x coordinate falls in. Positions off the side of the
+    /// board give columns outside the playfield.
+    pub fn world_to_column(&self, world_x: f32) -> i32 {
+        (world_x / self.block_size + GRID_SIZE_X as f32 / 2.0).floor() as i32
+    }
+}
+
+/// Resource scaling the preview blocks relative to the board blocks, so pieces sit well inside
+/// the preview box in cramped layouts. Set with `--preview-scale <x>`; 1.0 matches the board.
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct PreviewScale(pub f32);
+
+impl Default for PreviewScale {
+    fn default() -> Self {
+        Self(1.0)
+    }
+}
+
+/// Resource describing where the next piece preview box sits in world space.
+/// It is derived from the `BoardMetrics` so the box always stays clear of the playfield.
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct PreviewLayout {
+    pub center: Vec2,
+    pub size: Vec2,
+    /// Where the next piece is drawn, at the top of the preview box.
+    pub next_center: Vec2,
+    /// Where the first piece of the queue is drawn; the rest follow below it.
+    pub queue_top: Vec2,
+    /// How far apart the queued pieces are drawn.
+    pub queue_spacing: f32,
+    /// The center and size of the hold box, which sits below the preview box.
+    pub hold_center: Vec2,
+    pub hold_size: Vec2,
+    /// The size of each preview block, and of each block of the queued pieces.
+    pub block_size: f32,
+    pub queue_block_size: f32,
+}
+
+impl PreviewLayout {
+    /// Places the preview box just beside the playfield on the HUD side, from near its top,
+    /// with the hold box under it.
+    pub fn from_metrics(
+        metrics: &BoardMetrics,
+        hud_side: HudSide,
+        preview_scale: PreviewScale,
+    ) -> Self {
+        let block = metrics.block_size;
+        let x = hud_side.sign() * (GRID_SIZE_X as f32 / 2.0 + 3.5) * block;
+        let top = (GRID_SIZE_Y as f32 / 2.0 - 2.5) * block;
+        let queue_spacing = QUEUE_PREVIEW_SPACING * block;
+        // The next piece gets a full-size slot, and each queued piece a smaller one below it
+        let height = 5.0 * block + (NEXT_QUEUE_SIZE - 1) as f32 * queue_spacing;
+        let hold_size = Vec2::new(6.0 * block, 5.0 * block);
+        Self {
+            center: Vec2::new(x, top - height / 2.0),
+            size: Vec2::new(6.0 * block, height),
+            next_center: Vec2::new(x, top - 2.5 * block),
+            queue_top: Vec2::new(x, top - 5.0 * block - queue_spacing / 2.0),
+            queue_spacing,
+            // Far enough down to leave room for the hold label between the boxes
+            hold_center: Vec2::new(x, top - height - 1.5 * block - hold_size.y / 2.0),
+            hold_size,
+            block_size: block * preview_scale.0,
+            queue_block_size: block * preview_scale.0 * QUEUE_PREVIEW_SCALE,
+        }
+    }
+}
+
+impl Default for PreviewLayout {
+    fn default() -> Self {
+        Self::from_metrics(
+            &BoardMetrics::default(),
+            HudSide::default(),
+            PreviewScale::default(),
+        )
+    }
+}
+
+/// Why the last game ended, shown on the game over screen.
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GameOverReason {
+    /// The stack was left over the top of the playfield, by a lock or by rising garbage.
+    #[default]
+    TopOut,
+    /// A newly spawned piece overlapped a block.
+    BlockOut,
+    /// A piece locked entirely above the playfield.
+    LockOut,
+    /// The piece spawned clear but could not come to rest anywhere inside the playfield.
+    Stalemate,
+}
+
+impl GameOverReason {
+    /// Returns a short explanation of the reason for the game over screen.
+    pub fn description(self) -> &'static str {
+        match self {
+            GameOverReason::TopOut => "Top out: the stack reached over the top",
+            GameOverReason::BlockOut => "Block out: the next piece had no room to spawn",
+            GameOverReason::LockOut => "Lock out: the piece locked above the playfield",
+            GameOverReason::Stalemate => "Stalemate: the piece had nowhere to go",
+        }
+    }
+}
+
+/// How many seconds of play raise the level by one, on top of the lines cleared. The level is
+/// whichever of the two is higher. Off unless set with `--level-seconds <n>`.
+#[derive(Resource, Debug, Clone, Copy, Default)]
+pub struct TimedLevels(pub Option<f32>);
+
+/// Resource tracking how long, in seconds, the current game has spent in the `Playing` state.
+/// Pauses and the entry delay between pieces do not count.
+#[derive(Resource, Default)]
+pub struct PlayTime(pub f32);
+
+/// Resource counting the player's actions and locked pieces in the current game.
+#[derive(Resource, Default)]
+pub struct PlayCounters {
+    /// Key presses that moved, rotated or dropped the piece, whether or not it could move.
+    pub actions: u32,
+    pub pieces: u32,
+}
+
+/// Resource holding the actions per minute and pieces per second over the current game,
+/// refreshed every `EFFICIENCY_UPDATE_SECONDS` of play.
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq)]
+pub struct Efficiency {
+    pub apm: f32,
+    pub pps: f32,
+}
+
+impl Efficiency {
+    /// Works out the rates from the counters over the time played so far.
+    pub fn measure(counters: &PlayCounters, play_time: &PlayTime) -> Self {
+        if play_time.0 <= 0.0 {
+            return Self::default();
+        }
+        Self {
+            apm: counters.actions as f32 * 60.0 / play_time.0,
+            pps: counters.pieces as f32 / play_time.0,
+        }
+    }
+
+    /// Returns the rates as shown in the HUD and on the game over screen.
+    pub fn label(self) -> String {
+        format!("APM: {:.1}  PPS: {:.2}", self.apm, self.pps)
+    }
+}
+
+/// Whether the game is drawn at a fixed virtual resolution, letterboxed to the window, instead
+/// of resizing the blocks to fit it. Turned on with `--letterbox`.
+#[derive(Resource, Default)]
+pub struct Letterbox(pub bool);
+
+/// Whether pieces can also be played with a mouse or touchscreen, turned on with `--pointer`.
+#[derive(Resource, Default)]
+pub struct PointerInput(pub bool);
+
+/// The image drawn behind the playfield, loaded from `--backdrop <png>`. It is taken out and
+/// turned into the backdrop sprite when the game starts up.
+#[derive(Resource, Default)]
+pub struct BackdropImage(pub Option<Image>);
+
+/// Whether the HUD shows the live APM and PPS, turned on with `--stats`.
+#[derive(Resource, Default)]
+pub struct ShowEfficiency(pub bool);
+
+/// Whether a ghost of the falling piece is drawn where it will land. On unless the game is
+/// started with `--no-ghost`.
+#[derive(Resource)]
+pub struct GhostPieceEnabled(pub bool);
+
+/// Whether the background rows the falling piece would land in are brightened, a lighter aid
+/// than the ghost piece that can be used with or instead of it. Turned on with `--landing-rows`.
+#[derive(Resource, Default)]
+pub struct LandingHighlight(pub bool);
+
+/// Whether the whole grid pulses brighter for a moment after a line clear, more for bigger
+/// clears. Turned on with `--grid-pulse`.
+#[derive(Resource, Default)]
+pub struct GridPulseEnabled(pub bool);
+
+/// Whether holding shows the piece shrinking into the hold box and the held piece growing back
+/// out. On unless the game is started with `--no-hold-animation`.
+#[derive(Resource)]
+pub struct HoldAnimation(pub bool);
+
+/// Whether a tick sounds while a landed piece waits to lock, faster as the lock nears. Turned on
+/// with `--lock-ticks`.
+#[derive(Resource, Default)]
+pub struct LockTicks(pub bool);
+
+/// Whether the background music plays on without a break through game over and back to the
+/// title screen, instead of stopping at game over and starting over on the title screen. Turned
+/// on with `--keep-music`.
+#[derive(Resource, Default)]
+pub struct KeepMusic(pub bool);
+
+/// Whether the scoreboard pads its numbers with zeros to a fixed number of digits, like the
+/// classic games, so the HUD keeps its width as they grow. Turned on with `--classic-score`.
+#[derive(Resource, Debug, Clone, Copy, Default)]
+pub struct ClassicScore(pub bool);
+
+impl ClassicScore {
+    /// Formats a scoreboard number, zero-padded to `digits` in the classic style.
+    pub fn format(self, value: u32, digits: usize) -> String {
+        if self.0 {
+            format!("{value:0digits$}")
+        } else {
+            value.to_string()
+        }
+    }
+}
+
+/// The best results of the games finished in one mode.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PersonalBests {
+    pub games_played: u32,
+    pub best_score: u32,
+    pub best_lines: u32,
+    pub best_level: u32,
+}
+
+/// Resource holding the best results of the games finished this session, kept apart for each
+/// mode so a Survival run is never measured against Marathon scores. It is only updated when a
+/// game ends, so a run in progress is always compared against finished games.
+#[derive(Resource, Default)]
+pub struct LifetimeStats(HashMap<GameMode, PersonalBests>);
+
+impl LifetimeStats {
+    /// Returns the bests of the given mode, all zero if no game of it was finished yet.
+    pub fn bests(&self, game_mode: GameMode) -> PersonalBests {
+        self.0.get(&game_mode).copied().unwrap_or_default()
+    }
+
+    /// Files a finished game under its mode, keeping the highest score, lines and level.
+    pub fn record(&mut self, game_mode: GameMode, score: u32, lines: u32, level: u32) {
+        let bests = self.0.entry(game_mode).or_default();
+        bests.games_played += 1;
+        bests.best_score = bests.best_score.max(score);
+        bests.best_lines = bests.best_lines.max(lines);
+        bests.best_level = bests.best_level.max(level);
+    }
+
+    /// Forgets the bests of the given mode, leaving the other modes alone.
+    pub fn reset(&mut self, game_mode: GameMode) {
+        self.0.remove(&game_mode);
+    }
+}
+
+/// Whether the title screen is waiting for X to be pressed again to wipe the bests of the
+/// selected mode.
+#[derive(Resource, Default)]
+pub struct StatsResetPending(pub bool);
+
+/// Resource recording which personal bests the current run has already beaten, so each one is
+/// announced only once per run.
+#[derive(Resource, Default)]
+pub struct NewBests {
+    pub score: bool,
+    pub lines: bool,
+    pub level: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the frames at 60 frames per second a piece takes to fall one row at a level.
+    fn gravity_frames(level: u32) -> u32 {
+        (gravity_for_level(level).as_secs_f64() * 60.0).round() as u32
+    }
+
+    #[test]
+    fn gravity_follows_the_guideline_table() {
+        assert_eq!(gravity_frames(1), 60);
+        assert_eq!(gravity_frames(9), 6);
+        assert_eq!(gravity_frames(20), 1);
+    }
+
+    #[test]
+    fn gravity_never_slows_down() {
+        for level in 1..MAX_GRAVITY_LEVEL + 10 {
+            assert!(gravity_for_level(level + 1) <= gravity_for_level(level));
+        }
+        assert_eq!(gravity_for_level(0), gravity_for_level(1));
+    }
+
+    #[test]
+    fn fall_speed_stays_within_one_row_a_frame_at_any_level() {
+        let mut profile = RuleProfile::default();
+        for _ in 0..4 {
+            for level in [1, 15, 29, 30, MAX_GRAVITY_LEVEL, 100, u32::MAX] {
+                assert!(profile.fall_seconds(level) >= MIN_FALL_SECONDS);
+            }
+            profile = profile.next();
+        }
+    }
+
+    #[test]
+    fn score_multiplier_rounds_to_whole_points() {
+        assert_eq!(ScoreMultiplier::default().apply(123), 123);
+        assert_eq!(ScoreMultiplier(1.5).apply(100), 150);
+        assert_eq!(ScoreMultiplier(1.5).apply(5), 8);
+        assert_eq!(ScoreMultiplier(0.5).apply(3), 2);
+        assert_eq!(ScoreMultiplier(0.0).apply(800), 0);
+    }
+
+    #[test]
+    fn pointer_x_maps_to_the_column_under_it() {
+        for block_size in [BLOCK_SIZE, 12.0, 47.5] {
+            let metrics = BoardMetrics { block_size };
+            for x in 0..GRID_SIZE_X {
+                let center = metrics.grid_to_world(GridPosition { x, y: 0 }).x;
+                assert_eq!(metrics.world_to_column(center), x);
+                // Anywhere within the cell, up to just short of its right edge
+                assert_eq!(metrics.world_to_column(center - block_size * 0.5), x);
+                assert_eq!(metrics.world_to_column(center + block_size * 0.49), x);
+            }
+            // Past either side of the board the columns carry on outside the playfield
+            let left_edge = metrics.rows_rect(0, 0).min.x;
+            assert_eq!(metrics.world_to_column(left_edge - 1.0), -1);
+            assert_eq!(metrics.world_to_column(-left_edge + 1.0), GRID_SIZE_X);
+        }
+    }
+
+    #[test]
+    fn each_mode_keeps_its_own_bests() {
+        let mut lifetime_stats = LifetimeStats::default();
+        lifetime_stats.record(GameMode::Marathon, 12000, 40, 5);
+        lifetime_stats.record(GameMode::Survival, 3000, 60, 7);
+        // A weaker Marathon game takes none of the bests, a stronger one only those it beats
+        lifetime_stats.record(GameMode::Marathon, 8000, 30, 4);
+        lifetime_stats.record(GameMode::Marathon, 15000, 35, 6);
+
+        assert_eq!(
+            lifetime_stats.bests(GameMode::Marathon),
+            PersonalBests {
+                games_played: 3,
+                best_score: 15000,
+                best_lines: 40,
+                best_level: 6,
+            }
+        );
+        assert_eq!(
+            lifetime_stats.bests(GameMode::Survival),
+            PersonalBests {
+                games_played: 1,
+                best_score: 3000,
+                best_lines: 60,
+                best_level: 7,
+            }
+        );
+        assert_eq!(
+            lifetime_stats.bests(GameMode::Casual),
+            PersonalBests::default()
+        );
+
+        // Resetting one mode leaves the others alone
+        lifetime_stats.reset(GameMode::Marathon);
+        assert_eq!(lifetime_stats.bests(GameMode::Marathon).best_score, 0);
+        assert_eq!(lifetime_stats.bests(GameMode::Survival).best_score, 3000);
+    }
+}
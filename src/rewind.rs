@@ -0,0 +1,274 @@
+use bevy::prelude::*;
+use std::collections::VecDeque;
+
+use crate::{
+    atlas::BlockAtlas,
+    board::{Board, Cell, spawn_blocks, spawn_board_blocks},
+    components::{GridPosition, Shape, Tetromino},
+    constants::{GRID_SIZE_Y, REWIND_HISTORY_PIECES, REWINDS_PER_GAME},
+    game_state::GameState,
+    resources::{
+        AllClearStreak, BackToBack, BoardMetrics, ColorScheme, Combo, GameMode, GameOverReason,
+        HoldPiece, Level, LinesCleared, NextPiece, NextQueue, Score,
+    },
+};
+
+/// Everything needed to put a game back to how it was just before a piece spawned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameSnapshot {
+    pub board: Board,
+    /// The settled blocks in the buffer rows above the playfield, which the board leaves out.
+    pub buffer_blocks: Vec<(GridPosition, Cell)>,
+    /// The piece that was about to spawn.
+    pub next_piece: Shape,
+    /// The pieces queued up after it.
+    pub next_queue: VecDeque<Shape>,
+    pub hold_piece: HoldPiece,
+    pub score: u32,
+    pub lines_cleared: u32,
+    pub level: u32,
+    pub all_clear_streak: u32,
+    pub combo: Combo,
+    pub back_to_back: BackToBack,
+}
+
+/// Resource holding a snapshot from the start of each of the last few pieces, oldest first.
+/// Only the last `REWIND_HISTORY_PIECES` snapshots are kept.
+#[derive(Resource, Default)]
+pub struct RewindHistory(pub VecDeque<GameSnapshot>);
+
+impl RewindHistory {
+    /// Records a snapshot, dropping the oldest one when the history is full.
+    pub fn push(&mut self, snapshot: GameSnapshot) {
+        if self.0.len() == REWIND_HISTORY_PIECES {
+            self.0.pop_front();
+        }
+        self.0.push_back(snapshot);
+    }
+
+    /// Returns the snapshot a rewind goes back to: the oldest one kept. The newest snapshot is
+    /// taken at the start of the piece that ended the game, so a rewind always has to go back
+    /// further than that.
+    pub fn rewind_target(&self) -> Option<&GameSnapshot> {
+        if self.0.len() > 1 {
+            self.0.front()
+        } else {
+            None
+        }
+    }
+}
+
+/// Resource counting the rewinds the player has left in the current casual game.
+#[derive(Resource)]
+pub struct RewindsLeft(pub u32);
+
+impl Default for RewindsLeft {
+    fn default() -> Self {
+        Self(REWINDS_PER_GAME)
+    }
+}
+
+/// Returns true when the game that just ended can be rewound.
+pub fn can_rewind(
+    game_mode: GameMode,
+    rewinds_left: &RewindsLeft,
+    history: &RewindHistory,
+) -> bool {
+    game_mode == GameMode::Casual && rewinds_left.0 > 0 && history.rewind_target().is_some()
+}
+
+/// A system that snapshots the game in casual mode before each piece spawns, once full rows
+/// have been cleared.
+#[allow(clippy::too_many_arguments)]
+pub fn record_snapshot(
+    game_mode: Res<GameMode>,
+    block_query: Query<(&GridPosition, Option<&Shape>), Without<Tetromino>>,
+    next_piece: Res<NextPiece>,
+    next_queue: Res<NextQueue>,
+    hold_piece: Res<HoldPiece>,
+    score: Res<Score>,
+    lines_cleared: Res<LinesCleared>,
+    level: Res<Level>,
+    all_clear_streak: Res<AllClearStreak>,
+    combo: Res<Combo>,
+    back_to_back: Res<BackToBack>,
+    mut history: ResMut<RewindHistory>,
+) {
+    if *game_mode != GameMode::Casual {
+        return;
+    }
+
+    let blocks = block_query
+        .iter()
+        .map(|(position, shape)| (*position, Cell::from_shape(shape)));
+    history.push(GameSnapshot {
+        board: Board::from_blocks(blocks.clone()),
+        buffer_blocks: blocks
+            .filter(|(position, _)| position.y >= GRID_SIZE_Y)
+            .collect(),
+        next_piece: next_piece.0,
+        next_queue: next_queue.0.clone(),
+        hold_piece: *hold_piece,
+        score: score.0,
+        lines_cleared: lines_cleared.0,
+        level: level.0,
+        all_clear_streak: all_clear_streak.0,
+        combo: *combo,
+        back_to_back: *back_to_back,
+    });
+}
+
+/// A system that rewinds a finished casual game to the oldest kept snapshot and carries on
+/// playing from there, using up one rewind. The history is cleared, so the next rewind can
+/// only go back as far as the pieces played since.
+#[allow(clippy::too_many_arguments)]
+pub fn rewind_game(
+    mut commands: Commands,
+    game_mode: Res<GameMode>,
+    mut rewinds_left: ResMut<RewindsLeft>,
+    mut history: ResMut<RewindHistory>,
+    metrics: Res<BoardMetrics>,
+    color_scheme: Res<ColorScheme>,
+    block_atlas: Res<BlockAtlas>,
+    block_query: Query<Entity, With<GridPosition>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if !can_rewind(*game_mode, &rewinds_left, &history) {
+        return;
+    }
+    let Some(snapshot) = history.0.pop_front() else {
+        return;
+    };
+    history.0.clear();
+    rewinds_left.0 -= 1;
+    info!("Rewinding, {} rewind(s) left", rewinds_left.0);
+
+    for entity in block_query.iter() {
+        commands.entity(entity).try_despawn();
+    }
+    spawn_board_blocks(
+        &mut commands,
+        &metrics,
+        &block_atlas,
+        *color_scheme,
+        &snapshot.board,
+    );
+    spawn_blocks(
+        &mut commands,
+        &metrics,
+        &block_atlas,
+        *color_scheme,
+        snapshot.buffer_blocks,
+    );
+    commands.insert_resource(NextPiece(snapshot.next_piece));
+    commands.insert_resource(NextQueue(snapshot.next_queue));
+    commands.insert_resource(snapshot.hold_piece);
+    commands.insert_resource(Score(snapshot.score));
+    commands.insert_resource(LinesCleared(snapshot.lines_cleared));
+    commands.insert_resource(Level(snapshot.level));
+    commands.insert_resource(AllClearStreak(snapshot.all_clear_streak));
+    commands.insert_resource(snapshot.combo);
+    commands.insert_resource(snapshot.back_to_back);
+    commands.insert_resource(GameOverReason::default());
+    next_state.set(GameState::Spawning);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+
+    use crate::game_logic::tests::insert_scoring_resources;
+
+    /// Returns the settled blocks, sorted by column and row.
+    fn settled_cells(world: &mut World) -> Vec<(i32, i32)> {
+        let mut cells: Vec<(i32, i32)> = world
+            .query::<&GridPosition>()
+            .iter(world)
+            .map(|position| (position.x, position.y))
+            .collect();
+        cells.sort_unstable();
+        cells
+    }
+
+    fn snapshot_with_score(score: u32) -> GameSnapshot {
+        GameSnapshot {
+            board: Board::default(),
+            buffer_blocks: Vec::new(),
+            next_piece: Shape::T,
+            next_queue: VecDeque::new(),
+            hold_piece: HoldPiece::default(),
+            score,
+            lines_cleared: 0,
+            level: 1,
+            all_clear_streak: 0,
+            combo: Combo::default(),
+            back_to_back: BackToBack::default(),
+        }
+    }
+
+    #[test]
+    fn rewind_restores_the_board_before_the_death() {
+        let mut world = World::new();
+        insert_scoring_resources(&mut world);
+        world.insert_resource(GameMode::Casual);
+        world.insert_resource(NextPiece(Shape::L));
+        world.init_resource::<NextQueue>();
+        world.init_resource::<RewindHistory>();
+        world.init_resource::<RewindsLeft>();
+        world.init_resource::<BoardMetrics>();
+        world.init_resource::<ColorScheme>();
+        world.init_resource::<BlockAtlas>();
+        world.init_resource::<NextState<GameState>>();
+
+        // Two pieces in: a block on the floor, one reaching into the buffer rows and an O held
+        world.spawn((GridPosition { x: 0, y: 0 }, Shape::I));
+        world.spawn((
+            GridPosition {
+                x: 9,
+                y: GRID_SIZE_Y,
+            },
+            Shape::J,
+        ));
+        world.insert_resource(Score(100));
+        world.insert_resource(HoldPiece {
+            shape: Some(Shape::O),
+            pending: None,
+            can_hold: true,
+        });
+        world.run_system_once(record_snapshot).unwrap();
+        let before = settled_cells(&mut world);
+
+        // The next piece piles up and ends the game
+        for y in 1..GRID_SIZE_Y {
+            world.spawn(GridPosition { x: 4, y });
+        }
+        world.insert_resource(Score(900));
+        world.insert_resource(HoldPiece::default());
+        world.run_system_once(record_snapshot).unwrap();
+
+        world.run_system_once(rewind_game).unwrap();
+        assert_eq!(settled_cells(&mut world), before);
+        assert_eq!(world.resource::<Score>().0, 100);
+        assert_eq!(world.resource::<HoldPiece>().shape, Some(Shape::O));
+        assert_eq!(world.resource::<RewindsLeft>().0, REWINDS_PER_GAME - 1);
+        assert!(world.resource::<RewindHistory>().0.is_empty());
+        assert!(matches!(
+            world.resource::<NextState<GameState>>(),
+            NextState::Pending(GameState::Spawning)
+        ));
+    }
+
+    #[test]
+    fn history_keeps_only_the_last_pieces() {
+        let mut history = RewindHistory::default();
+        for score in 0..REWIND_HISTORY_PIECES as u32 + 2 {
+            history.push(snapshot_with_score(score));
+        }
+        assert_eq!(history.0.len(), REWIND_HISTORY_PIECES);
+        assert_eq!(
+            history.rewind_target().map(|snapshot| snapshot.score),
+            Some(2)
+        );
+    }
+}
@@ -1,53 +1,380 @@
 use bevy::prelude::*;
 
-use crate::constants::{BLOCK_SIZE, GRID_SIZE_X, GRID_SIZE_Y};
+use bevy::{
+    core_pipeline::bloom::Bloom,
+    render::{
+        camera::{ScalingMode, Viewport},
+        view::RenderLayers,
+    },
+    window::{PrimaryWindow, WindowResized},
+};
+
+use crate::{
+    atlas::BlockAtlas,
+    board::spawn_board_blocks,
+    components::{
+        Backdrop, BackgroundMusic, GridBackground, LandingRow, LetterboxBars, TitleScreen,
+    },
+    constants::{
+        BACKDROP_TINT, BLOCK_SIZE, GAME_RENDER_LAYER, GRID_SIZE_X, GRID_SIZE_Y, SETTINGS_FILE,
+        VIRTUAL_HEIGHT, VIRTUAL_WIDTH,
+    },
+    resources::{
+        BackdropImage, BoardMetrics, ColorScheme, GameMode, ImportedBoard, KeepMusic, Letterbox,
+        Volume,
+    },
+};
+
+/// Filter matching the cameras that draw the game, leaving out the letterbox bars camera.
+pub type GameCameraFilter = (With<Camera2d>, Without<LetterboxBars>);
 
 /// A startup system to spawn a 2D camera and the UI text.
-pub fn setup_camera(mut commands: Commands) {
+pub fn setup_camera(mut commands: Commands, letterbox: Res<Letterbox>) {
     // Spawn the camera.
-    commands.spawn((
+    let mut camera = commands.spawn((
         Camera2d::default(),
         Camera {
             hdr: true,
             ..default()
         },
         bevy::core_pipeline::bloom::Bloom::default(),
+        RenderLayers::layer(GAME_RENDER_LAYER),
+    ));
+
+    // Letterboxing shows exactly the virtual resolution in world units, whatever the window size.
+    // A second camera behind the game clears the bars around its viewport to black.
+    if letterbox.0 {
+        camera.insert(Projection::from(OrthographicProjection {
+            scaling_mode: ScalingMode::Fixed {
+                width: VIRTUAL_WIDTH,
+                height: VIRTUAL_HEIGHT,
+            },
+            ..OrthographicProjection::default_2d()
+        }));
+        commands.spawn((
+            Camera2d::default(),
+            Camera {
+                order: -1,
+                clear_color: ClearColorConfig::Custom(Color::BLACK),
+                ..default()
+            },
+            // Only clears; the game is drawn by the camera above, inside its viewport
+            RenderLayers::none(),
+            LetterboxBars,
+        ));
+    }
+    info!("Camera set up successfully!");
+}
+
+/// Returns the position and size, in physical pixels, of the largest area with the aspect ratio
+/// of the virtual resolution that fits centered in a window of the given size.
+pub fn letterbox_rect(window_size: UVec2) -> (UVec2, UVec2) {
+    let scale = (window_size.x as f32 / VIRTUAL_WIDTH).min(window_size.y as f32 / VIRTUAL_HEIGHT);
+    let size = (Vec2::new(VIRTUAL_WIDTH, VIRTUAL_HEIGHT) * scale)
+        .round()
+        .as_uvec2()
+        .min(window_size);
+    ((window_size - size) / 2, size)
+}
+
+/// A system that fits the game camera's viewport to the window with letterboxing, and scales
+/// the UI by the same amount so the HUD keeps its place relative to the board.
+pub fn apply_letterbox(
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    mut camera_query: Query<&mut Camera, GameCameraFilter>,
+    mut ui_scale: ResMut<UiScale>,
+) {
+    let Ok(window) = window_query.single() else {
+        return;
+    };
+    let window_size = window.physical_size();
+    // A minimized window has no room for a viewport
+    if window_size.x == 0 || window_size.y == 0 {
+        return;
+    }
+
+    let (position, size) = letterbox_rect(window_size);
+    for mut camera in camera_query.iter_mut() {
+        let current = camera
+            .viewport
+            .as_ref()
+            .map(|viewport| (viewport.physical_position, viewport.physical_size));
+        if current != Some((position, size)) {
+            camera.viewport = Some(Viewport {
+                physical_position: position,
+                physical_size: size,
+                ..default()
+            });
+        }
+    }
+    let scale = size.y as f32 / window.scale_factor() / VIRTUAL_HEIGHT;
+    if ui_scale.0 != scale {
+        ui_scale.0 = scale;
+    }
+}
+
+/// A system that keeps bloom on every game camera in line with the color scheme.
+/// The title screen camera draws over the game camera's output, so it never blooms it again.
+pub fn sync_camera_bloom(
+    mut commands: Commands,
+    color_scheme: Res<ColorScheme>,
+    camera_query: Query<(Entity, Has<Bloom>), (GameCameraFilter, Without<TitleScreen>)>,
+) {
+    for (entity, has_bloom) in camera_query.iter() {
+        if color_scheme.bloom() && !has_bloom {
+            commands.entity(entity).insert(Bloom::default());
+        } else if !color_scheme.bloom() && has_bloom {
+            commands.entity(entity).remove::<Bloom>();
+        }
+    }
+}
+
+/// A startup system that spawns the backdrop image, if one was given, far behind the playfield.
+/// It is dimmed so the board and HUD stay readable over it, and stays for the whole session.
+pub fn setup_backdrop(
+    mut commands: Commands,
+    mut backdrop_image: ResMut<BackdropImage>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let Some(image) = backdrop_image.0.take() else {
+        return;
+    };
+    commands.spawn((
+        Sprite {
+            image: images.add(image),
+            color: BACKDROP_TINT,
+            ..default()
+        },
+        Transform::from_xyz(0.0, 0.0, -10.0),
+        Backdrop,
     ));
-    println!("Camera set up successfully!");
 }
 
-/// A startup system to set up background audio.
-pub fn setup_audio(asset_server: Res<AssetServer>, mut commands: Commands) {
+/// A system that stretches the backdrop over everything the game camera shows, following
+/// window resizes and letterboxing.
+pub fn fit_backdrop(
+    camera_query: Query<&Projection, (GameCameraFilter, Without<TitleScreen>)>,
+    mut backdrop_query: Query<&mut Sprite, With<Backdrop>>,
+) {
+    let Some(Projection::Orthographic(projection)) = camera_query.iter().next() else {
+        return;
+    };
+    let size = Some(projection.area.size());
+    for mut sprite in backdrop_query.iter_mut() {
+        if sprite.custom_size != size {
+            sprite.custom_size = size;
+        }
+    }
+}
+
+/// A system to set up background audio. It runs whenever the title screen is shown, so the
+/// track starts again from the beginning for every game. With `--keep-music` the track is only
+/// started the first time and then left playing.
+pub fn setup_audio(
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+    volume: Res<Volume>,
+    keep_music: Res<KeepMusic>,
+    music_query: Query<Entity, With<BackgroundMusic>>,
+) {
+    if keep_music.0 && !music_query.is_empty() {
+        return;
+    }
+    for entity in music_query.iter() {
+        commands.entity(entity).despawn();
+    }
+
     let asset_path = format!("embedded://sounds/162764.ogg");
 
     commands.spawn((
         AudioPlayer::new(asset_server.load(asset_path)),
         PlaybackSettings {
             mode: bevy::audio::PlaybackMode::Loop,
+            volume: bevy::audio::Volume::Linear(volume.0),
             ..default()
         },
+        BackgroundMusic,
     ));
 }
 
+/// A system to pause the background music while the game is paused or over.
+pub fn pause_music(music_query: Query<&AudioSink, With<BackgroundMusic>>) {
+    for sink in music_query.iter() {
+        sink.pause();
+    }
+}
+
+/// A system to carry on playing the background music from where it was paused.
+pub fn resume_music(music_query: Query<&AudioSink, With<BackgroundMusic>>) {
+    for sink in music_query.iter() {
+        sink.play();
+    }
+}
+
+/// A system that sets the playing music to the master volume whenever it changes.
+pub fn apply_volume(
+    volume: Res<Volume>,
+    mut music_query: Query<&mut AudioSink, With<BackgroundMusic>>,
+) {
+    for mut sink in music_query.iter_mut() {
+        sink.set_volume(bevy::audio::Volume::Linear(volume.0));
+    }
+}
+
+/// Reads the master volume from the settings file, which holds a `volume=<0 to 1>` line. With
+/// no settings file yet the volume starts at full.
+pub fn load_volume() -> Volume {
+    let Ok(text) = std::fs::read_to_string(SETTINGS_FILE) else {
+        return Volume::default();
+    };
+    let Some(value) = text
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("volume="))
+    else {
+        return Volume::default();
+    };
+    match value.trim().parse::<f32>() {
+        Ok(level) if (0.0..=1.0).contains(&level) => Volume(level),
+        _ => {
+            warn!("Ignoring invalid volume {value} in {SETTINGS_FILE}, it must be 0 to 1");
+            Volume::default()
+        }
+    }
+}
+
+/// Writes the master volume to the settings file, so it is kept for the next session.
+pub fn save_volume(volume: Volume) {
+    if let Err(error) = std::fs::write(SETTINGS_FILE, format!("volume={:.1}\n", volume.0)) {
+        error!("Could not save settings to {SETTINGS_FILE}: {error}");
+    }
+}
+
+/// Returns the color of the empty background cells in the given color scheme.
+pub fn grid_cell_color(color_scheme: ColorScheme) -> Color {
+    match color_scheme {
+        ColorScheme::Standard => bevy::prelude::Color::srgb(0.2, 0.2, 0.2), // Dark gray color
+        ColorScheme::HighContrast => bevy::prelude::Color::srgb(0.08, 0.08, 0.08), // Near black
+    }
+}
+
+/// Returns the color of the background cells in the rows the falling piece will land in, a
+/// little brighter than the rest of the grid.
+pub fn landing_row_color(color_scheme: ColorScheme) -> Color {
+    match color_scheme {
+        ColorScheme::Standard => bevy::prelude::Color::srgb(0.32, 0.32, 0.32),
+        ColorScheme::HighContrast => bevy::prelude::Color::srgb(0.2, 0.2, 0.2),
+    }
+}
+
 /// A startup system to spawn the empty grid squares.
-pub fn setup_grid(mut commands: Commands) {
-    for x in 0..GRID_SIZE_X {
-        for y in 0..GRID_SIZE_Y {
-            commands.spawn((
-                Sprite {
-                    color: bevy::prelude::Color::srgb(0.2, 0.2, 0.2), // Dark gray color
-                    custom_size: Some(Vec2::new(BLOCK_SIZE, BLOCK_SIZE)),
-                    ..default()
-                },
-                Transform::from_xyz(
-                    (x as f32 - (GRID_SIZE_X as f32 / 2.0) + 0.5) * BLOCK_SIZE,
-                    (y as f32 - (GRID_SIZE_Y as f32 / 2.0) + 0.5) * BLOCK_SIZE,
-                    0.0,
-                )
-                // add a border to each block
-                .with_scale(Vec3::splat(0.95)),
-            ));
+pub fn setup_grid(
+    mut commands: Commands,
+    metrics: Res<BoardMetrics>,
+    color_scheme: Res<ColorScheme>,
+    block_atlas: Res<BlockAtlas>,
+) {
+    // The whole playfield is one tiled sprite, so the background is a single entity
+    let rect = metrics.rows_rect(0, GRID_SIZE_Y - 1);
+    commands.spawn((
+        block_atlas.grid_sprite(
+            grid_cell_color(*color_scheme),
+            rect.size(),
+            metrics.block_size,
+        ),
+        Transform::from_translation(rect.center().extend(0.0)),
+        GridBackground,
+    ));
+    // Sized and shown by `update_landing_highlight` once there is a piece to follow
+    commands.spawn((
+        block_atlas.grid_sprite(
+            landing_row_color(*color_scheme),
+            rect.size(),
+            metrics.block_size,
+        ),
+        Transform::from_translation(rect.center().extend(0.1)),
+        Visibility::Hidden,
+        LandingRow,
+    ));
+    info!("Grid set up successfully!");
+}
+
+/// A system that places the imported board, if any, onto the playfield as a game starts.
+pub fn spawn_imported_board(
+    mut commands: Commands,
+    metrics: Res<BoardMetrics>,
+    imported_board: Res<ImportedBoard>,
+    game_mode: Res<GameMode>,
+    color_scheme: Res<ColorScheme>,
+    block_atlas: Res<BlockAtlas>,
+) {
+    // Puzzles and training positions bring their own board
+    if game_mode.uses_puzzle_board() {
+        return;
+    }
+    if let Some(board) = &imported_board.0 {
+        spawn_board_blocks(&mut commands, &metrics, &block_atlas, *color_scheme, board);
+        info!("Imported board loaded!");
+    }
+}
+
+/// A system that shrinks the blocks when the window becomes too small to fit the board.
+/// It is not used with letterboxing, which scales the whole game instead.
+/// The block size never grows past `BLOCK_SIZE`, so the default window keeps the original look.
+pub fn fit_board_to_window(
+    mut resize_events: EventReader<WindowResized>,
+    mut metrics: ResMut<BoardMetrics>,
+) {
+    if let Some(event) = resize_events.read().last() {
+        // Leave room for a couple of rows above/below the board and the panels beside it
+        let block_size = BLOCK_SIZE
+            .min(event.height / (GRID_SIZE_Y + 4) as f32)
+            .min(event.width / (GRID_SIZE_X + 14) as f32);
+        metrics.set_if_neq(BoardMetrics { block_size });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn letterbox_fills_a_matching_window_and_bars_the_rest() {
+        let cases = [
+            // 16:9 fills the whole window
+            (UVec2::new(1920, 1080), UVec2::ZERO, UVec2::new(1920, 1080)),
+            // 4:3 gets bars above and below
+            (
+                UVec2::new(1024, 768),
+                UVec2::new(0, 96),
+                UVec2::new(1024, 576),
+            ),
+            // Ultrawide gets bars at the sides
+            (
+                UVec2::new(2560, 1080),
+                UVec2::new(320, 0),
+                UVec2::new(1920, 1080),
+            ),
+            // Portrait keeps the full width in the middle
+            (
+                UVec2::new(720, 1280),
+                UVec2::new(0, 437),
+                UVec2::new(720, 405),
+            ),
+        ];
+        for (window_size, position, size) in cases {
+            assert_eq!(
+                letterbox_rect(window_size),
+                (position, size),
+                "{window_size}"
+            );
         }
     }
-    println!("Grid set up successfully!");
-}
\ No newline at end of file
+
+    #[test]
+    fn letterbox_stays_inside_the_window() {
+        for window_size in [UVec2::new(1, 1), UVec2::new(1281, 721), UVec2::new(1279, 1)] {
+            let (position, size) = letterbox_rect(window_size);
+            assert!((position + size).cmple(window_size).all(), "{window_size}");
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use bevy::{
+    audio::{PlaybackMode, Volume as AudioVolume},
+    prelude::*,
+};
+use std::time::Duration;
+
+use crate::resources::{LockDelay, Volume};
+
+/// The tones of the sound effects, as a frequency in hertz and a length in seconds. There are no
+/// sound files for them, so each is a short sine tone: a high blip for a line clear, a longer,
+/// higher one for a tetris, a low thud for a hard drop and a faint click for a lock tick.
+const LINE_CLEAR_TONE: (f32, f32) = (660.0, 0.12);
+const TETRIS_TONE: (f32, f32) = (990.0, 0.35);
+const HARD_DROP_TONE: (f32, f32) = (110.0, 0.06);
+const LOCK_TICK_TONE: (f32, f32) = (1500.0, 0.02);
+
+/// The time between lock ticks when a piece has just landed, and when it is about to lock. Both
+/// are longer than a tick, so ticks never overlap.
+const LOCK_TICK_SLOWEST_SECONDS: f32 = 0.25;
+const LOCK_TICK_FASTEST_SECONDS: f32 = 0.06;
+
+/// A short sound played when something happens in a game.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoundEffect {
+    LineClear,
+    Tetris,
+    HardDrop,
+    LockTick,
+}
+
+/// Resource holding the sound effects, built once at startup so playing one never loads
+/// anything.
+#[derive(Resource, Default)]
+pub struct SoundEffects {
+    line_clear: Handle<Pitch>,
+    tetris: Handle<Pitch>,
+    hard_drop: Handle<Pitch>,
+    lock_tick: Handle<Pitch>,
+}
+
+impl SoundEffects {
+    fn handle(&self, effect: SoundEffect) -> Handle<Pitch> {
+        match effect {
+            SoundEffect::LineClear => self.line_clear.clone(),
+            SoundEffect::Tetris => self.tetris.clone(),
+            SoundEffect::HardDrop => self.hard_drop.clone(),
+            SoundEffect::LockTick => self.lock_tick.clone(),
+        }
+    }
+}
+
+/// A startup system that builds the sound effects.
+pub fn setup_sound_effects(mut commands: Commands, mut pitches: ResMut<Assets<Pitch>>) {
+    let mut tone = |(frequency, seconds): (f32, f32)| {
+        pitches.add(Pitch::new(frequency, Duration::from_secs_f32(seconds)))
+    };
+    commands.insert_resource(SoundEffects {
+        line_clear: tone(LINE_CLEAR_TONE),
+        tetris: tone(TETRIS_TONE),
+        hard_drop: tone(HARD_DROP_TONE),
+        lock_tick: tone(LOCK_TICK_TONE),
+    });
+}
+
+/// Plays a sound effect once at the master volume. The sound is queued as a command, so it can
+/// be played from anywhere that has `Commands`, and its entity despawns when it ends.
+pub fn play_sound(commands: &mut Commands, effect: SoundEffect) {
+    commands.queue(move |world: &mut World| {
+        let Some(sound_effects) = world.get_resource::<SoundEffects>() else {
+            return;
+        };
+        let handle = sound_effects.handle(effect);
+        let volume = world.get_resource::<Volume>().copied().unwrap_or_default();
+        world.spawn((
+            AudioPlayer(handle),
+            PlaybackSettings {
+                mode: PlaybackMode::Despawn,
+                volume: AudioVolume::Linear(volume.0),
+                ..default()
+            },
+        ));
+    });
+}
+
+/// A system that ticks while the falling piece rests on the stack and its lock delay runs down,
+/// faster as the lock nears, like a bomb timer. It goes quiet as soon as the piece is lifted
+/// off the stack again, and only runs when turned on with `--lock-ticks`.
+pub fn tick_lock_sound(
+    mut commands: Commands,
+    time: Res<Time>,
+    lock_delay: Res<LockDelay>,
+    mut since_last_tick: Local<Option<f32>>,
+) {
+    if !lock_delay.grounded {
+        *since_last_tick = None;
+        return;
+    }
+    let interval = LOCK_TICK_SLOWEST_SECONDS
+        + (LOCK_TICK_FASTEST_SECONDS - LOCK_TICK_SLOWEST_SECONDS) * lock_delay.timer.fraction();
+    match since_last_tick.as_mut() {
+        Some(since) if *since + time.delta_secs() < interval => *since += time.delta_secs(),
+        // The first tick sounds as the piece lands
+        _ => {
+            play_sound(&mut commands, SoundEffect::LockTick);
+            *since_last_tick = Some(0.0);
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use bevy::prelude::*;
+
+use crate::{
+    atlas::BlockAtlas,
+    board::{ActivePiece, Board, Cell, CompactBoard},
+    components::{GridPosition, SpectatorBlock},
+    constants::{GRID_SIZE_X, GRID_SIZE_Y},
+    resources::{BoardMetrics, ColorScheme, HudSide},
+    setup::grid_cell_color,
+};
+
+/// How large the spectator board is drawn compared to the main playfield.
+const SPECTATOR_SCALE: f32 = 0.5;
+
+/// Resource holding the board shown in the read-only spectator view beside the playfield,
+/// such as an opponent's board. Set with `--spectate <hex or file>`.
+#[derive(Resource, Default)]
+pub struct SpectatorBoard(pub Option<CompactBoard>);
+
+/// Returns the world position of a cell of the spectator board. The board sits on the side
+/// of the playfield away from the HUD, at reduced scale.
+fn spectator_to_world(metrics: &BoardMetrics, hud_side: HudSide, position: GridPosition) -> Vec2 {
+    let block_size = metrics.block_size * SPECTATOR_SCALE;
+    let center = Vec2::new(
+        -hud_side.sign() * (GRID_SIZE_X as f32 / 2.0 + 1.0) * metrics.block_size
+            - hud_side.sign() * GRID_SIZE_X as f32 / 2.0 * block_size,
+        0.0,
+    );
+    center
+        + Vec2::new(
+            (position.x as f32 - GRID_SIZE_X as f32 / 2.0 + 0.5) * block_size,
+            (position.y as f32 - GRID_SIZE_Y as f32 / 2.0 + 0.5) * block_size,
+        )
+}
+
+/// Returns every block of a board to draw in a small read-only view, with its color: the
+/// settled blocks first, then the active piece on top.
+pub fn mini_board_blocks(
+    board: &Board,
+    piece: Option<&ActivePiece>,
+    color_scheme: ColorScheme,
+) -> Vec<(GridPosition, Color)> {
+    let mut blocks: Vec<(GridPosition, Color)> = board
+        .occupied()
+        .map(|(position, cell)| (position, cell.color(color_scheme)))
+        .collect();
+    if let Some(piece) = piece {
+        let color = Cell::Piece(piece.shape).color(color_scheme);
+        blocks.extend(piece.blocks.iter().map(|position| (*position, color)));
+    }
+    blocks
+}
+
+/// A system that draws the spectator board. It only reads the stored state: nothing here
+/// takes input or simulates, and the whole view is redrawn when the state or layout changes.
+pub fn render_spectator_board(
+    mut commands: Commands,
+    spectator: Res<SpectatorBoard>,
+    metrics: Res<BoardMetrics>,
+    hud_side: Res<HudSide>,
+    color_scheme: Res<ColorScheme>,
+    block_atlas: Res<BlockAtlas>,
+    block_query: Query<Entity, With<SpectatorBlock>>,
+) {
+    let Some(compact) = spectator.0 else {
+        return;
+    };
+    let changed = spectator.is_changed() || metrics.is_changed() || hud_side.is_changed();
+    if !changed && !block_query.is_empty() {
+        return;
+    }
+    let (board, piece, _) = match compact.decode() {
+        Ok(decoded) => decoded,
+        Err(error) => {
+            warn!("Could not show spectator board: {error}");
+            return;
+        }
+    };
+
+    for entity in block_query.iter() {
+        commands.entity(entity).try_despawn();
+    }
+
+    let block_size = Vec2::splat(metrics.block_size * SPECTATOR_SCALE);
+
+    // The background as one tiled sprite, then the settled blocks and the active piece on top
+    let bottom_left = spectator_to_world(&metrics, *hud_side, GridPosition { x: 0, y: 0 });
+    let top_right = spectator_to_world(
+        &metrics,
+        *hud_side,
+        GridPosition {
+            x: GRID_SIZE_X - 1,
+            y: GRID_SIZE_Y - 1,
+        },
+    );
+    commands.spawn((
+        block_atlas.grid_sprite(
+            grid_cell_color(*color_scheme),
+            top_right - bottom_left + block_size,
+            block_size.x,
+        ),
+        Transform::from_translation(((bottom_left + top_right) / 2.0).extend(0.0)),
+        SpectatorBlock,
+    ));
+    for (position, color) in mini_board_blocks(&board, piece.as_ref(), *color_scheme) {
+        let world = spectator_to_world(&metrics, *hud_side, position);
+        commands.spawn((
+            block_atlas.sprite(color, block_size),
+            Transform::from_xyz(world.x, world.y, 1.0),
+            SpectatorBlock,
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+
+    use crate::{components::Shape, resources::HoldPiece};
+
+    #[test]
+    fn spectator_board_is_drawn_at_half_scale_beside_the_playfield() {
+        let board = Board::from_blocks(
+            [(0, 0), (1, 0), (9, 0), (4, 1)].map(|(x, y)| (GridPosition { x, y }, Cell::Garbage)),
+        );
+        let piece = ActivePiece {
+            shape: Shape::T,
+            blocks: [(3, 10), (4, 10), (5, 10), (4, 11)].map(|(x, y)| GridPosition { x, y }),
+        };
+        let metrics = BoardMetrics::default();
+        let half_block = metrics.block_size * 0.5;
+        let playfield = metrics.rows_rect(0, GRID_SIZE_Y - 1);
+
+        for hud_side in [HudSide::Left, HudSide::Right] {
+            let mut world = World::new();
+            world.insert_resource(SpectatorBoard(Some(CompactBoard::encode(
+                &board,
+                Some(&piece),
+                &HoldPiece::default(),
+            ))));
+            world.insert_resource(metrics);
+            world.insert_resource(hud_side);
+            world.init_resource::<ColorScheme>();
+            world.init_resource::<BlockAtlas>();
+            world.run_system_once(render_spectator_board).unwrap();
+
+            let sprites: Vec<Rect> = world
+                .query_filtered::<(&Transform, &Sprite), With<SpectatorBlock>>()
+                .iter(&world)
+                .map(|(transform, sprite)| {
+                    Rect::from_center_size(
+                        transform.translation.truncate(),
+                        sprite.custom_size.unwrap(),
+                    )
+                })
+                .collect();
+            let (blocks, backgrounds): (Vec<Rect>, Vec<Rect>) = sprites
+                .into_iter()
+                .partition(|rect| rect.size() == Vec2::splat(half_block));
+
+            // A half-size background beside the playfield, with every block drawn on it
+            assert_eq!(backgrounds.len(), 1);
+            let background = backgrounds[0];
+            assert_eq!(
+                background.size(),
+                Vec2::new(GRID_SIZE_X as f32, GRID_SIZE_Y as f32) * half_block
+            );
+            assert!(background.intersect(playfield).is_empty());
+            assert_eq!(blocks.len(), 8);
+            assert!(
+                blocks
+                    .iter()
+                    .all(|block| background.contains(block.center()))
+            );
+
+            // Neighboring cells are half a block apart
+            let cell = |x, y| spectator_to_world(&metrics, hud_side, GridPosition { x, y });
+            assert_eq!(cell(1, 0) - cell(0, 0), Vec2::new(half_block, 0.0));
+            assert_eq!(cell(0, 1) - cell(0, 0), Vec2::new(0.0, half_block));
+        }
+    }
+}
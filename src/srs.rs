@@ -0,0 +1,37 @@
+use crate::components::{RotationState, Shape};
+
+/// Offsets tried in order, as (x, y) with y up, when J, L, S, T or Z turn between two states.
+/// The first that leaves the piece clear of walls and blocks is applied.
+fn jlstz_kicks(from: RotationState, to: RotationState) -> &'static [(i32, i32)] {
+    use RotationState::*;
+    match (from, to) {
+        (Spawn, Right) | (Flipped, Right) => &[(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)],
+        (Right, Spawn) | (Right, Flipped) => &[(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)],
+        (Flipped, Left) | (Spawn, Left) => &[(0, 0), (1, 0), (1, 1), (0, -2), (1, -2)],
+        (Left, Flipped) | (Left, Spawn) => &[(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)],
+        _ => &[(0, 0)],
+    }
+}
+
+/// Offsets tried in order when the I piece turns between two states. It has its own table, as
+/// it is long enough to need kicks of two cells.
+fn i_kicks(from: RotationState, to: RotationState) -> &'static [(i32, i32)] {
+    use RotationState::*;
+    match (from, to) {
+        (Spawn, Right) | (Left, Flipped) => &[(0, 0), (-2, 0), (1, 0), (-2, -1), (1, 2)],
+        (Right, Spawn) | (Flipped, Left) => &[(0, 0), (2, 0), (-1, 0), (2, 1), (-1, -2)],
+        (Right, Flipped) | (Spawn, Left) => &[(0, 0), (-1, 0), (2, 0), (-1, 2), (2, -1)],
+        (Flipped, Right) | (Left, Spawn) => &[(0, 0), (1, 0), (-2, 0), (1, -2), (-2, 1)],
+        _ => &[(0, 0)],
+    }
+}
+
+/// Returns the wall kicks of the Super Rotation System for a piece turning between two states,
+/// starting with turning in place. The O piece does not kick, and neither does a half turn.
+pub fn kick_offsets(shape: Shape, from: RotationState, to: RotationState) -> &'static [(i32, i32)] {
+    match shape {
+        Shape::I => i_kicks(from, to),
+        Shape::O => &[(0, 0)],
+        Shape::T | Shape::L | Shape::J | Shape::S | Shape::Z => jlstz_kicks(from, to),
+    }
+}
@@ -1,12 +1,19 @@
 use bevy::prelude::*;
-use rand::seq::IndexedRandom;
+use std::collections::HashSet;
 
 use crate::{
-    components::{GridPosition, RotationCenter, Shape, Tetromino},
-    constants::{BLOCK_SIZE, GRID_SIZE_X, GRID_SIZE_Y},
-    game_logic::check_collision,
+    atlas::BlockAtlas,
+    board::CollisionGrid,
+    components::{GridPosition, HoldSwap, RotationCenter, RotationState, Shape, Tetromino},
+    constants::{GRID_SIZE_X, GRID_SIZE_Y, NEXT_QUEUE_SIZE},
+    game_logic::{check_collision, row_visibility},
     game_state::GameState,
-    resources::NextPiece,
+    placement::{has_legal_placement, rotated},
+    randomizer::{GameRng, GameSeed, PieceGenerator, RandomizerKind, RepeatLimit},
+    resources::{
+        BoardMetrics, ColorScheme, ForcedSequence, GameOverReason, HoldPiece, LockDelay, NextPiece,
+        NextQueue, NoOverhangStart,
+    },
 };
 
 /// Returns the block positions for a given tetromino shape, relative to the piece's origin
@@ -57,8 +64,19 @@ pub fn get_tetromino_blocks(shape: Shape) -> Vec<GridPosition> {
     }
 }
 
-/// Returns the color for a given tetromino shape
-pub fn get_tetromino_color(shape: Shape) -> bevy::prelude::Color {
+/// Returns the color for a given tetromino shape in the given color scheme
+pub fn get_tetromino_color(shape: Shape, color_scheme: ColorScheme) -> bevy::prelude::Color {
+    if color_scheme == ColorScheme::HighContrast {
+        return match shape {
+            Shape::I => bevy::prelude::Color::srgb(0.0, 0.9, 0.9), // Cyan
+            Shape::O => bevy::prelude::Color::srgb(1.0, 0.9, 0.0), // Yellow
+            Shape::T => bevy::prelude::Color::srgb(0.65, 0.0, 0.85), // Purple
+            Shape::L => bevy::prelude::Color::srgb(1.0, 0.5, 0.0), // Orange
+            Shape::J => bevy::prelude::Color::srgb(0.15, 0.35, 1.0), // Blue
+            Shape::S => bevy::prelude::Color::srgb(0.1, 0.8, 0.1), // Green
+            Shape::Z => bevy::prelude::Color::srgb(0.9, 0.1, 0.1), // Red
+        };
+    }
     match shape {
         Shape::I => bevy::prelude::Color::srgba(0.0, 2.0, 2.0, 0.8), // Cyan
         Shape::O => bevy::prelude::Color::srgba(2.0, 2.0, 0.0, 0.8), // Yellow
@@ -83,82 +101,361 @@ pub fn get_rotation_center_index(shape: Shape) -> Option<usize> {
     }
 }
 
+/// Returns the offset from a shape's rotation center block to the point it turns around, in half
+/// cells, while the piece faces the way it spawned. The I piece turns around the corner between
+/// four cells, as in SRS, so it stays in the middle of its 4x4 box instead of wobbling from side
+/// to side; the other shapes turn around the center block itself.
+fn rotation_pivot_offset(shape: Shape) -> IVec2 {
+    match shape {
+        Shape::I => IVec2::new(1, -1),
+        Shape::O | Shape::T | Shape::L | Shape::J | Shape::S | Shape::Z => IVec2::ZERO,
+    }
+}
+
+/// Returns the point a piece turns around from the position of its rotation center block and
+/// the way it faces. The point can sit between cells, so it is in doubled grid coordinates.
+pub fn rotation_pivot(shape: Shape, center: GridPosition, state: RotationState) -> IVec2 {
+    let offset = (0..state.quarter_turns())
+        .fold(rotation_pivot_offset(shape), |offset, _| {
+            IVec2::new(offset.y, -offset.x)
+        });
+    IVec2::new(center.x, center.y) * 2 + offset
+}
+
+/// Turns a block by a number of quarter turns clockwise around a pivot in doubled grid
+/// coordinates; negative turns go counterclockwise.
+pub fn rotate_about(position: GridPosition, pivot: IVec2, quarter_turns: i32) -> GridPosition {
+    let mut relative = IVec2::new(position.x, position.y) * 2 - pivot;
+    for _ in 0..quarter_turns.rem_euclid(4) {
+        // Rotate 90 degrees clockwise: (x, y) -> (y, -x)
+        relative = IVec2::new(relative.y, -relative.x);
+    }
+    let turned = (pivot + relative) / 2;
+    GridPosition {
+        x: turned.x,
+        y: turned.y,
+    }
+}
+
+/// Checks the tables above for one shape: the piece must have four distinct blocks, its rotation
+/// center must be one of them, and four quarter turns must bring every block back where it was.
+/// Returns a description of the first problem found.
+pub fn check_shape_definition(shape: Shape) -> Result<(), String> {
+    let blocks = get_tetromino_blocks(shape);
+    let distinct: HashSet<(i32, i32)> = blocks.iter().map(|block| (block.x, block.y)).collect();
+    if blocks.len() != 4 || distinct.len() != 4 {
+        return Err(format!(
+            "{shape:?} has {} blocks, {} of them distinct, instead of 4",
+            blocks.len(),
+            distinct.len()
+        ));
+    }
+
+    let center_index = get_rotation_center_index(shape);
+    if let Some(index) = center_index
+        && index >= blocks.len()
+    {
+        return Err(format!(
+            "{shape:?} has rotation center index {index}, but only {} blocks",
+            blocks.len()
+        ));
+    }
+
+    // O has no rotation center and never turns
+    if let Some(index) = center_index {
+        let pivot = rotation_pivot(shape, blocks[index], RotationState::default());
+        let mut turned = blocks.clone();
+        for _ in 0..4 {
+            turned = rotated(&turned, pivot, 1);
+        }
+        if turned != blocks {
+            return Err(format!(
+                "{shape:?} does not come back to its starting blocks after four rotations"
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// A startup system, run in debug builds, that stops the game straight away if a shape table
+/// was edited into an invalid piece, rather than letting it misbehave mid-game.
+pub fn validate_shape_definitions() {
+    for shape in Shape::ALL {
+        if let Err(problem) = check_shape_definition(shape) {
+            panic!("Invalid tetromino definition: {problem}");
+        }
+    }
+    info!("Tetromino definitions checked!");
+}
+
+/// Starts a fresh randomizer of the selected kind from the game seed and returns the shape a
+/// new game opens with. With the no-overhang rule on, S and Z are drawn again until another
+/// shape comes up; pieces after the first are drawn as usual.
+pub fn first_shape(
+    piece_generator: &mut PieceGenerator,
+    randomizer_kind: RandomizerKind,
+    game_seed: GameSeed,
+    repeat_limit: RepeatLimit,
+    no_overhang_start: NoOverhangStart,
+) -> Shape {
+    *piece_generator = PieceGenerator::new(randomizer_kind, game_seed, repeat_limit);
+    loop {
+        let shape = piece_generator.next_shape();
+        if !no_overhang_start.0 || !matches!(shape, Shape::S | Shape::Z) {
+            return shape;
+        }
+    }
+}
+
+/// A system that draws the opening piece when a game starts from the title screen, and reseeds
+/// the rest of the game's randomness.
+#[allow(clippy::too_many_arguments)]
+pub fn draw_first_piece(
+    no_overhang_start: Res<NoOverhangStart>,
+    randomizer_kind: Res<RandomizerKind>,
+    game_seed: Res<GameSeed>,
+    repeat_limit: Res<RepeatLimit>,
+    mut piece_generator: ResMut<PieceGenerator>,
+    mut next_piece: ResMut<NextPiece>,
+    mut next_queue: ResMut<NextQueue>,
+    mut game_rng: ResMut<GameRng>,
+) {
+    *game_rng = GameRng::new(*game_seed);
+    next_queue.0.clear();
+    next_piece.0 = first_shape(
+        &mut piece_generator,
+        *randomizer_kind,
+        *game_seed,
+        *repeat_limit,
+        *no_overhang_start,
+    );
+}
+
+/// Returns the board positions of a shape's blocks at the spawn point
+pub fn spawn_blocks(shape: Shape) -> Vec<GridPosition> {
+    // The tetromino's origin starts at the center of the lowest buffer row, just above the
+    // visible field
+    let initial_y_offset = GRID_SIZE_Y;
+    let initial_x_offset = GRID_SIZE_X as i32 / 2 - 1;
+
+    get_tetromino_blocks(shape)
+        .into_iter()
+        .map(|block_position| GridPosition {
+            x: block_position.x + initial_x_offset,
+            y: block_position.y + initial_y_offset,
+        })
+        .collect()
+}
+
 /// Spawns a new tetromino and transitions the state.
+#[allow(clippy::too_many_arguments)]
 pub fn spawn_tetromino(
     mut commands: Commands,
     mut next_state: ResMut<NextState<GameState>>,
-    grid_query: Query<&GridPosition, Without<Tetromino>>,
+    collision_grid: Res<CollisionGrid>,
     mut next_piece: ResMut<NextPiece>,
+    mut forced_sequence: ResMut<ForcedSequence>,
+    mut piece_generator: ResMut<PieceGenerator>,
+    metrics: Res<BoardMetrics>,
+    mut game_over_reason: ResMut<GameOverReason>,
+    color_scheme: Res<ColorScheme>,
+    block_atlas: Res<BlockAtlas>,
+    mut hold_piece: ResMut<HoldPiece>,
+    mut lock_delay: ResMut<LockDelay>,
+    mut next_queue: ResMut<NextQueue>,
 ) {
-    // 1. Determine the shape to spawn (It's the NextPiece from the previous cycle)
-    let current_shape_to_spawn = next_piece.0;
-
-    // 2. Generate the shape for the *next* spawn (and store it)
-    let shapes = [
-        Shape::I,
-        Shape::O,
-        Shape::T,
-        Shape::L,
-        Shape::J,
-        Shape::S,
-        Shape::Z,
-    ];
-    // Pick a random shape from the list
-    let new_next_shape = shapes.choose(&mut rand::rng()).unwrap().clone();
-    next_piece.0 = new_next_shape;
+    // Every piece gets the full lock delay and all of its resets
+    *lock_delay = LockDelay::default();
+
+    // 1. Determine the shape to spawn. After a hold the piece held before comes back out of
+    // the hold box; otherwise it's the NextPiece from the previous cycle.
+    let held_shape = match hold_piece.pending.take() {
+        Some(just_held) => hold_piece.shape.replace(just_held),
+        None => {
+            // A piece locked, so the new one may be held again
+            hold_piece.can_hold = true;
+            None
+        }
+    };
+    let from_hold = held_shape.is_some();
+    let current_shape_to_spawn = match held_shape {
+        Some(shape) => shape,
+        None => {
+            let shape = next_piece.0;
+            // 2. Move the queue up and top it back up. Take the next forced piece if there is
+            // one, otherwise draw from the randomizer
+            let mut draw = || {
+                forced_sequence
+                    .0
+                    .pop_front()
+                    .unwrap_or_else(|| piece_generator.next_shape())
+            };
+            next_piece.0 = next_queue.0.pop_front().unwrap_or_else(&mut draw);
+            while next_queue.0.len() < NEXT_QUEUE_SIZE - 1 {
+                next_queue.0.push_back(draw());
+            }
+            shape
+        }
+    };
 
     // Get the blocks and color for the current shape
-    let blocks = get_tetromino_blocks(current_shape_to_spawn);
-    let color = get_tetromino_color(current_shape_to_spawn);
-    
-    // Set the initial position of the tetromino's origin
-    let initial_y_offset = GRID_SIZE_Y as i32 - 1;
-    let initial_x_offset = GRID_SIZE_X as i32 / 2 - 1;
+    let blocks = spawn_blocks(current_shape_to_spawn);
+    let color = get_tetromino_color(current_shape_to_spawn, *color_scheme);
 
     // Check for game over condition
-    let static_blocks: Vec<GridPosition> = grid_query.iter().cloned().collect();
     for block_position in &blocks {
-        let new_pos = GridPosition {
-            x: block_position.x + initial_x_offset,
-            y: block_position.y + initial_y_offset,
-        };
-        if check_collision(new_pos, &static_blocks) {
-            println!("Game Over!");
+        if check_collision(*block_position, &collision_grid) {
+            info!("Game Over!");
+            *game_over_reason = GameOverReason::BlockOut;
             next_state.set(GameState::GameOver);
             return;
         }
     }
 
+    // The piece spawned clear, but it may still have nowhere inside the playfield to rest
+    if !has_legal_placement(current_shape_to_spawn, &collision_grid) {
+        info!("Stalemate! The piece has nowhere to go.");
+        *game_over_reason = GameOverReason::Stalemate;
+        next_state.set(GameState::GameOver);
+        return;
+    }
+
     // Spawn the individual blocks for the new tetromino
-    for (i, block_position) in blocks.iter().enumerate() {
+    for (i, grid_position) in blocks.iter().copied().enumerate() {
+        let world = metrics.grid_to_world(grid_position);
         let mut entity_commands = commands.spawn((
-            Sprite {
-                color,
-                custom_size: Some(Vec2::new(BLOCK_SIZE, BLOCK_SIZE)),
-                ..default()
-            },
-            Transform::from_xyz(
-                (block_position.x + initial_x_offset) as f32 * BLOCK_SIZE,
-                (block_position.y + initial_y_offset) as f32 * BLOCK_SIZE,
-                1.0,
-            ),
-            GridPosition {
-                x: block_position.x + initial_x_offset,
-                y: block_position.y + initial_y_offset,
-            },
+            block_atlas.sprite(color, Vec2::splat(metrics.block_size)),
+            Transform::from_xyz(world.x, world.y, 1.0),
+            row_visibility(grid_position),
+            grid_position,
+            current_shape_to_spawn,
             Tetromino,
         ));
-        
+        if from_hold {
+            entity_commands.insert(HoldSwap::new(false));
+        }
+
         // Add the rotation center component to the correct block
         if let Some(center_index) = get_rotation_center_index(current_shape_to_spawn) {
             if i == center_index {
-                entity_commands.insert(RotationCenter(GridPosition {
-                    x: block_position.x + initial_x_offset,
-                    y: block_position.y + initial_y_offset,
-                }));
+                entity_commands.insert(RotationCenter {
+                    shape: current_shape_to_spawn,
+                    state: RotationState::default(),
+                    rotated_last: false,
+                });
             }
         }
     }
-    println!("New tetromino spawned!");
+    info!("New tetromino spawned!");
     next_state.set(GameState::Playing);
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+
+    /// A board with the top visible row filled but for a two-wide gap in the corner, as deep as
+    /// given, so nothing can reach the rows under it.
+    fn capped_board(gap_depth: i32) -> CollisionGrid {
+        let top = GRID_SIZE_Y - 1;
+        let cap = (2..GRID_SIZE_X).map(|x| GridPosition { x, y: top });
+        let gap_floor =
+            (0..2).flat_map(|x| (0..GRID_SIZE_Y - gap_depth).map(move |y| GridPosition { x, y }));
+        CollisionGrid::from_blocks(
+            cap.chain(gap_floor)
+                .map(|position| (Entity::PLACEHOLDER, position)),
+        )
+    }
+
+    /// Spawns an O piece over the board, returning the world it was spawned in.
+    fn spawn_o_piece(collision_grid: CollisionGrid) -> World {
+        let mut world = World::new();
+        world.insert_resource(collision_grid);
+        world.insert_resource(NextPiece(Shape::O));
+        world.init_resource::<NextState<GameState>>();
+        world.init_resource::<ForcedSequence>();
+        world.init_resource::<PieceGenerator>();
+        world.init_resource::<BoardMetrics>();
+        world.init_resource::<GameOverReason>();
+        world.init_resource::<ColorScheme>();
+        world.init_resource::<BlockAtlas>();
+        world.init_resource::<HoldPiece>();
+        world.init_resource::<LockDelay>();
+        world.init_resource::<NextQueue>();
+        world.run_system_once(spawn_tetromino).unwrap();
+        world
+    }
+
+    #[test]
+    fn a_piece_with_nowhere_to_rest_is_a_stalemate() {
+        // The O spawns clear above the cap, but the gap is too shallow for it to drop into
+        let mut world = spawn_o_piece(capped_board(1));
+        assert!(matches!(
+            world.resource::<NextState<GameState>>(),
+            NextState::Pending(GameState::GameOver)
+        ));
+        assert_eq!(
+            *world.resource::<GameOverReason>(),
+            GameOverReason::Stalemate
+        );
+        assert_eq!(world.query::<&Tetromino>().iter(&world).count(), 0);
+    }
+
+    #[test]
+    fn a_piece_that_fits_in_the_gap_plays_on() {
+        let mut world = spawn_o_piece(capped_board(2));
+        assert!(matches!(
+            world.resource::<NextState<GameState>>(),
+            NextState::Pending(GameState::Playing)
+        ));
+        assert_eq!(world.query::<&Tetromino>().iter(&world).count(), 4);
+    }
+
+    #[test]
+    fn spawning_over_a_block_is_a_block_out() {
+        let spawn_cell = spawn_blocks(Shape::O)[0];
+        let mut world = spawn_o_piece(CollisionGrid::from_blocks([(
+            Entity::PLACEHOLDER,
+            spawn_cell,
+        )]));
+        assert!(matches!(
+            world.resource::<NextState<GameState>>(),
+            NextState::Pending(GameState::GameOver)
+        ));
+        assert_eq!(
+            *world.resource::<GameOverReason>(),
+            GameOverReason::BlockOut
+        );
+        assert_eq!(world.query::<&Tetromino>().iter(&world).count(), 0);
+    }
+
+    #[test]
+    fn no_overhang_start_never_opens_with_s_or_z() {
+        let mut piece_generator = PieceGenerator::default();
+        for kind in [
+            RandomizerKind::Uniform,
+            RandomizerKind::SevenBag,
+            RandomizerKind::FourteenBag,
+            RandomizerKind::Tgm,
+        ] {
+            let mut openings = |no_overhang_start| {
+                (0..500)
+                    .map(|seed| {
+                        first_shape(
+                            &mut piece_generator,
+                            kind,
+                            GameSeed(seed),
+                            RepeatLimit::default(),
+                            NoOverhangStart(no_overhang_start),
+                        )
+                    })
+                    .collect::<Vec<Shape>>()
+            };
+            let overhang = |shape: &Shape| matches!(shape, Shape::S | Shape::Z);
+            // Without the rule some of these seeds do open with one
+            assert!(openings(false).iter().any(overhang), "{kind:?}");
+            assert!(!openings(true).iter().any(overhang), "{kind:?}");
+        }
+    }
+}
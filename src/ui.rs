@@ -1,27 +1,52 @@
-use bevy::prelude::*;
+use bevy::{prelude::*, render::view::RenderLayers};
 
 use crate::{
-    components::{GameOverOverlay, PauseMenu, PreviewBlock, Scoreboard, TitleScreen},
+    atlas::BlockAtlas,
+    bindings::KeyBindings,
+    board::{ActivePiece, Board, Cell},
+    components::{
+        BestBanner, ClearingRow, Confetti, ControlsLegend, ControlsLegendText, CountdownText,
+        GameOverOverlay, GhostBlock, GridBackground, GridPosition, GridPulse, HoldBlock, HoldSwap,
+        LandingRow, PauseMenu, PreviewBlock, PreviewFrame, Scoreboard, Shape, SpectatorBlock,
+        Tetromino, TitleScreen, TitleSetting, TransitionOverlay, VictoryOverlay,
+    },
     constants::{
-        BLOCK_SIZE, GRID_SIZE_X, GRID_SIZE_Y, SCOREBOARD_FONT_SIZE, SCOREBOARD_LINE_TEXT_PADDING,
-        SCOREBOARD_TEXT_PADDING,
+        BEST_BANNER_SECONDS, CLASSIC_LEVEL_DIGITS, CLASSIC_LINES_DIGITS, CLASSIC_SCORE_DIGITS,
+        GRID_PULSE_SECONDS, GRID_PULSE_STRENGTH_PER_ROW, GRID_SIZE_X, GRID_SIZE_Y,
+        HOLD_UNAVAILABLE_ALPHA, PREVIEW_BOX_COLOR, SCOREBOARD_FONT_SIZE,
+        SCOREBOARD_LINE_TEXT_PADDING, SCOREBOARD_TEXT_PADDING, THUMBNAIL_CELL_SIZE,
+        UI_RENDER_LAYER,
+    },
+    game_state::GameState,
+    puzzle::{PuzzleOutcome, PuzzleProgress, Puzzles},
+    randomizer::{GameSeed, RandomizerKind},
+    resources::{
+        BackToBack, BoardMetrics, ClassicScore, ColorScheme, Combo, Efficiency, GameMode,
+        GameOverReason, HoldAnimation, HoldPiece, HudSide, Level, LifetimeStats, LinesCleared,
+        NewBests, NextPiece, NextQueue, PlayCounters, PlayTime, PreviewLayout, PreviewScale,
+        RuleProfile, Score, ScoreMultiplier, ScreenTransition, ShowEfficiency, StartCountdown,
+        StartingLevel, StatsResetPending,
     },
-    resources::{Level, LinesCleared, NextPiece, Score},
+    rewind::{RewindHistory, RewindsLeft, can_rewind},
+    setup::grid_cell_color,
+    spectator::mini_board_blocks,
     tetromino::{get_tetromino_blocks, get_tetromino_color},
 };
 
 /// A system to set up the title screen UI.
 pub fn setup_title_screen(mut commands: Commands) {
-    // A separate camera for the UI to prevent it from moving with the game camera
+    // A separate camera for the UI to prevent it from moving with the game camera. It sees no
+    // sprites and keeps what the game camera drew, so the board shows through once, unchanged.
     commands.spawn((
         Camera2d::default(),
         Camera {
             hdr: true,
             // Renders the title screen UI on top of the main camera
             order: 1,
+            clear_color: ClearColorConfig::None,
             ..default()
         },
-        bevy::core_pipeline::bloom::Bloom::default(),
+        RenderLayers::layer(UI_RENDER_LAYER),
         TitleScreen,
     ));
 
@@ -89,7 +114,116 @@ pub fn setup_title_screen(mut commands: Commands) {
         },
         TitleScreen,
     ));
-    println!("Title screen set up successfully!");
+
+    // Selectable settings, kept up to date by `update_title_settings`
+    for (top, setting) in [
+        (400.0, TitleSetting::GameMode),
+        (430.0, TitleSetting::RuleProfile),
+        (460.0, TitleSetting::Randomizer),
+        (490.0, TitleSetting::HudSide),
+        (520.0, TitleSetting::ColorScheme),
+        (550.0, TitleSetting::Seed),
+        (580.0, TitleSetting::StartingLevel),
+        (580.0, TitleSetting::Puzzle),
+        (620.0, TitleSetting::Bests),
+    ] {
+        commands.spawn((
+            Text::new(""),
+            TextFont {
+                font_size: 20.0,
+                ..default()
+            },
+            TextColor(bevy::prelude::Color::WHITE),
+            Node {
+                position_type: PositionType::Absolute,
+                top: Val::Px(top),
+                left: Val::Percent(50.0),
+                // offset by half the text width to truly center it
+                margin: UiRect {
+                    left: Val::Px(-130.0), // Approximate half the width of the text
+                    ..default()
+                },
+                ..default()
+            },
+            setting,
+            TitleScreen,
+        ));
+    }
+    info!("Title screen set up successfully!");
+}
+
+/// A system that shows the selected settings on the title screen.
+#[allow(clippy::too_many_arguments)]
+pub fn update_title_settings(
+    game_mode: Res<GameMode>,
+    rule_profile: Res<RuleProfile>,
+    randomizer_kind: Res<RandomizerKind>,
+    hud_side: Res<HudSide>,
+    color_scheme: Res<ColorScheme>,
+    game_seed: Res<GameSeed>,
+    puzzles: Res<Puzzles>,
+    lifetime_stats: Res<LifetimeStats>,
+    stats_reset: Res<StatsResetPending>,
+    starting_level: Res<StartingLevel>,
+    mut query: Query<(&mut Text, &TitleSetting)>,
+) {
+    for (mut text, setting) in query.iter_mut() {
+        match setting {
+            TitleSetting::GameMode => {
+                *text = Text::new(format!("Mode: {} (M to change)", game_mode.label()));
+            }
+            TitleSetting::RuleProfile => {
+                *text = Text::new(format!("Rules: {} (L to change)", rule_profile.label()));
+            }
+            TitleSetting::Randomizer => {
+                *text = Text::new(format!("Pieces: {} (G to change)", randomizer_kind.label()));
+            }
+            TitleSetting::HudSide => {
+                *text = Text::new(format!("HUD: {} (H to change)", hud_side.label()));
+            }
+            TitleSetting::ColorScheme => {
+                *text = Text::new(format!("Colors: {} (C to change)", color_scheme.label()));
+            }
+            TitleSetting::Seed => {
+                *text = Text::new(format!("Seed: {} (D to reroll)", game_seed.label()));
+            }
+            TitleSetting::StartingLevel if !game_mode.uses_puzzle_board() => {
+                *text = Text::new(format!(
+                    "Starting level: {} (Up/Down to change)",
+                    starting_level.0
+                ));
+            }
+            TitleSetting::StartingLevel => {
+                *text = Text::new("");
+            }
+            TitleSetting::Puzzle if game_mode.uses_puzzle_board() => {
+                let label = if *game_mode == GameMode::Puzzle {
+                    "Puzzle"
+                } else {
+                    "Position"
+                };
+                *text = Text::new(format!("{label}: {} (N to change)", puzzles.current().name));
+            }
+            TitleSetting::Puzzle => {
+                *text = Text::new("");
+            }
+            TitleSetting::Bests if stats_reset.0 => {
+                *text = Text::new(format!(
+                    "Press X again to reset your {} bests",
+                    game_mode.label()
+                ));
+            }
+            TitleSetting::Bests => {
+                let bests = lifetime_stats.bests(*game_mode);
+                *text = Text::new(format!(
+                    "Best: {} points, {} lines, level {} (X to reset)",
+                    bests.best_score,
+                    bests.best_lines,
+                    bests.best_level.max(1)
+                ));
+            }
+        }
+    }
 }
 
 /// A system to despawn the title screen entities.
@@ -107,6 +241,7 @@ pub fn setup_pause_menu(mut commands: Commands) {
             Node {
                 width: Val::Percent(100.0),
                 height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
                 align_items: AlignItems::Center,
                 justify_content: JustifyContent::Center,
                 ..default()
@@ -124,9 +259,64 @@ pub fn setup_pause_menu(mut commands: Commands) {
                 },
                 TextColor(bevy::prelude::Color::WHITE),
             ));
+
+            parent.spawn((
+                Text::new("P to resume | S to restart | R to quit to title | +/- volume"),
+                TextFont {
+                    font_size: 20.0,
+                    ..default()
+                },
+                TextColor(bevy::prelude::Color::WHITE),
+            ));
         });
 }
 
+/// A system to show the countdown over the board. The board stays in view behind it.
+pub fn setup_countdown(mut commands: Commands, countdown: Res<StartCountdown>) {
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            CountdownText,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new(countdown.label()),
+                TextFont {
+                    font_size: 120.0,
+                    ..default()
+                },
+                TextColor(bevy::prelude::Color::WHITE),
+                CountdownText,
+            ));
+        });
+}
+
+/// A system that keeps the countdown showing the current beat.
+pub fn update_countdown(
+    countdown: Res<StartCountdown>,
+    mut text_query: Query<&mut Text, With<CountdownText>>,
+) {
+    for mut text in text_query.iter_mut() {
+        if text.0 != countdown.label() {
+            text.0 = countdown.label().to_string();
+        }
+    }
+}
+
+/// A system to despawn the countdown.
+pub fn despawn_countdown(mut commands: Commands, query: Query<Entity, With<CountdownText>>) {
+    for entity in query.iter() {
+        commands.entity(entity).try_despawn();
+    }
+}
+
 /// A system to despawn the pause menu.
 pub fn despawn_pause_menu(mut commands: Commands, query: Query<Entity, With<PauseMenu>>) {
     for entity in query.iter() {
@@ -135,7 +325,54 @@ pub fn despawn_pause_menu(mut commands: Commands, query: Query<Entity, With<Paus
 }
 
 /// A system to set up the game over screen.
-pub fn setup_game_over_screen(mut commands: Commands) {
+#[allow(clippy::too_many_arguments)]
+pub fn setup_game_over_screen(
+    mut commands: Commands,
+    puzzle_progress: Res<PuzzleProgress>,
+    game_over_reason: Res<GameOverReason>,
+    game_mode: Res<GameMode>,
+    rewinds_left: Res<RewindsLeft>,
+    rewind_history: Res<RewindHistory>,
+    play_counters: Res<PlayCounters>,
+    play_time: Res<PlayTime>,
+    color_scheme: Res<ColorScheme>,
+    block_query: Query<(&GridPosition, Option<&Shape>), Without<Tetromino>>,
+    tetromino_query: Query<(&GridPosition, &Shape), With<Tetromino>>,
+) {
+    // A finished puzzle says how it went; any other game over explains what ended it
+    let (title, reason) = match puzzle_progress.outcome {
+        Some(PuzzleOutcome::Failed) => ("PUZZLE FAILED", ""),
+        _ => ("GAME OVER", game_over_reason.description()),
+    };
+    // The final rates are worked out afresh, as the live ones may be a moment out of date
+    let final_efficiency = format!(
+        "Pieces: {}  {}",
+        play_counters.pieces,
+        Efficiency::measure(&play_counters, &play_time).label()
+    );
+    // A casual game that can still be rewound offers it below the restart prompt
+    let rewind_prompt = if can_rewind(*game_mode, &rewinds_left, &rewind_history) {
+        format!("Press B to rewind ({} left)", rewinds_left.0)
+    } else {
+        String::new()
+    };
+
+    // The final board is captured now, so the thumbnail stays as it was whatever happens after
+    let final_board = Board::from_blocks(
+        block_query
+            .iter()
+            .map(|(position, shape)| (*position, Cell::from_shape(shape))),
+    );
+    let blocks: Vec<(&GridPosition, &Shape)> = tetromino_query.iter().collect();
+    let final_piece = match blocks[..] {
+        [(a, shape), (b, _), (c, _), (d, _)] => Some(ActivePiece {
+            shape: *shape,
+            blocks: [*a, *b, *c, *d],
+        }),
+        _ => None,
+    };
+    let thumbnail_blocks = mini_board_blocks(&final_board, final_piece.as_ref(), *color_scheme);
+
     // Spawn a transparent background that covers the whole screen
     commands
         .spawn((
@@ -150,9 +387,45 @@ pub fn setup_game_over_screen(mut commands: Commands) {
             GameOverOverlay,
         ))
         .with_children(|parent| {
+            // A small snapshot of the final board beside the stats. Only the visible rows are
+            // shown, row 0 at the bottom.
+            parent
+                .spawn((
+                    Node {
+                        position_type: PositionType::Absolute,
+                        top: Val::Px(200.0),
+                        left: Val::Percent(50.0),
+                        width: Val::Px(GRID_SIZE_X as f32 * THUMBNAIL_CELL_SIZE),
+                        height: Val::Px(GRID_SIZE_Y as f32 * THUMBNAIL_CELL_SIZE),
+                        margin: UiRect {
+                            left: Val::Px(220.0),
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    BackgroundColor(grid_cell_color(*color_scheme)),
+                ))
+                .with_children(|thumbnail| {
+                    for (position, color) in &thumbnail_blocks {
+                        thumbnail.spawn((
+                            Node {
+                                position_type: PositionType::Absolute,
+                                left: Val::Px(position.x as f32 * THUMBNAIL_CELL_SIZE),
+                                top: Val::Px(
+                                    (GRID_SIZE_Y - 1 - position.y) as f32 * THUMBNAIL_CELL_SIZE,
+                                ),
+                                width: Val::Px(THUMBNAIL_CELL_SIZE),
+                                height: Val::Px(THUMBNAIL_CELL_SIZE),
+                                ..default()
+                            },
+                            BackgroundColor(*color),
+                        ));
+                    }
+                });
+
             // "GAME OVER" text
             parent.spawn((
-                Text::new("GAME OVER"),
+                Text::new(title),
                 TextFont {
                     font_size: 60.0,
                     ..default()
@@ -171,6 +444,26 @@ pub fn setup_game_over_screen(mut commands: Commands) {
                 },
             ));
 
+            parent.spawn((
+                Text::new(reason),
+                TextFont {
+                    font_size: 20.0,
+                    ..default()
+                },
+                TextColor(bevy::prelude::Color::WHITE),
+                Node {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(270.0),
+                    left: Val::Percent(50.0),
+                    // offset by half the text width to truly center it
+                    margin: UiRect {
+                        left: Val::Px(-200.0), // Approximate half the width of the text
+                        ..default()
+                    },
+                    ..default()
+                },
+            ));
+
             parent.spawn((
                 Text::new("Press R to restart"),
                 TextFont {
@@ -180,7 +473,7 @@ pub fn setup_game_over_screen(mut commands: Commands) {
                 TextColor(bevy::prelude::Color::WHITE),
                 Node {
                     position_type: PositionType::Absolute,
-                    top: Val::Px(275.0),
+                    top: Val::Px(305.0),
                     left: Val::Percent(50.0),
                     // offset by half the text width to truly center it
                     margin: UiRect {
@@ -190,9 +483,113 @@ pub fn setup_game_over_screen(mut commands: Commands) {
                     ..default()
                 },
             ));
+
+            parent.spawn((
+                Text::new(final_efficiency),
+                TextFont {
+                    font_size: 20.0,
+                    ..default()
+                },
+                TextColor(bevy::prelude::Color::WHITE),
+                Node {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(340.0),
+                    left: Val::Percent(50.0),
+                    // offset by half the text width to truly center it
+                    margin: UiRect {
+                        left: Val::Px(-140.0), // Approximate half the width of the text
+                        ..default()
+                    },
+                    ..default()
+                },
+            ));
+
+            parent.spawn((
+                Text::new(rewind_prompt),
+                TextFont {
+                    font_size: 20.0,
+                    ..default()
+                },
+                TextColor(bevy::prelude::Color::WHITE),
+                Node {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(375.0),
+                    left: Val::Percent(50.0),
+                    // offset by half the text width to truly center it
+                    margin: UiRect {
+                        left: Val::Px(-130.0), // Approximate half the width of the text
+                        ..default()
+                    },
+                    ..default()
+                },
+            ));
         });
 }
 
+/// A system to set up the victory screen shown when a mode is completed, with the result.
+pub fn setup_victory_screen(
+    mut commands: Commands,
+    puzzles: Res<Puzzles>,
+    score: Res<Score>,
+    play_time: Res<PlayTime>,
+) {
+    // Only puzzles can be completed so far, so the result is the solved puzzle
+    let result = format!(
+        "{} solved in {:.1}s with {} points",
+        puzzles.current().name,
+        play_time.0,
+        score.0
+    );
+
+    commands
+        .spawn((
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                row_gap: Val::Px(20.0),
+                ..default()
+            },
+            BackgroundColor(bevy::prelude::Color::srgba(0.0, 0.0, 0.0, 0.5)),
+            VictoryOverlay,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new("PUZZLE SOLVED"),
+                TextFont {
+                    font_size: 60.0,
+                    ..default()
+                },
+                TextColor(bevy::prelude::Color::WHITE),
+            ));
+            parent.spawn((
+                Text::new(result),
+                TextFont {
+                    font_size: 20.0,
+                    ..default()
+                },
+                TextColor(bevy::prelude::Color::WHITE),
+            ));
+            parent.spawn((
+                Text::new("Press ENTER for the next puzzle, R for the title"),
+                TextFont {
+                    font_size: 20.0,
+                    ..default()
+                },
+                TextColor(bevy::prelude::Color::WHITE),
+            ));
+        });
+}
+
+/// A system to despawn the victory screen. Any confetti still in the air despawns by itself.
+pub fn despawn_victory_screen(mut commands: Commands, query: Query<Entity, With<VictoryOverlay>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
 /// A system to despawn the game over screen.
 pub fn despawn_game_over_screen(
     mut commands: Commands,
@@ -204,87 +601,311 @@ pub fn despawn_game_over_screen(
 }
 
 /// A system to set up the static "NEXT" label and background box for the preview.
-pub fn setup_next_piece_preview(mut commands: Commands) {
-    // World coordinates for the top-right area, outside the grid
-    let preview_center_x = (GRID_SIZE_X as f32 / 2.0 + 3.5) * BLOCK_SIZE;
-    let preview_center_y = (GRID_SIZE_Y as f32 / 2.0 - 5.0) * BLOCK_SIZE;
-    let preview_width = 6.0 * BLOCK_SIZE;
-    let preview_height = 5.0 * BLOCK_SIZE;
-
+pub fn setup_next_piece_preview(mut commands: Commands, layout: Res<PreviewLayout>) {
     // 1. Static Preview Box (Background)
     commands.spawn((
         Sprite {
-            color: bevy::prelude::Color::srgba(0.1, 0.1, 0.1, 0.9), // Dark background box
-            custom_size: Some(Vec2::new(preview_width, preview_height)),
+            color: PREVIEW_BOX_COLOR, // Dark background box
+            custom_size: Some(layout.size),
             ..default()
         },
-        Transform::from_xyz(preview_center_x, preview_center_y, 0.5),
-        PreviewBlock,
+        Transform::from_xyz(layout.center.x, layout.center.y, 0.5),
+        PreviewFrame::Box,
     ));
 
+    // 2. Label sitting just above the box, in world space so it follows the box
     commands.spawn((
-        Text::new("Next"),
+        Text2d::new("Next"),
         TextFont {
             font_size: SCOREBOARD_FONT_SIZE,
             ..default()
         },
         TextColor(bevy::prelude::Color::WHITE),
-        Node {
-            position_type: PositionType::Absolute,
-            top: SCOREBOARD_LINE_TEXT_PADDING,
-            left: SCOREBOARD_TEXT_PADDING,
-            ..default()
-        },
-        PreviewBlock,
+        Transform::from_xyz(
+            layout.center.x,
+            layout.center.y + layout.size.y / 2.0 + SCOREBOARD_FONT_SIZE / 2.0,
+            1.5,
+        ),
+        PreviewFrame::Label,
+    ));
+}
+
+/// A system that re-derives the preview layout from the board metrics and HUD side and moves
+/// the preview and hold boxes and their labels to match.
+pub fn update_preview_layout(
+    metrics: Res<BoardMetrics>,
+    hud_side: Res<HudSide>,
+    preview_scale: Res<PreviewScale>,
+    mut layout: ResMut<PreviewLayout>,
+    mut frame_query: Query<(&PreviewFrame, &mut Transform, Option<&mut Sprite>)>,
+) {
+    layout.set_if_neq(PreviewLayout::from_metrics(
+        &metrics,
+        *hud_side,
+        *preview_scale,
     ));
+
+    for (frame, mut transform, sprite) in frame_query.iter_mut() {
+        match frame {
+            PreviewFrame::Box => {
+                transform.translation.x = layout.center.x;
+                transform.translation.y = layout.center.y;
+                if let Some(mut sprite) = sprite {
+                    sprite.custom_size = Some(layout.size);
+                }
+            }
+            PreviewFrame::Label => {
+                transform.translation.x = layout.center.x;
+                transform.translation.y =
+                    layout.center.y + layout.size.y / 2.0 + SCOREBOARD_FONT_SIZE / 2.0;
+            }
+            PreviewFrame::HoldBox => {
+                transform.translation.x = layout.hold_center.x;
+                transform.translation.y = layout.hold_center.y;
+                if let Some(mut sprite) = sprite {
+                    sprite.custom_size = Some(layout.hold_size);
+                }
+            }
+            PreviewFrame::HoldLabel => {
+                transform.translation.x = layout.hold_center.x;
+                transform.translation.y =
+                    layout.hold_center.y + layout.hold_size.y / 2.0 + SCOREBOARD_FONT_SIZE / 2.0;
+            }
+        }
+    }
 }
 
-/// A system to draw the next piece blocks
+/// A system to draw the next piece blocks, with the queued pieces after it stacked below
 pub fn update_next_piece_preview(
     mut commands: Commands,
     next_piece: Res<NextPiece>,
+    next_queue: Res<NextQueue>,
+    layout: Res<PreviewLayout>,
+    color_scheme: Res<ColorScheme>,
+    block_atlas: Res<BlockAtlas>,
     block_query: Query<Entity, With<PreviewBlock>>,
 ) {
-    // World coordinates for centering the blocks in the preview box
-    let center_x = (GRID_SIZE_X as f32 / 2.0 + 3.5) * BLOCK_SIZE;
-    let center_y = (GRID_SIZE_Y as f32 / 2.0 - 5.0) * BLOCK_SIZE;
-
-    // Only update when the next piece resource has changed
-    if next_piece.is_changed() {
+    // Only update when the upcoming pieces or the preview layout have changed
+    if next_piece.is_changed() || next_queue.is_changed() || layout.is_changed() {
         // 1. Despawn old preview blocks
         for entity in block_query.iter() {
-            commands.entity(entity).despawn();
+            commands.entity(entity).try_despawn();
+        }
+
+        // 2. Spawn the new preview blocks, the next piece at full size and the queue smaller
+        let queued = next_queue.0.iter().enumerate().map(|(i, shape)| {
+            let center = layout.queue_top - Vec2::new(0.0, i as f32 * layout.queue_spacing);
+            (*shape, center, layout.queue_block_size)
+        });
+        for (shape, center, block_size) in
+            std::iter::once((next_piece.0, layout.next_center, layout.block_size)).chain(queued)
+        {
+            let color = get_tetromino_color(shape, *color_scheme);
+            for position in preview_block_positions(shape, center, block_size) {
+                commands.spawn((
+                    block_atlas.sprite(color, Vec2::splat(block_size)),
+                    // Z is higher than the box background
+                    Transform::from_translation(position.extend(1.5)),
+                    PreviewBlock,
+                ));
+            }
+        }
+    }
+}
+
+/// Returns where to draw each block of a shape so that the piece sits centered on the given
+/// point, by the middle of its bounding box.
+fn preview_block_positions(shape: Shape, center: Vec2, block_size: f32) -> Vec<Vec2> {
+    let blocks = get_tetromino_blocks(shape);
+    let (min, max) = blocks
+        .iter()
+        .fold((IVec2::MAX, IVec2::MIN), |(min, max), block| {
+            let block = IVec2::new(block.x, block.y);
+            (min.min(block), max.max(block))
+        });
+    let offset = (min + max).as_vec2() / 2.0;
+    blocks
+        .iter()
+        .map(|block| center + (Vec2::new(block.x as f32, block.y as f32) - offset) * block_size)
+        .collect()
+}
+
+/// A system to set up the hold box below the next piece preview. It starts empty.
+pub fn setup_hold_preview(mut commands: Commands, layout: Res<PreviewLayout>) {
+    commands.spawn((
+        Sprite {
+            color: PREVIEW_BOX_COLOR, // Same box as the preview
+            custom_size: Some(layout.hold_size),
+            ..default()
+        },
+        Transform::from_xyz(layout.hold_center.x, layout.hold_center.y, 0.5),
+        PreviewFrame::HoldBox,
+    ));
+
+    commands.spawn((
+        Text2d::new("Hold"),
+        TextFont {
+            font_size: SCOREBOARD_FONT_SIZE,
+            ..default()
+        },
+        TextColor(bevy::prelude::Color::WHITE),
+        Transform::from_xyz(
+            layout.hold_center.x,
+            layout.hold_center.y + layout.hold_size.y / 2.0 + SCOREBOARD_FONT_SIZE / 2.0,
+            1.5,
+        ),
+        PreviewFrame::HoldLabel,
+    ));
+}
+
+/// A system to draw the held piece in the hold box. The box and the piece in it are faded
+/// while the falling piece cannot be held, until the next piece spawns or a new game starts.
+pub fn update_hold_preview(
+    mut commands: Commands,
+    hold_piece: Res<HoldPiece>,
+    layout: Res<PreviewLayout>,
+    color_scheme: Res<ColorScheme>,
+    block_atlas: Res<BlockAtlas>,
+    block_query: Query<Entity, With<HoldBlock>>,
+    mut frame_query: Query<(&PreviewFrame, &mut Sprite)>,
+) {
+    if !hold_piece.is_changed() && !layout.is_changed() {
+        return;
+    }
+    for entity in block_query.iter() {
+        commands.entity(entity).try_despawn();
+    }
+
+    let alpha = if hold_piece.can_hold {
+        1.0
+    } else {
+        HOLD_UNAVAILABLE_ALPHA
+    };
+    for (frame, mut sprite) in frame_query.iter_mut() {
+        if matches!(frame, PreviewFrame::HoldBox) {
+            sprite.color = PREVIEW_BOX_COLOR.with_alpha(PREVIEW_BOX_COLOR.alpha() * alpha);
         }
+    }
+
+    let Some(shape) = hold_piece.shown() else {
+        return;
+    };
+    let color = get_tetromino_color(shape, *color_scheme).with_alpha(alpha);
+    for position in preview_block_positions(shape, layout.hold_center, layout.block_size) {
+        commands.spawn((
+            block_atlas.sprite(color, Vec2::splat(layout.block_size)),
+            // Above the box but below the ghost piece, so the ghost wins if the two ever meet
+            Transform::from_translation(position.extend(0.6)),
+            HoldBlock,
+        ));
+    }
+}
 
-        // Get the shape and color of the next piece
-        let shape_to_preview = next_piece.0;
-        let blocks = get_tetromino_blocks(shape_to_preview);
-        let color = get_tetromino_color(shape_to_preview);
-
-        // 3. Spawn the new preview blocks
-        for block_position in blocks.iter() {
-            commands.spawn((
-                Sprite {
-                    color,
-                    custom_size: Some(Vec2::new(BLOCK_SIZE, BLOCK_SIZE)),
+/// A system that animates a hold: the blocks of the piece put away fly into the hold box as they
+/// shrink away, and the blocks of the piece taken out grow to full size where they spawned.
+/// With the animation off the swap shows at once.
+pub fn animate_hold_swap(
+    mut commands: Commands,
+    time: Res<Time>,
+    hold_animation: Res<HoldAnimation>,
+    layout: Res<PreviewLayout>,
+    mut query: Query<(Entity, &mut HoldSwap, &mut Transform), Without<ClearingRow>>,
+) {
+    for (entity, mut swap, mut transform) in query.iter_mut() {
+        swap.timer.tick(time.delta());
+        if swap.timer.finished() || !hold_animation.0 {
+            if swap.into_hold {
+                commands.entity(entity).try_despawn();
+            } else {
+                transform.scale = Vec3::ONE;
+                commands.entity(entity).remove::<HoldSwap>();
+            }
+            continue;
+        }
+        let progress = swap.timer.fraction();
+        if swap.into_hold {
+            let start = *swap.start.get_or_insert(transform.translation);
+            transform.translation = start.lerp(layout.hold_center.extend(start.z), progress);
+            transform.scale = Vec3::splat(1.0 - progress);
+        } else {
+            transform.scale = Vec3::splat(progress);
+        }
+    }
+}
+
+/// Returns the text of the controls legend: the keys bound to each action, then the key that
+/// toggles the legend itself.
+fn controls_legend(key_bindings: &KeyBindings) -> String {
+    let mut lines = key_bindings.legend();
+    lines.push("F1: Toggle controls".to_string());
+    lines.join("\n")
+}
+
+/// A system to set up the controls legend. It starts hidden and is toggled with F1,
+/// sitting in the bottom-left corner so it never covers the playfield.
+pub fn setup_controls_legend(mut commands: Commands, key_bindings: Res<KeyBindings>) {
+    let legend = controls_legend(&key_bindings);
+
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                bottom: SCOREBOARD_TEXT_PADDING,
+                left: SCOREBOARD_TEXT_PADDING,
+                padding: UiRect::all(Val::Px(10.0)),
+                ..default()
+            },
+            BackgroundColor(bevy::prelude::Color::srgba(0.0, 0.0, 0.0, 0.6)),
+            Visibility::Hidden,
+            ControlsLegend,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                Text::new(legend),
+                TextFont {
+                    font_size: 16.0,
                     ..default()
                 },
-                Transform::from_xyz(
-                    center_x + block_position.x as f32 * BLOCK_SIZE,
-                    center_y + block_position.y as f32 * BLOCK_SIZE,
-                    1.5, // Z is higher than the box background
-                ),
-                PreviewBlock,
+                TextColor(bevy::prelude::Color::WHITE),
+                ControlsLegendText,
             ));
-        }
+        });
+}
+
+/// A system that rebuilds the controls legend from the key bindings when they change.
+pub fn update_controls_legend(
+    key_bindings: Res<KeyBindings>,
+    mut query: Query<&mut Text, With<ControlsLegendText>>,
+) {
+    for mut text in query.iter_mut() {
+        text.0 = controls_legend(&key_bindings);
+    }
+}
+
+/// A system that shows or hides the controls legend when F1 is pressed.
+pub fn toggle_controls_legend(
+    input: Res<ButtonInput<KeyCode>>,
+    mut query: Query<&mut Visibility, With<ControlsLegend>>,
+) {
+    if !input.just_pressed(KeyCode::F1) {
+        return;
+    }
+    for mut visibility in query.iter_mut() {
+        *visibility = match *visibility {
+            Visibility::Hidden => Visibility::Inherited,
+            _ => Visibility::Hidden,
+        };
     }
 }
 
 /// A system to set up the scoreboard UI.
-pub fn setup_scoreboard(mut commands: Commands) {
+pub fn setup_scoreboard(mut commands: Commands, classic_score: Res<ClassicScore>) {
     // Spawn the score, lines, and level text in a single container for clean UI
     commands.spawn((
-        Text::new("Score: 0"),
+        Text::new(format!(
+            "Score: {}",
+            classic_score.format(0, CLASSIC_SCORE_DIGITS)
+        )),
         TextFont {
             font_size: SCOREBOARD_FONT_SIZE,
             ..default()
@@ -301,7 +922,10 @@ pub fn setup_scoreboard(mut commands: Commands) {
 
     // Spawn the scoreboard text for the lines cleared.
     commands.spawn((
-        Text::new("Lines: 0"),
+        Text::new(format!(
+            "Lines: {}",
+            classic_score.format(0, CLASSIC_LINES_DIGITS)
+        )),
         TextFont {
             font_size: SCOREBOARD_FONT_SIZE,
             ..default()
@@ -318,7 +942,10 @@ pub fn setup_scoreboard(mut commands: Commands) {
 
     // Spawn the scoreboard text for the level.
     commands.spawn((
-        Text::new("Level: 1"),
+        Text::new(format!(
+            "Level: {}",
+            classic_score.format(1, CLASSIC_LEVEL_DIGITS)
+        )),
         TextFont {
             font_size: SCOREBOARD_FONT_SIZE,
             ..default()
@@ -333,53 +960,595 @@ pub fn setup_scoreboard(mut commands: Commands) {
         Scoreboard::Level,
     ));
 
-    println!("UI set up successfully!");
+    // Spawn the scoreboard text for the score multiplier, which stays empty at 1.0.
+    commands.spawn((
+        Text::new(""),
+        TextFont {
+            font_size: SCOREBOARD_FONT_SIZE,
+            ..default()
+        },
+        TextColor(bevy::prelude::Color::WHITE),
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(50.0 + 3.0 * SCOREBOARD_FONT_SIZE),
+            left: SCOREBOARD_TEXT_PADDING,
+            ..default()
+        },
+        Scoreboard::Multiplier,
+    ));
+
+    // Spawn the scoreboard text for the APM and PPS, which stays empty unless enabled.
+    commands.spawn((
+        Text::new(""),
+        TextFont {
+            font_size: SCOREBOARD_FONT_SIZE,
+            ..default()
+        },
+        TextColor(bevy::prelude::Color::WHITE),
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(50.0 + 4.0 * SCOREBOARD_FONT_SIZE),
+            left: SCOREBOARD_TEXT_PADDING,
+            ..default()
+        },
+        Scoreboard::Efficiency,
+    ));
+
+    // Spawn the scoreboard text for the pieces placed, shown along with the APM and PPS.
+    commands.spawn((
+        Text::new(""),
+        TextFont {
+            font_size: SCOREBOARD_FONT_SIZE,
+            ..default()
+        },
+        TextColor(bevy::prelude::Color::WHITE),
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(50.0 + 5.0 * SCOREBOARD_FONT_SIZE),
+            left: SCOREBOARD_TEXT_PADDING,
+            ..default()
+        },
+        Scoreboard::Pieces,
+    ));
+
+    // Spawn the scoreboard text for the combo, which stays empty until one is running.
+    commands.spawn((
+        Text::new(""),
+        TextFont {
+            font_size: SCOREBOARD_FONT_SIZE,
+            ..default()
+        },
+        TextColor(bevy::prelude::Color::WHITE),
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(50.0 + 6.0 * SCOREBOARD_FONT_SIZE),
+            left: SCOREBOARD_TEXT_PADDING,
+            ..default()
+        },
+        Scoreboard::Combo,
+    ));
+
+    // Spawn the back-to-back indicator, which stays empty until a difficult clear is made.
+    commands.spawn((
+        Text::new(""),
+        TextFont {
+            font_size: SCOREBOARD_FONT_SIZE,
+            ..default()
+        },
+        TextColor(bevy::prelude::Color::WHITE),
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(50.0 + 7.0 * SCOREBOARD_FONT_SIZE),
+            left: SCOREBOARD_TEXT_PADDING,
+            ..default()
+        },
+        Scoreboard::BackToBack,
+    ));
+
+    info!("UI set up successfully!");
 }
 
 /// A system that updates the scoreboard UI.
+#[allow(clippy::too_many_arguments)]
 pub fn update_scoreboard(
     score: Res<Score>,
     lines_cleared: Res<LinesCleared>,
     level: Res<Level>,
+    score_multiplier: Res<ScoreMultiplier>,
+    efficiency: Res<Efficiency>,
+    show_efficiency: Res<ShowEfficiency>,
+    play_counters: Res<PlayCounters>,
+    classic_score: Res<ClassicScore>,
+    combo: Res<Combo>,
+    back_to_back: Res<BackToBack>,
     mut query: Query<(&mut Text, &Scoreboard)>,
 ) {
     for (mut text, scoreboard) in query.iter_mut() {
         match scoreboard {
             Scoreboard::Score => {
-                *text = Text::new(format!("Score: {}", score.0));
+                *text = Text::new(format!(
+                    "Score: {}",
+                    classic_score.format(score.0, CLASSIC_SCORE_DIGITS)
+                ));
             }
             Scoreboard::Lines => {
-                *text = Text::new(format!("Lines: {}", lines_cleared.0));
+                *text = Text::new(format!(
+                    "Lines: {}",
+                    classic_score.format(lines_cleared.0, CLASSIC_LINES_DIGITS)
+                ));
             }
             Scoreboard::Level => {
-                *text = Text::new(format!("Level: {}", level.0));
+                *text = Text::new(format!(
+                    "Level: {}",
+                    classic_score.format(level.0, CLASSIC_LEVEL_DIGITS)
+                ));
+            }
+            Scoreboard::Multiplier if score_multiplier.0 != 1.0 => {
+                *text = Text::new(format!("Multiplier: x{}", score_multiplier.0));
+            }
+            Scoreboard::Multiplier => {}
+            Scoreboard::Efficiency if show_efficiency.0 => {
+                *text = Text::new(efficiency.label());
             }
+            Scoreboard::Efficiency => {}
+            Scoreboard::Pieces if show_efficiency.0 => {
+                *text = Text::new(format!("Pieces: {}", play_counters.pieces));
+            }
+            Scoreboard::Pieces => {}
+            Scoreboard::Combo if combo.0 > 0 => {
+                *text = Text::new(format!("Combo: {}", combo.0));
+            }
+            Scoreboard::Combo => *text = Text::new(""),
+            Scoreboard::BackToBack if back_to_back.0 => *text = Text::new("B2B"),
+            Scoreboard::BackToBack => *text = Text::new(""),
         }
     }
 }
 
-/// System to despawn game board entities when transitioning back to title
-pub fn despawn_game_board(
+/// A system that flashes a "NEW BEST" banner the first time in a run that the score, lines or
+/// level pass the best of the games finished before in the same mode. Nothing is announced in
+/// the first game of a mode, as there is no best to beat yet.
+#[allow(clippy::too_many_arguments)]
+pub fn check_personal_bests(
     mut commands: Commands,
-    query1: Query<Entity, With<crate::components::GridPosition>>,
-    query2: Query<Entity, With<Scoreboard>>,
-    query3: Query<Entity, With<crate::components::Tetromino>>,
-    query4: Query<Entity, With<Sprite>>,
-    query5: Query<Entity, With<PreviewBlock>>,
+    score: Res<Score>,
+    lines_cleared: Res<LinesCleared>,
+    level: Res<Level>,
+    game_mode: Res<GameMode>,
+    lifetime_stats: Res<LifetimeStats>,
+    mut new_bests: ResMut<NewBests>,
+    banner_query: Query<Entity, With<BestBanner>>,
 ) {
-    for entity in query1.iter() {
-        commands.entity(entity).despawn();
+    let lifetime_stats = lifetime_stats.bests(*game_mode);
+    if lifetime_stats.games_played == 0 {
+        return;
     }
-    for entity in query2.iter() {
-        commands.entity(entity).despawn();
+
+    let new_bests = &mut *new_bests;
+    let mut beaten = Vec::new();
+    for (value, best, announced, name) in [
+        (
+            score.0,
+            lifetime_stats.best_score,
+            &mut new_bests.score,
+            "SCORE",
+        ),
+        (
+            lines_cleared.0,
+            lifetime_stats.best_lines,
+            &mut new_bests.lines,
+            "LINES",
+        ),
+        (
+            level.0,
+            lifetime_stats.best_level,
+            &mut new_bests.level,
+            "LEVEL",
+        ),
+    ] {
+        if value > best && !*announced {
+            *announced = true;
+            beaten.push(name);
+        }
     }
-    for entity in query3.iter() {
-        commands.entity(entity).despawn();
+    if beaten.is_empty() {
+        return;
     }
-    for entity in query4.iter() {
-        commands.entity(entity).despawn();
+
+    // A newer banner replaces one still showing
+    for entity in banner_query.iter() {
+        commands.entity(entity).try_despawn();
     }
-    for entity in query5.iter() {
-        commands.entity(entity).despawn();
+    commands.spawn((
+        Text::new(format!("NEW BEST {}!", beaten.join(" & "))),
+        TextFont {
+            font_size: 40.0,
+            ..default()
+        },
+        TextColor(bevy::prelude::Color::srgb(1.0, 0.85, 0.2)),
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Percent(20.0),
+            width: Val::Percent(100.0),
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        TextLayout::new_with_justify(JustifyText::Center),
+        BestBanner(Timer::from_seconds(BEST_BANNER_SECONDS, TimerMode::Once)),
+    ));
+}
+
+/// A system that fades the "NEW BEST" banner out over its lifetime and then removes it.
+pub fn update_best_banner(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut BestBanner, &mut TextColor)>,
+) {
+    for (entity, mut banner, mut text_color) in query.iter_mut() {
+        banner.0.tick(time.delta());
+        if banner.0.finished() {
+            commands.entity(entity).try_despawn();
+        } else {
+            text_color.0.set_alpha(banner.0.fraction_remaining());
+        }
+    }
+}
+
+/// A system that pulses the whole grid brighter when lines are cleared, stronger for more rows,
+/// and fades it smoothly back to normal. The pulse is a single sprite over the background,
+/// under the blocks; a new clear restarts it at the new strength.
+pub fn pulse_grid_on_clear(
+    mut commands: Commands,
+    time: Res<Time>,
+    lines_cleared: Res<LinesCleared>,
+    metrics: Res<BoardMetrics>,
+    mut pulse_query: Query<(Entity, &mut GridPulse, &mut Sprite, &mut Transform)>,
+    mut last_lines_cleared: Local<u32>,
+) {
+    // Line counts only ever grow during a game; a reset or rewind puts them back
+    if lines_cleared.0 > *last_lines_cleared {
+        let rows = lines_cleared.0 - *last_lines_cleared;
+        let pulse = GridPulse {
+            timer: Timer::from_seconds(GRID_PULSE_SECONDS, TimerMode::Once),
+            strength: rows as f32 * GRID_PULSE_STRENGTH_PER_ROW,
+        };
+        match pulse_query.iter_mut().next() {
+            Some((_, mut current, _, _)) => *current = pulse,
+            None => {
+                // Sized and shown from the next frame on
+                commands.spawn((
+                    Sprite::from_color(Color::NONE, Vec2::ZERO),
+                    Transform::default(),
+                    pulse,
+                ));
+            }
+        }
+    }
+    *last_lines_cleared = lines_cleared.0;
+
+    let rect = metrics.rows_rect(0, GRID_SIZE_Y - 1);
+    for (entity, mut pulse, mut sprite, mut transform) in pulse_query.iter_mut() {
+        pulse.timer.tick(time.delta());
+        if pulse.timer.finished() {
+            commands.entity(entity).try_despawn();
+            continue;
+        }
+        // Eases out, so the flash drops quickly and settles gently into the base color
+        let remaining = pulse.timer.fraction_remaining();
+        sprite.color = Color::srgba(1.0, 1.0, 1.0, pulse.strength * remaining * remaining);
+        sprite.custom_size = Some(rect.size());
+        // Above the background and the landing highlight, below every block
+        transform.translation = rect.center().extend(0.5);
     }
-}
\ No newline at end of file
+}
+
+/// Filter matching every block drawn on or beside the playfield.
+type BoardBlockFilter = Or<(
+    With<GridPosition>,
+    With<Tetromino>,
+    With<ClearingRow>,
+    With<GhostBlock>,
+    With<PreviewBlock>,
+    With<HoldBlock>,
+    With<SpectatorBlock>,
+    With<HoldSwap>,
+)>;
+
+/// Filter matching every entity that belongs to the game board. Each kind of entity is matched
+/// by its own marker, so sprites that are not part of the board, such as the backdrop, are
+/// left alone.
+type GameBoardFilter = Or<(
+    BoardBlockFilter,
+    With<GridBackground>,
+    With<LandingRow>,
+    With<GridPulse>,
+    With<Confetti>,
+    With<Scoreboard>,
+    With<PreviewFrame>,
+    With<ControlsLegend>,
+    With<BestBanner>,
+)>;
+
+/// System to despawn game board entities when transitioning back to title.
+/// This is the only system that tears the board down, and it matches every entity
+/// through a single query so nothing is despawned twice.
+pub fn despawn_game_board(mut commands: Commands, query: Query<Entity, GameBoardFilter>) {
+    for entity in query.iter() {
+        commands.entity(entity).try_despawn();
+    }
+}
+
+/// A startup system that spawns the overlay used for screen transitions. It stays for the whole
+/// session above every other UI and is fully transparent while no transition plays.
+pub fn setup_transition_overlay(mut commands: Commands) {
+    commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            ..default()
+        },
+        BackgroundColor(bevy::prelude::Color::NONE),
+        GlobalZIndex(100),
+        TransitionOverlay,
+    ));
+}
+
+/// A system that starts a fade whenever the game changes screens. Moving between `Spawning`
+/// and `Playing` happens for every piece and is not a screen change, so it never fades.
+pub fn start_screen_transition(
+    mut transitions: EventReader<StateTransitionEvent<GameState>>,
+    mut screen_transition: ResMut<ScreenTransition>,
+) {
+    for transition in transitions.read() {
+        let is_screen_change = match (transition.exited, transition.entered) {
+            (Some(exited), Some(entered)) if exited == entered => false,
+            (Some(GameState::Spawning), Some(GameState::Playing))
+            | (Some(GameState::Playing), Some(GameState::Spawning))
+            | (Some(GameState::Countdown), Some(GameState::Spawning)) => false,
+            _ => true,
+        };
+        if is_screen_change {
+            screen_transition.start();
+        }
+    }
+}
+
+/// Run condition that is true while no fade is playing.
+pub fn screen_transition_finished(screen_transition: Res<ScreenTransition>) -> bool {
+    !screen_transition.is_running()
+}
+
+/// A system that ends the current fade early when any key is pressed.
+pub fn skip_screen_transition(
+    input: Res<ButtonInput<KeyCode>>,
+    mut screen_transition: ResMut<ScreenTransition>,
+) {
+    if screen_transition.is_running() && input.get_just_pressed().next().is_some() {
+        screen_transition.skip();
+    }
+}
+
+/// A system that fades the transition overlay from black to transparent.
+pub fn animate_screen_transition(
+    time: Res<Time>,
+    mut screen_transition: ResMut<ScreenTransition>,
+    mut overlay_query: Query<&mut BackgroundColor, With<TransitionOverlay>>,
+) {
+    screen_transition.timer.tick(time.delta());
+    let alpha = 1.0 - screen_transition.timer.fraction();
+    for mut background in overlay_query.iter_mut() {
+        background.0 = bevy::prelude::Color::srgba(0.0, 0.0, 0.0, alpha);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+    use rand::Rng;
+    use std::time::Duration;
+
+    use crate::{
+        board::{CollisionGrid, sync_collision_grid},
+        constants::{LINE_CLEAR_SECONDS, NEXT_QUEUE_SIZE},
+        game_logic::{
+            animate_clearing_rows, clear_lines, tests::insert_scoring_resources, update_ghost_piece,
+        },
+        randomizer::GameRng,
+        tetromino::spawn_blocks,
+    };
+
+    /// Returns the corners and depth of every sprite with the marker component.
+    fn sprite_rects<T: Component>(world: &mut World) -> Vec<(Rect, f32)> {
+        world
+            .query_filtered::<(&Transform, &Sprite), With<T>>()
+            .iter(world)
+            .map(|(transform, sprite)| {
+                let size = sprite.custom_size.unwrap();
+                (
+                    Rect::from_center_size(transform.translation.truncate(), size),
+                    transform.translation.z,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn ghost_piece_and_hold_preview_never_share_a_cell() {
+        for hud_side in [HudSide::Left, HudSide::Right] {
+            let mut world = World::new();
+            world.init_resource::<BoardMetrics>();
+            world.init_resource::<ColorScheme>();
+            world.init_resource::<BlockAtlas>();
+            world.init_resource::<CollisionGrid>();
+            world.insert_resource(PreviewLayout::from_metrics(
+                &BoardMetrics::default(),
+                hud_side,
+                PreviewScale::default(),
+            ));
+            world.insert_resource(HoldPiece {
+                shape: Some(Shape::I),
+                pending: None,
+                can_hold: true,
+            });
+            for position in spawn_blocks(Shape::I) {
+                world.spawn((position, Shape::I, Tetromino));
+            }
+            world.run_system_once(update_ghost_piece).unwrap();
+            world.run_system_once(update_hold_preview).unwrap();
+
+            let ghost = sprite_rects::<GhostBlock>(&mut world);
+            let hold = sprite_rects::<HoldBlock>(&mut world);
+            assert_eq!((ghost.len(), hold.len()), (4, 4));
+            for (ghost_rect, ghost_z) in &ghost {
+                for (hold_rect, hold_z) in &hold {
+                    assert!(ghost_rect.intersect(*hold_rect).is_empty());
+                    // Should they ever meet, the ghost is drawn on top
+                    assert!(ghost_z > hold_z);
+                }
+            }
+        }
+    }
+
+    /// Returns the bounding box of the given sprite rectangles.
+    fn bounds(rects: &[Rect]) -> Rect {
+        rects
+            .iter()
+            .fold(rects[0], |bounds, rect| bounds.union(*rect))
+    }
+
+    #[test]
+    fn scaled_previews_stay_centered_in_their_boxes() {
+        for scale in [0.6, 1.0] {
+            let mut world = World::new();
+            world.init_resource::<ColorScheme>();
+            world.init_resource::<BlockAtlas>();
+            let layout = PreviewLayout::from_metrics(
+                &BoardMetrics::default(),
+                HudSide::default(),
+                PreviewScale(scale),
+            );
+            world.insert_resource(layout);
+            // The widest piece everywhere, with the queue at its longest
+            world.insert_resource(NextPiece(Shape::I));
+            world.insert_resource(NextQueue(vec![Shape::I; NEXT_QUEUE_SIZE - 1].into()));
+            world.insert_resource(HoldPiece {
+                shape: Some(Shape::I),
+                ..default()
+            });
+            world.run_system_once(update_next_piece_preview).unwrap();
+            world.run_system_once(update_hold_preview).unwrap();
+
+            let preview_box = Rect::from_center_size(layout.center, layout.size);
+            let preview = sprite_rects::<PreviewBlock>(&mut world);
+            assert_eq!(preview.len(), 4 * NEXT_QUEUE_SIZE);
+            assert!(
+                preview
+                    .iter()
+                    .all(|(rect, _)| preview_box.contains(rect.min)
+                        && preview_box.contains(rect.max))
+            );
+
+            // The next piece at the preview scale, centered in its slot
+            let next = preview
+                .iter()
+                .map(|(rect, _)| *rect)
+                .filter(|rect| (rect.width() - layout.block_size).abs() < 1e-3)
+                .collect::<Vec<_>>();
+            assert_eq!(next.len(), 4);
+            assert!(bounds(&next).center().abs_diff_eq(layout.next_center, 1e-3));
+
+            // Each queued piece smaller still, centered in its own slot below
+            for i in 0..NEXT_QUEUE_SIZE - 1 {
+                let slot = layout.queue_top - Vec2::new(0.0, i as f32 * layout.queue_spacing);
+                let queued = preview
+                    .iter()
+                    .map(|(rect, _)| *rect)
+                    .filter(|rect| {
+                        (rect.width() - layout.queue_block_size).abs() < 1e-3
+                            && (rect.center().y - slot.y).abs() < layout.queue_spacing / 2.0
+                    })
+                    .collect::<Vec<_>>();
+                assert_eq!(queued.len(), 4);
+                assert!(bounds(&queued).center().abs_diff_eq(slot, 1e-3));
+            }
+
+            // The held piece at the preview scale, centered in the hold box
+            let hold_box = Rect::from_center_size(layout.hold_center, layout.hold_size);
+            let hold = sprite_rects::<HoldBlock>(&mut world)
+                .into_iter()
+                .map(|(rect, _)| rect)
+                .collect::<Vec<_>>();
+            assert_eq!(hold.len(), 4);
+            assert!(
+                hold.iter()
+                    .all(|rect| (rect.width() - layout.block_size).abs() < 1e-3
+                        && hold_box.contains(rect.min)
+                        && hold_box.contains(rect.max))
+            );
+            assert!(bounds(&hold).center().abs_diff_eq(layout.hold_center, 1e-3));
+        }
+    }
+
+    #[test]
+    fn resetting_at_random_leaves_nothing_behind() {
+        let mut world = World::new();
+        insert_scoring_resources(&mut world);
+        world.init_resource::<CollisionGrid>();
+
+        let mut play = Schedule::default();
+        play.add_systems((
+            animate_clearing_rows,
+            (clear_lines, sync_collision_grid).chain(),
+        ));
+        // The board is torn down on the frame the cleared rows finish fading, so both systems
+        // despawn those blocks at once
+        let mut reset = Schedule::default();
+        reset.add_systems((animate_clearing_rows, despawn_game_board));
+
+        let mut rng = GameRng::new(GameSeed(671)).0;
+        let mut time = Time::<()>::default();
+        for _ in 0..2000 {
+            time.advance_by(Duration::from_secs_f32(LINE_CLEAR_SECONDS));
+            world.insert_resource(time);
+            if rng.random_ratio(1, 8) {
+                reset.run(&mut world);
+                let mut board_query = world.query_filtered::<(), GameBoardFilter>();
+                assert_eq!(board_query.iter(&world).count(), 0);
+                continue;
+            }
+
+            // Drop a few blocks onto random columns, clearing any rows they fill
+            for _ in 0..4 {
+                let x = rng.random_range(0..GRID_SIZE_X);
+                let y = world
+                    .query::<&GridPosition>()
+                    .iter(&world)
+                    .filter(|position| position.x == x)
+                    .map(|position| position.y + 1)
+                    .max()
+                    .unwrap_or(0);
+                if y < GRID_SIZE_Y {
+                    world.spawn((
+                        GridPosition { x, y },
+                        Sprite::default(),
+                        Transform::default(),
+                    ));
+                }
+            }
+            play.run(&mut world);
+
+            let blocks: Vec<(Entity, GridPosition)> = world
+                .query_filtered::<(Entity, &GridPosition), Without<Tetromino>>()
+                .iter(&world)
+                .map(|(entity, position)| (entity, *position))
+                .collect();
+            assert_eq!(
+                *world.resource::<CollisionGrid>(),
+                CollisionGrid::from_blocks(blocks)
+            );
+        }
+        assert!(world.resource::<LinesCleared>().0 > 0);
+    }
+}